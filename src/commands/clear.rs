@@ -0,0 +1,30 @@
+use crate::{core::Model, session::Session};
+
+use super::{Command, CommandFuture, CommandOutcome};
+
+pub struct ClearCommand;
+
+impl<M: Model> Command<M> for ClearCommand {
+    fn name(&self) -> &'static str {
+        "clear"
+    }
+
+    fn description(&self) -> &'static str {
+        "Start a new conversation; the current one stays saved"
+    }
+
+    fn execute<'s>(&'s self, session: &'s mut Session<'_, M>, _args: &'s str) -> CommandFuture<'s> {
+        Box::pin(async move {
+            let previous_id = session.session_id().to_string();
+            let had_history = !session.messages().is_empty();
+            session.clear();
+            let text = if had_history {
+                format!("Started a new conversation. Resume the previous one with `deputy --resume {}`.", previous_id)
+            } else {
+                "Started a new conversation.".to_string()
+            };
+            session.io().show_message("Conversation cleared", &text);
+            Ok(CommandOutcome::Continue)
+        })
+    }
+}
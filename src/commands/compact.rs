@@ -0,0 +1,26 @@
+use crate::{core::Model, session::Session};
+
+use super::{Command, CommandFuture, CommandOutcome};
+
+pub struct CompactCommand;
+
+impl<M: Model> Command<M> for CompactCommand {
+    fn name(&self) -> &'static str {
+        "compact"
+    }
+
+    fn description(&self) -> &'static str {
+        "Summarise older turns to free up context"
+    }
+
+    fn execute<'s>(&'s self, session: &'s mut Session<'_, M>, _args: &'s str) -> CommandFuture<'s> {
+        Box::pin(async move {
+            if session.compact().await? {
+                session.save()?;
+            } else {
+                session.io().show_message("Nothing to compact", "There is not enough history to compact yet.");
+            }
+            Ok(CommandOutcome::Continue)
+        })
+    }
+}
@@ -0,0 +1,22 @@
+use crate::{core::Model, session::Session};
+
+use super::{Command, CommandFuture, CommandOutcome};
+
+pub struct CostCommand;
+
+impl<M: Model> Command<M> for CostCommand {
+    fn name(&self) -> &'static str {
+        "cost"
+    }
+
+    fn description(&self) -> &'static str {
+        "Show token usage and estimated cost"
+    }
+
+    fn execute<'s>(&'s self, session: &'s mut Session<'_, M>, _args: &'s str) -> CommandFuture<'s> {
+        Box::pin(async move {
+            session.io().show_message("Token usage", &session.usage_report());
+            Ok(CommandOutcome::Continue)
+        })
+    }
+}
@@ -0,0 +1,19 @@
+use crate::{core::Model, session::Session};
+
+use super::{Command, CommandFuture, CommandOutcome};
+
+pub struct ExitCommand;
+
+impl<M: Model> Command<M> for ExitCommand {
+    fn name(&self) -> &'static str {
+        "exit"
+    }
+
+    fn description(&self) -> &'static str {
+        "Exit deputy"
+    }
+
+    fn execute<'s>(&'s self, _session: &'s mut Session<'_, M>, _args: &'s str) -> CommandFuture<'s> {
+        Box::pin(async move { Ok(CommandOutcome::Exit) })
+    }
+}
@@ -0,0 +1,40 @@
+use crate::{core::Model, session::Session};
+
+use super::{Command, CommandFuture, CommandOutcome};
+
+pub struct HelpCommand {
+    text: String,
+}
+
+impl HelpCommand {
+    /// Builds the help text from the `(name, usage, description)` of every other command.
+    pub fn new<'c>(commands: impl Iterator<Item = (&'c str, &'c str, &'c str)>) -> Self {
+        let mut lines = vec!["/help - Show this list of commands".to_string()];
+        for (name, usage, description) in commands {
+            let invocation = if usage.is_empty() {
+                format!("/{}", name)
+            } else {
+                format!("/{} {}", name, usage)
+            };
+            lines.push(format!("{} - {}", invocation, description));
+        }
+        Self { text: lines.join("\n") }
+    }
+}
+
+impl<M: Model> Command<M> for HelpCommand {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn description(&self) -> &'static str {
+        "Show this list of commands"
+    }
+
+    fn execute<'s>(&'s self, session: &'s mut Session<'_, M>, _args: &'s str) -> CommandFuture<'s> {
+        Box::pin(async move {
+            session.io().show_message("Commands", &self.text);
+            Ok(CommandOutcome::Continue)
+        })
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{
+    core::{Message, Model},
+    session::Session,
+};
+
+use super::{Command, CommandFuture, CommandOutcome};
+
+pub struct HistoryCommand;
+
+const PREVIEW_CHARS: usize = 80;
+
+pub(super) fn preview(text: &str) -> String {
+    let first_line = text.lines().next().unwrap_or_default();
+    if first_line.chars().count() > PREVIEW_CHARS || text.lines().nth(1).is_some() {
+        format!("{}…", first_line.chars().take(PREVIEW_CHARS).collect::<String>())
+    } else {
+        first_line.to_string()
+    }
+}
+
+impl<M: Model> Command<M> for HistoryCommand {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn description(&self) -> &'static str {
+        "Show the messages in the current conversation"
+    }
+
+    fn execute<'s>(&'s self, session: &'s mut Session<'_, M>, _args: &'s str) -> CommandFuture<'s> {
+        Box::pin(async move {
+            let lines: Vec<String> = session
+                .messages()
+                .iter()
+                .enumerate()
+                .map(|(i, message)| {
+                    let line = match message {
+                        Message::User(text) => format!("You: {}", preview(text)),
+                        Message::Model(text) => format!("Deputy: {}", preview(text)),
+                        Message::ToolCall { tool_name, arguments, .. } => {
+                            format!("Tool call: {} {}", tool_name, preview(&arguments.to_string()))
+                        }
+                        Message::ToolResult { output, attachments, is_error, .. } => format!(
+                            "Tool {}: {}{}",
+                            if *is_error { "error" } else { "result" },
+                            preview(output),
+                            attachments.iter().map(|attachment| format!(" [{}]", attachment.path)).collect::<String>()
+                        ),
+                    };
+                    format!("{}. {}", i + 1, line)
+                })
+                .collect();
+
+            let text = if lines.is_empty() {
+                "The conversation is empty.".to_string()
+            } else {
+                lines.join("\n")
+            };
+            session.io().show_message(
+                &format!("Session {} (~{} tokens)", session.session_id(), session.context_tokens()),
+                &text,
+            );
+            Ok(CommandOutcome::Continue)
+        })
+    }
+}
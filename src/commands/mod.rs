@@ -0,0 +1,53 @@
+mod clear;
+mod compact;
+mod cost;
+mod exit;
+mod help;
+mod history;
+mod model;
+mod permissions;
+mod registry;
+mod rewind;
+mod save;
+mod tools;
+mod undo;
+
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::{core::Model, error::Result, session::Session};
+
+pub use clear::ClearCommand;
+pub use compact::CompactCommand;
+pub use cost::CostCommand;
+pub use exit::ExitCommand;
+pub use help::HelpCommand;
+pub use history::HistoryCommand;
+pub use model::ModelCommand;
+pub use permissions::PermissionsCommand;
+pub use registry::CommandRegistry;
+pub use rewind::RewindCommand;
+pub use save::SaveCommand;
+pub use tools::ToolsCommand;
+pub use undo::UndoCommand;
+
+/// What the REPL should do after a command has run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandOutcome {
+    Continue,
+    Exit,
+}
+
+pub type CommandFuture<'s> = Pin<Box<dyn Future<Output = Result<CommandOutcome>> + 's>>;
+
+/// A slash command typed at the REPL prompt, e.g. `/clear`.
+pub trait Command<M: Model> {
+    /// Name of the command, without the leading slash.
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    /// Arguments accepted by the command, shown in `/help`.
+    fn usage(&self) -> &'static str {
+        ""
+    }
+    fn execute<'s>(&'s self, session: &'s mut Session<'_, M>, args: &'s str) -> CommandFuture<'s>;
+}
@@ -0,0 +1,35 @@
+use crate::{core::Model, session::Session};
+
+use super::{Command, CommandFuture, CommandOutcome};
+
+pub struct ModelCommand;
+
+impl<M: Model> Command<M> for ModelCommand {
+    fn name(&self) -> &'static str {
+        "model"
+    }
+
+    fn description(&self) -> &'static str {
+        "Show the current model, or switch to another model of the same provider"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[name]"
+    }
+
+    fn execute<'s>(&'s self, session: &'s mut Session<'_, M>, args: &'s str) -> CommandFuture<'s> {
+        Box::pin(async move {
+            if args.is_empty() {
+                session.io().show_message(
+                    "Model",
+                    &format!("Currently using {} (~{} tokens in context).", session.model_name(), session.context_tokens()),
+                );
+            } else {
+                let previous = session.model_name().to_string();
+                session.set_model_name(args.to_string());
+                session.io().show_message("Model switched", &format!("{} -> {}", previous, args));
+            }
+            Ok(CommandOutcome::Continue)
+        })
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{core::Model, session::Session};
+
+use super::{Command, CommandFuture, CommandOutcome};
+
+pub struct PermissionsCommand;
+
+impl PermissionsCommand {
+    fn list<M: Model>(session: &Session<'_, M>) -> String {
+        let mut tool_names: Vec<&String> = session
+            .tool_permissions()
+            .iter()
+            .filter(|(_, mode)| !mode.is_empty())
+            .map(|(tool_name, _)| tool_name)
+            .collect();
+        tool_names.sort();
+
+        if tool_names.is_empty() {
+            return "No permissions have been granted or denied yet; every tool call will ask first.".to_string();
+        }
+
+        let mut lines = Vec::new();
+        for tool_name in tool_names {
+            let mode = &session.tool_permissions()[tool_name];
+            lines.push(tool_name.clone());
+            if !mode.approved().is_empty() {
+                lines.push(format!("  allowed: {}", Self::join(mode.approved())));
+            }
+            if !mode.denied().is_empty() {
+                lines.push(format!("  denied:  {}", Self::join(mode.denied())));
+            }
+        }
+        lines.join("\n")
+    }
+
+    fn join<'a>(ids: impl IntoIterator<Item = &'a String>) -> String {
+        ids.into_iter().map(String::as_str).collect::<Vec<_>>().join(", ")
+    }
+}
+
+impl<M: Model> Command<M> for PermissionsCommand {
+    fn name(&self) -> &'static str {
+        "permissions"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the tool permissions of this session, or allow, deny or revoke a permission id"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[allow|deny|revoke <tool> [id]]"
+    }
+
+    fn execute<'s>(&'s self, session: &'s mut Session<'_, M>, args: &'s str) -> CommandFuture<'s> {
+        Box::pin(async move {
+            let mut parts = args.splitn(3, char::is_whitespace);
+            let action = parts.next().unwrap_or_default();
+            let tool_name = parts.next().unwrap_or_default();
+            let permission_id = parts.next().unwrap_or_default().trim();
+
+            if action.is_empty() || action == "list" {
+                session.io().show_message("Permissions", &Self::list(session));
+                return Ok(CommandOutcome::Continue);
+            }
+
+            if !matches!(action, "allow" | "deny" | "revoke") {
+                session.io().show_message(
+                    "Permissions",
+                    &format!("Unknown action {}; use /permissions [allow|deny|revoke <tool> [id]].", action),
+                );
+                return Ok(CommandOutcome::Continue);
+            }
+            if !session.tools().contains_key(tool_name) {
+                let mut names: Vec<&String> = session.tools().keys().collect();
+                names.sort();
+                session.io().show_message(
+                    "Permissions",
+                    &format!("Unknown tool {:?}; available tools: {}.", tool_name, Self::join(names)),
+                );
+                return Ok(CommandOutcome::Continue);
+            }
+            if permission_id.is_empty() && action != "revoke" {
+                session.io().show_message(
+                    "Permissions",
+                    &format!("Specify the permission id to {}, e.g. /permissions {} exec_command cargo test.", action, action),
+                );
+                return Ok(CommandOutcome::Continue);
+            }
+
+            let mode = session.tool_permissions_mut(tool_name);
+            let message = match action {
+                "allow" => {
+                    mode.approve(permission_id);
+                    format!("{} ({}) is allowed for the rest of this session.", tool_name, permission_id)
+                }
+                "deny" => {
+                    mode.deny(permission_id);
+                    format!("{} ({}) is denied for the rest of this session.", tool_name, permission_id)
+                }
+                _ if permission_id.is_empty() => {
+                    *mode = Default::default();
+                    format!("All permissions of {} were revoked.", tool_name)
+                }
+                _ if mode.revoke(permission_id) => {
+                    format!("{} ({}) will be asked about again.", tool_name, permission_id)
+                }
+                _ => format!("{} ({}) was neither allowed nor denied.", tool_name, permission_id),
+            };
+            session.io().show_message("Permissions", &message);
+            Ok(CommandOutcome::Continue)
+        })
+    }
+}
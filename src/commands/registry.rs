@@ -0,0 +1,92 @@
+use crate::{core::Model, error::Result, session::Session};
+
+use super::{
+    ClearCommand, Command, CommandOutcome, CompactCommand, CostCommand, ExitCommand, HelpCommand,
+    HistoryCommand, ModelCommand, PermissionsCommand, RewindCommand, SaveCommand, ToolsCommand, UndoCommand,
+};
+
+pub struct CommandRegistry<M: Model> {
+    commands: Vec<Box<dyn Command<M>>>,
+}
+
+impl<M: Model> CommandRegistry<M> {
+    pub fn with_default_commands() -> Self {
+        let mut commands: Vec<Box<dyn Command<M>>> = vec![
+            Box::new(ClearCommand),
+            Box::new(CompactCommand),
+            Box::new(CostCommand),
+            Box::new(HistoryCommand),
+            Box::new(ModelCommand),
+            Box::new(PermissionsCommand),
+            Box::new(SaveCommand),
+            Box::new(ToolsCommand),
+            Box::new(UndoCommand),
+            Box::new(RewindCommand),
+            Box::new(ExitCommand),
+        ];
+        let help = HelpCommand::new(commands.iter().map(|c| (c.name(), c.usage(), c.description())));
+        commands.insert(0, Box::new(help));
+        Self { commands }
+    }
+
+    /// Names of all commands, including the leading slash.
+    pub fn names(&self) -> Vec<String> {
+        self.commands.iter().map(|c| format!("/{}", c.name())).collect()
+    }
+
+    /// Returns whether `input` invokes a command: a slash immediately followed by the name of a
+    /// registered command. Anything else, such as an absolute path, is meant for the model.
+    pub fn is_command(&self, input: &str) -> bool {
+        input
+            .strip_prefix('/')
+            .is_some_and(|invocation| self.commands.iter().any(|c| c.name() == split(invocation).0))
+    }
+
+    /// Runs the command in `invocation` (the user's input without the leading slash).
+    pub async fn execute(&self, session: &mut Session<'_, M>, invocation: &str) -> Result<CommandOutcome> {
+        let (name, args) = split(invocation);
+
+        match self.commands.iter().find(|c| c.name() == name) {
+            Some(command) => command.execute(session, args.trim()).await,
+            None => {
+                session.io().show_message(
+                    "Unknown command",
+                    &format!("/{} is not a command. Type /help to see the available commands.", name),
+                );
+                Ok(CommandOutcome::Continue)
+            }
+        }
+    }
+}
+
+/// Splits an invocation into the command's name and its arguments.
+fn split(invocation: &str) -> (&str, &str) {
+    invocation
+        .split_once(char::is_whitespace)
+        .unwrap_or((invocation, ""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::anthropic::anthropic_model::AnthropicModel;
+
+    #[test]
+    fn only_registered_names_are_commands() {
+        let commands = CommandRegistry::<AnthropicModel>::with_default_commands();
+        assert!(commands.is_command("/help"));
+        assert!(commands.is_command("/model claude-opus-4-1"));
+        assert!(commands.is_command("/rewind\t2"));
+        assert!(!commands.is_command("/etc/nginx/nginx.conf fails to parse, why?"));
+        assert!(!commands.is_command("/helpme"));
+        assert!(!commands.is_command("/"));
+        assert!(!commands.is_command("help"));
+        assert!(!commands.is_command(" /help"));
+    }
+
+    #[test]
+    fn splits_name_from_arguments() {
+        assert_eq!(split("model gpt-4o"), ("model", "gpt-4o"));
+        assert_eq!(split("cost"), ("cost", ""));
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{core::Model, session::{RewindOutcome, Session}};
+
+use super::{history::preview, undo::describe, Command, CommandFuture, CommandOutcome};
+
+pub struct RewindCommand;
+
+impl RewindCommand {
+    fn list<M: Model>(session: &Session<'_, M>) -> String {
+        let lines: Vec<String> = session
+            .turns()
+            .into_iter()
+            .map(|(turn, text, changes)| match changes {
+                0 => format!("{}. {}", turn, preview(text)),
+                1 => format!("{}. {} (1 file change)", turn, preview(text)),
+                _ => format!("{}. {} ({} file changes)", turn, preview(text), changes),
+            })
+            .collect();
+        if lines.is_empty() {
+            return "The conversation is empty.".to_string();
+        }
+        format!("{}\n\nUse /rewind <turn> to go back to before that turn.", lines.join("\n"))
+    }
+}
+
+impl<M: Model> Command<M> for RewindCommand {
+    fn name(&self) -> &'static str {
+        "rewind"
+    }
+
+    fn description(&self) -> &'static str {
+        "Go back to before a turn, reverting the files changed since and removing it and all later turns from the conversation; without a turn, list the turns"
+    }
+
+    fn usage(&self) -> &'static str {
+        "[turn]"
+    }
+
+    fn execute<'s>(&'s self, session: &'s mut Session<'_, M>, args: &'s str) -> CommandFuture<'s> {
+        Box::pin(async move {
+            if args.is_empty() {
+                session.io().show_message("Turns", &Self::list(session));
+                return Ok(CommandOutcome::Continue);
+            }
+
+            let rewind = match args.parse::<usize>() {
+                Ok(turn) => session.rewind(turn)?,
+                Err(_) => RewindOutcome::NoSuchTurn,
+            };
+            match rewind {
+                RewindOutcome::NoSuchTurn => session.io().show_message(
+                    "Rewind",
+                    &format!("{} is not a turn of this conversation. Type /rewind to list the turns.", args),
+                ),
+                RewindOutcome::Cancelled => session.io().show_message("Rewind cancelled", "Nothing was changed."),
+                RewindOutcome::Rewound(rewind) => {
+                    session.save()?;
+                    session.io().show_message("Rewound", &describe(&rewind));
+                }
+            }
+            Ok(CommandOutcome::Continue)
+        })
+    }
+}
@@ -0,0 +1,30 @@
+use crate::{core::Model, session::Session};
+
+use super::{Command, CommandFuture, CommandOutcome};
+
+pub struct SaveCommand;
+
+impl<M: Model> Command<M> for SaveCommand {
+    fn name(&self) -> &'static str {
+        "save"
+    }
+
+    fn description(&self) -> &'static str {
+        "Save the conversation now"
+    }
+
+    fn execute<'s>(&'s self, session: &'s mut Session<'_, M>, _args: &'s str) -> CommandFuture<'s> {
+        Box::pin(async move {
+            if session.messages().is_empty() {
+                session.io().show_message("Nothing to save", "The conversation is empty.");
+            } else {
+                session.save()?;
+                session.io().show_message(
+                    "Session saved",
+                    &format!("Resume this conversation with `deputy --resume {}`.", session.session_id()),
+                );
+            }
+            Ok(CommandOutcome::Continue)
+        })
+    }
+}
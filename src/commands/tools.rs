@@ -0,0 +1,32 @@
+use crate::{core::Model, session::Session};
+
+use super::{Command, CommandFuture, CommandOutcome};
+
+pub struct ToolsCommand;
+
+impl<M: Model> Command<M> for ToolsCommand {
+    fn name(&self) -> &'static str {
+        "tools"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the tools available to the model"
+    }
+
+    fn execute<'s>(&'s self, session: &'s mut Session<'_, M>, _args: &'s str) -> CommandFuture<'s> {
+        Box::pin(async move {
+            let mut lines: Vec<String> = session
+                .tools()
+                .values()
+                .map(|tool| {
+                    let description = tool.description();
+                    let summary = description.split(". ").next().unwrap_or_default().trim_end_matches('.');
+                    format!("{} - {}", tool.name(), summary)
+                })
+                .collect();
+            lines.sort();
+            session.io().show_message("Tools", &lines.join("\n"));
+            Ok(CommandOutcome::Continue)
+        })
+    }
+}
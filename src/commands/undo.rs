@@ -0,0 +1,48 @@
+use crate::{core::Model, session::{Rewind, RewindOutcome, Session}};
+
+use super::{Command, CommandFuture, CommandOutcome};
+
+pub struct UndoCommand;
+
+/// Describes what a rewind removed from the conversation and reverted on disk.
+pub(super) fn describe(rewind: &Rewind) -> String {
+    let mut text = format!("Removed {} messages from the conversation.", rewind.messages_removed);
+    if !rewind.files.is_empty() {
+        text.push_str("\nReverted file changes:");
+        for line in &rewind.files {
+            text.push_str(&format!("\n  {}", line));
+        }
+    }
+    if rewind.before_resume {
+        text.push_str("\nFiles changed before this session was resumed were left as they are: deputy can only revert the changes it made since.");
+    }
+    text
+}
+
+impl<M: Model> Command<M> for UndoCommand {
+    fn name(&self) -> &'static str {
+        "undo"
+    }
+
+    fn description(&self) -> &'static str {
+        "Remove your last message and the model's response from the conversation, and revert the files it changed"
+    }
+
+    fn execute<'s>(&'s self, session: &'s mut Session<'_, M>, _args: &'s str) -> CommandFuture<'s> {
+        Box::pin(async move {
+            let rewind = match session.last_turn() {
+                Some(turn) => session.rewind(turn)?,
+                None => RewindOutcome::NoSuchTurn,
+            };
+            match rewind {
+                RewindOutcome::NoSuchTurn => session.io().show_message("Nothing to undo", "The conversation is empty."),
+                RewindOutcome::Cancelled => session.io().show_message("Undo cancelled", "Nothing was changed."),
+                RewindOutcome::Rewound(rewind) => {
+                    session.save()?;
+                    session.io().show_message("Undone", &describe(&rewind));
+                }
+            }
+            Ok(CommandOutcome::Continue)
+        })
+    }
+}
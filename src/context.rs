@@ -1,7 +1,10 @@
 use ignore::WalkBuilder;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use crate::core::{CheckpointLog, PermissionPolicy, UnattendedPolicy};
 use crate::provider::Provider;
+use crate::tools::SandboxConfig;
 use crate::error::{ConfigError, Result};
 
 pub struct ModelConfig {
@@ -10,12 +13,20 @@ pub struct ModelConfig {
     pub base_url_override: Option<String>,
     pub yolo_mode: bool,
     pub max_tokens: u32,
+    pub context_budget: u32,
+    pub unattended_policy: Option<UnattendedPolicy>,
+    pub sandbox: Option<SandboxConfig>,
+    pub persistent_shell: bool,
+    /// Directories besides the working directory that the file tools may access without asking.
+    pub allowed_roots: Vec<PathBuf>,
 }
 
 pub struct SessionConfig {
     agent_instructions: Option<String>,
     cwd: String,
     initial_file_tree: Option<String>,
+    permission_policy: PermissionPolicy,
+    checkpoints: Arc<CheckpointLog>,
 }
 
 pub struct Context {
@@ -27,18 +38,54 @@ impl ModelConfig {
     /// Creates a new ModelConfig with the provided settings.
     /// 
     /// Validates the provider configuration before creating the config.
-    /// Sets max_tokens to a default value of 5,000.
+    /// Sets max_tokens to a default value of 5,000 and the context budget to the provider's default.
     pub fn new(provider: Provider, model_name: String, yolo_mode: bool, base_url_override: Option<String>) -> Result<Self> {
         provider.validate_configuration()?;
 
         Ok(Self {
+            context_budget: provider.default_context_budget(),
             provider,
             model_name,
             base_url_override,
             yolo_mode,
             max_tokens: 5_000,
+            unattended_policy: None,
+            sandbox: None,
+            persistent_shell: false,
+            allowed_roots: Vec::new(),
         })
     }
+
+    /// Overrides the number of tokens the conversation may occupy before it is compacted.
+    pub fn with_context_budget(mut self, context_budget: u32) -> Self {
+        self.context_budget = context_budget;
+        self
+    }
+
+    /// Resolves permission requests with the given policy instead of prompting the user.
+    pub fn with_unattended_policy(mut self, policy: UnattendedPolicy) -> Self {
+        self.unattended_policy = Some(policy);
+        self
+    }
+
+    /// Runs shell commands in a sandbox with the given restrictions.
+    pub fn with_sandbox(mut self, sandbox: SandboxConfig) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Offers the model a shell that keeps its working directory and environment between calls.
+    pub fn with_persistent_shell(mut self) -> Self {
+        self.persistent_shell = true;
+        self
+    }
+
+    /// Lets the file tools access these directories, in addition to the working directory,
+    /// without asking.
+    pub fn with_allowed_roots(mut self, allowed_roots: Vec<PathBuf>) -> Self {
+        self.allowed_roots = allowed_roots;
+        self
+    }
 }
 
 impl SessionConfig {
@@ -52,6 +99,9 @@ impl SessionConfig {
     /// 3. `AGENTS.md` in current directory
     /// 4. `CLAUDE.md` in current directory
     /// 5. `~/.claude/CLAUDE.md`
+    ///
+    /// Also loads the permission policy from `.deputy/permissions.toml` in the current
+    /// directory and in the home directory.
     pub fn from_env(custom_config_path: Option<PathBuf>) -> Result<Self> {
         let cwd = std::env::current_dir()
             .map_err(|e| ConfigError::Invalid { 
@@ -59,6 +109,7 @@ impl SessionConfig {
             })?;
 
         let initial_file_tree = Self::generate_file_tree(&cwd);
+        let permission_policy = PermissionPolicy::load(&cwd)?;
 
         let instructions = if let Some(custom_path) = custom_config_path {
             // Use custom config path if provided
@@ -104,9 +155,26 @@ impl SessionConfig {
             agent_instructions: instructions,
             cwd: cwd.to_string_lossy().into_owned(),
             initial_file_tree,
+            permission_policy,
+            checkpoints: Arc::new(CheckpointLog::new()),
         })
     }
 
+    /// Returns the working directory the session was started from.
+    pub fn cwd(&self) -> &str {
+        &self.cwd
+    }
+
+    /// Returns the permission rules loaded at startup.
+    pub fn permission_policy(&self) -> &PermissionPolicy {
+        &self.permission_policy
+    }
+
+    /// Returns the session's record of file changes, shared by the file tools and the session.
+    pub fn checkpoints(&self) -> &Arc<CheckpointLog> {
+        &self.checkpoints
+    }
+
     /// Generates the system prompt based on the session configuration.
     pub fn to_system_prompt(&self) -> String {
         let mut prompt = String::new();
@@ -279,6 +347,20 @@ Note: This is a snapshot from when Deputy started. The actual file structure may
     }
 }
 
+#[cfg(test)]
+impl SessionConfig {
+    /// A configuration for `cwd` without instructions or permission rules.
+    pub fn for_tests(cwd: &Path) -> Self {
+        Self {
+            agent_instructions: None,
+            cwd: cwd.to_string_lossy().into_owned(),
+            initial_file_tree: None,
+            permission_policy: PermissionPolicy::empty(cwd),
+            checkpoints: Arc::new(CheckpointLog::new()),
+        }
+    }
+}
+
 impl Context {
     /// Creates a new Context from the provided model configuration and session configuration.
     pub fn new(model_config: ModelConfig, session_config: SessionConfig) -> Self {
@@ -0,0 +1,177 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::error::{SessionError, Result};
+
+/// One change a tool made to a file.
+#[derive(Debug, Clone)]
+struct FileChange {
+    /// The conversation turn during which the change was made.
+    turn: usize,
+    path: PathBuf,
+    /// The file's content before the change, or `None` if the change created the file.
+    original: Option<String>,
+    updated: String,
+}
+
+#[derive(Debug, Default)]
+struct CheckpointState {
+    turn: usize,
+    changes: Vec<FileChange>,
+}
+
+/// Records every change deputy's file tools make during a session, so that the changes of the
+/// most recent turns can be reverted. Changes made by shell commands are not recorded.
+#[derive(Debug, Default)]
+pub struct CheckpointLog {
+    state: Mutex<CheckpointState>,
+}
+
+impl CheckpointLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attributes the changes recorded from now on to `turn`.
+    pub fn begin_turn(&self, turn: usize) {
+        if let Ok(mut state) = self.state.lock() {
+            state.turn = turn;
+        }
+    }
+
+    /// Records that `path` was changed from `original` (`None` if it did not exist) to `updated`.
+    pub fn record(&self, path: &Path, original: Option<String>, updated: String) {
+        if let Ok(mut state) = self.state.lock() {
+            let turn = state.turn;
+            state.changes.push(FileChange {
+                turn,
+                path: path.to_path_buf(),
+                original,
+                updated,
+            });
+        }
+    }
+
+    /// Number of changes made during `turn`.
+    pub fn changes_in(&self, turn: usize) -> usize {
+        self.state
+            .lock()
+            .map(|state| state.changes.iter().filter(|change| change.turn == turn).count())
+            .unwrap_or_default()
+    }
+
+    /// Files changed during `turn` or later whose content on disk is no longer what deputy last
+    /// wrote, e.g. because the user edited them since; reverting would discard those edits.
+    pub fn modified_since(&self, turn: usize) -> Vec<PathBuf> {
+        let Ok(state) = self.state.lock() else {
+            return Vec::new();
+        };
+        let mut seen: Vec<&Path> = Vec::new();
+        let mut modified = Vec::new();
+        // the newest change of each file tells what it should contain now
+        for change in state.changes.iter().rev().take_while(|change| change.turn >= turn) {
+            if seen.contains(&change.path.as_path()) {
+                continue;
+            }
+            seen.push(&change.path);
+            if change.path.exists() && std::fs::read(&change.path).ok().as_deref() != Some(change.updated.as_bytes()) {
+                modified.push(change.path.clone());
+            }
+        }
+        modified.reverse();
+        modified
+    }
+
+    /// Reverts every change made during `turn` or later, newest first, and forgets them. Files
+    /// created during those turns are deleted. Returns a line describing each reverted file.
+    pub fn revert_since(&self, turn: usize) -> Result<Vec<String>> {
+        let mut state = self.state.lock().map_err(|_| SessionError::Processing {
+            reason: "checkpoints: state is poisoned".to_string()
+        })?;
+        let first = state.changes.iter().position(|change| change.turn >= turn).unwrap_or(state.changes.len());
+        let reverted: Vec<FileChange> = state.changes.drain(first..).collect();
+
+        let mut lines: Vec<(&Path, String)> = Vec::new();
+        for change in reverted.iter().rev() {
+            let display = change.path.display();
+            let modified_since = std::fs::read_to_string(&change.path).ok().as_ref() != Some(&change.updated);
+            let result = match &change.original {
+                Some(original) => std::fs::write(&change.path, original).map(|_| format!("restored {}", display)),
+                None if change.path.exists() => std::fs::remove_file(&change.path).map(|_| format!("deleted {}", display)),
+                None => Ok(format!("{} was already deleted", display)),
+            };
+            let line = match result {
+                Ok(line) if modified_since => format!("{} (its later changes were discarded)", line),
+                Ok(line) => line,
+                Err(e) => format!("could not revert {}: {}", display, e),
+            };
+            // a file edited several times is reported once, for its oldest change
+            lines.retain(|(path, _)| *path != change.path);
+            lines.push((&change.path, line));
+        }
+        Ok(lines.into_iter().map(|(_, line)| line).collect())
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.changes.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_project;
+
+    /// Writes `updated` to `path` and records it as a change made during `turn`.
+    fn change(log: &CheckpointLog, turn: usize, path: &Path, updated: &str) {
+        let original = std::fs::read_to_string(path).ok();
+        std::fs::write(path, updated).unwrap();
+        log.begin_turn(turn);
+        log.record(path, original, updated.to_string());
+    }
+
+    #[test]
+    fn reverts_changes_since_a_turn() {
+        let dir = temp_project(&[]);
+        let edited = dir.join("edited.txt");
+        let created = dir.join("created.txt");
+        std::fs::write(&edited, "v0").unwrap();
+        let log = CheckpointLog::new();
+        change(&log, 1, &edited, "v1");
+        change(&log, 2, &edited, "v2");
+        change(&log, 2, &created, "new");
+        change(&log, 3, &edited, "v3");
+        assert_eq!(log.changes_in(2), 2);
+
+        let lines = log.revert_since(2).unwrap();
+
+        assert_eq!(lines, vec![format!("deleted {}", created.display()), format!("restored {}", edited.display())]);
+        assert_eq!(std::fs::read_to_string(&edited).unwrap(), "v1");
+        assert!(!created.exists());
+        assert_eq!(log.changes_in(2), 0);
+        assert_eq!(log.changes_in(1), 1);
+    }
+
+    #[test]
+    fn finds_files_changed_outside_deputy() {
+        let dir = temp_project(&[]);
+        let kept = dir.join("kept.txt");
+        let edited = dir.join("edited.txt");
+        let deleted = dir.join("deleted.txt");
+        let log = CheckpointLog::new();
+        change(&log, 1, &kept, "a");
+        change(&log, 1, &edited, "a");
+        change(&log, 2, &edited, "b");
+        change(&log, 2, &deleted, "a");
+        assert!(log.modified_since(1).is_empty());
+
+        std::fs::write(&edited, "by the user").unwrap();
+        std::fs::remove_file(&deleted).unwrap();
+
+        // a deleted file loses nothing when it is restored
+        assert_eq!(log.modified_since(1), vec![edited.clone()]);
+        assert_eq!(log.modified_since(3), Vec::<PathBuf>::new());
+    }
+}
@@ -0,0 +1,64 @@
+//! Routes Ctrl-C to the operation that is currently running, if any.
+//!
+//! Child processes run in their own process group, so they do not receive the terminal's
+//! SIGINT themselves. Instead, deputy listens for it: while an operation holds an
+//! [`InterruptGuard`], Ctrl-C cancels that operation; otherwise deputy exits, as it would have
+//! without a handler, after running the hooks registered with [`on_exit`].
+
+use std::sync::{LazyLock, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::sync::Notify;
+
+/// Exit status conventionally used for processes terminated by SIGINT.
+const EXIT_INTERRUPTED: i32 = 130;
+
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+static INTERRUPTS: LazyLock<Notify> = LazyLock::new(Notify::new);
+static EXIT_HOOKS: Mutex<Vec<fn()>> = Mutex::new(Vec::new());
+
+/// Starts listening for Ctrl-C. Must be called from within the tokio runtime.
+pub fn install() {
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if ACTIVE.load(Ordering::SeqCst) > 0 {
+                INTERRUPTS.notify_waiters();
+            } else {
+                let hooks = EXIT_HOOKS.lock().map(|hooks| hooks.clone()).unwrap_or_default();
+                for hook in hooks {
+                    hook();
+                }
+                std::process::exit(EXIT_INTERRUPTED);
+            }
+        }
+    });
+}
+
+/// Registers a function to run when Ctrl-C makes deputy exit, such as one that stops the
+/// processes deputy left running in the background.
+pub fn on_exit(hook: fn()) {
+    if let Ok(mut hooks) = EXIT_HOOKS.lock() {
+        hooks.push(hook);
+    }
+}
+
+/// Marks an interruptible operation as running for as long as it is alive.
+pub struct InterruptGuard;
+
+impl InterruptGuard {
+    pub fn new() -> Self {
+        ACTIVE.fetch_add(1, Ordering::SeqCst);
+        Self
+    }
+
+    /// Resolves when the user presses Ctrl-C.
+    pub async fn interrupted(&self) {
+        INTERRUPTS.notified().await
+    }
+}
+
+impl Drop for InterruptGuard {
+    fn drop(&mut self) {
+        ACTIVE.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -1,7 +1,13 @@
+mod checkpoint;
+pub mod interrupt;
 mod model;
 mod permissions;
+mod policy;
+pub mod shell;
 mod tool;
 
+pub use checkpoint::CheckpointLog;
 pub use model::*;
-pub use permissions::PermissionMode;
+pub use permissions::{PermissionMode, UnattendedPolicy};
+pub use policy::{PermissionPolicy, PolicyAction, PolicyScope};
 pub use tool::*;
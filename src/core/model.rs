@@ -1,4 +1,10 @@
-#[derive(Clone, Debug)]
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+use crate::{error::Result, io::IO};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "role", content = "content", rename_all = "snake_case")]
 pub enum Message {
     User(String),
     Model(String),
@@ -10,17 +16,61 @@ pub enum Message {
     ToolResult {
         id: std::option::Option<String>,
         output: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        attachments: Vec<Attachment>,
         is_error: bool,
     },
 }
 
-use crate::error::Result;
-use std::future::Future;
+/// A file passed to the model alongside a tool's text output, such as an image or a PDF it read.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub path: String,
+    pub media_type: String,
+    /// The file's content, base64-encoded.
+    pub data: String,
+}
+
+impl Attachment {
+    pub fn is_image(&self) -> bool {
+        self.media_type.starts_with("image/")
+    }
+}
+
+/// Token usage reported by the provider for a single request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+impl Usage {
+    pub fn total(&self) -> u32 {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+/// The messages produced by a single model request, along with the tokens it consumed.
+#[derive(Clone, Debug)]
+pub struct ModelResponse {
+    pub messages: Vec<Message>,
+    pub usage: Option<Usage>,
+}
 
 pub trait Model {
+    fn model_name(&self) -> &str;
+    fn set_model_name(&mut self, model_name: String);
     fn send_message(
         &self,
         message: Message,
         message_history: Vec<Message>,
-    ) -> impl Future<Output = Result<Vec<Message>>>;
+        io: &dyn IO,
+    ) -> impl Future<Output = Result<ModelResponse>>;
 }
@@ -1,4 +1,101 @@
-pub enum PermissionMode {
-    Ask,
-    ApprovedForId { command_id: String },
-}
\ No newline at end of file
+use std::collections::BTreeSet;
+
+use clap::ValueEnum;
+
+/// Permission ids that have been approved or denied for a tool during a session. Ids in
+/// neither set are asked about.
+#[derive(Debug, Default)]
+pub struct PermissionMode {
+    approved: BTreeSet<String>,
+    denied: BTreeSet<String>,
+}
+
+impl PermissionMode {
+    pub fn approved(&self) -> &BTreeSet<String> {
+        &self.approved
+    }
+
+    pub fn denied(&self) -> &BTreeSet<String> {
+        &self.denied
+    }
+
+    pub fn is_approved(&self, permission_id: &str) -> bool {
+        self.approved.contains(permission_id)
+    }
+
+    pub fn is_denied(&self, permission_id: &str) -> bool {
+        self.denied.contains(permission_id)
+    }
+
+    /// Approves an id, replacing an earlier denial.
+    pub fn approve(&mut self, permission_id: &str) {
+        self.denied.remove(permission_id);
+        self.approved.insert(permission_id.to_string());
+    }
+
+    /// Denies an id, replacing an earlier approval.
+    pub fn deny(&mut self, permission_id: &str) {
+        self.approved.remove(permission_id);
+        self.denied.insert(permission_id.to_string());
+    }
+
+    /// Forgets an approval or denial, so that the id is asked about again.
+    /// Returns `false` if the id was neither approved nor denied.
+    pub fn revoke(&mut self, permission_id: &str) -> bool {
+        self.approved.remove(permission_id) | self.denied.remove(permission_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.approved.is_empty() && self.denied.is_empty()
+    }
+}
+
+/// How permission requests are resolved when nobody is available to answer a prompt.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum UnattendedPolicy {
+    /// Deny every tool call that would otherwise require a prompt
+    Deny,
+    /// Allow every tool call that would otherwise require a prompt
+    Allow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_every_approved_id() {
+        let mut mode = PermissionMode::default();
+        mode.approve("cargo test");
+        mode.approve("git status");
+        assert!(mode.is_approved("cargo test"));
+        assert!(mode.is_approved("git status"));
+        assert!(!mode.is_approved("git push"));
+        assert!(!mode.is_denied("git push"));
+    }
+
+    #[test]
+    fn approving_and_denying_replace_each_other() {
+        let mut mode = PermissionMode::default();
+        mode.approve("rm");
+        mode.deny("rm");
+        assert!(mode.is_denied("rm"));
+        assert!(!mode.is_approved("rm"));
+
+        mode.approve("rm");
+        assert!(mode.is_approved("rm"));
+        assert!(!mode.is_denied("rm"));
+    }
+
+    #[test]
+    fn revoking_forgets_an_id() {
+        let mut mode = PermissionMode::default();
+        mode.approve("cargo test");
+        mode.deny("curl");
+
+        assert!(mode.revoke("cargo test"));
+        assert!(mode.revoke("curl"));
+        assert!(!mode.revoke("curl"));
+        assert!(mode.is_empty());
+    }
+}
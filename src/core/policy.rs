@@ -0,0 +1,534 @@
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::core::shell::{self, ShellCommand};
+use crate::error::{ConfigError, Result};
+use crate::tools::resolve_path;
+
+/// Location of the policy file, relative to the project root or the home directory.
+const POLICY_FILE: &str = ".deputy/permissions.toml";
+
+/// What happens to a tool call matched by a rule. Variants are ordered from least to most
+/// restrictive, so that the most restrictive of several matching rules can be picked with `max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyAction {
+    Allow,
+    Ask,
+    Deny,
+}
+
+/// Which policy file a rule is read from or saved to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PolicyScope {
+    Project,
+    User,
+}
+
+impl std::fmt::Display for PolicyScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyScope::Project => write!(f, "project"),
+            PolicyScope::User => write!(f, "user"),
+        }
+    }
+}
+
+/// A single `[[rules]]` entry of a policy file. Every pattern that is set must match for the
+/// rule to apply; a rule without patterns applies to every call of the tool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// Tool name, or `*` for every tool.
+    pub tool: String,
+    pub action: PolicyAction,
+    /// Glob matched against the `path`/`paths` arguments, e.g. `src/**`. Relative globs are matched
+    /// against paths relative to the project root, absolute globs against absolute paths.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Prefix matched against the `command` argument on word boundaries, e.g. `cargo test`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Regular expression matched against the `command` argument.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_regex: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PolicyFile {
+    #[serde(default)]
+    rules: Vec<PolicyRule>,
+}
+
+#[derive(Clone)]
+struct CompiledRule {
+    rule: PolicyRule,
+    scope: PolicyScope,
+    path: Option<GlobMatcher>,
+    command_regex: Option<Regex>,
+}
+
+/// A `path`/`paths` argument of a tool call, resolved against the project root.
+struct ArgumentPath {
+    resolved: PathBuf,
+    /// The path relative to the project root, or `.` for the root itself; `None` for paths
+    /// outside the project.
+    relative: Option<String>,
+    /// Whether the path as given climbs with `..`.
+    climbs: bool,
+}
+
+impl ArgumentPath {
+    fn new(path: &str, project_root: &Path) -> Self {
+        let resolved = resolve_path(&project_root.join(path));
+        let relative = resolved.strip_prefix(project_root).ok().map(|relative| match relative.as_os_str().is_empty() {
+            true => ".".to_string(),
+            false => relative.to_string_lossy().into_owned(),
+        });
+        let climbs = Path::new(path).components().any(|component| component == Component::ParentDir);
+        Self { resolved, relative, climbs }
+    }
+}
+
+/// Allow/ask/deny rules loaded from the project-level and user-level policy files.
+#[derive(Clone)]
+pub struct PermissionPolicy {
+    rules: Vec<CompiledRule>,
+    project_root: PathBuf,
+    project_path: PathBuf,
+    user_path: Option<PathBuf>,
+}
+
+impl PermissionPolicy {
+    /// Loads `.deputy/permissions.toml` from the project root and from the home directory.
+    /// Missing files are treated as empty; files that cannot be parsed are an error.
+    pub fn load(project_root: &Path) -> Result<Self> {
+        let mut policy = Self {
+            rules: Vec::new(),
+            project_root: resolve_path(project_root),
+            project_path: project_root.join(POLICY_FILE),
+            user_path: dirs::home_dir().map(|home| home.join(POLICY_FILE)),
+        };
+
+        let mut sources = vec![(policy.project_path.clone(), PolicyScope::Project)];
+        if let Some(user_path) = &policy.user_path {
+            sources.push((user_path.clone(), PolicyScope::User));
+        }
+        for (path, scope) in sources {
+            let data = match std::fs::read_to_string(&path) {
+                Ok(data) => data,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(ConfigError::ReadFailed {
+                        reason: format!("permission policy {}: {}", path.display(), e)
+                    }.into());
+                }
+            };
+            let file: PolicyFile = toml::from_str(&data).map_err(|e| ConfigError::Invalid {
+                reason: format!("permission policy {}: {}", path.display(), e)
+            })?;
+            for rule in file.rules {
+                let compiled = Self::compile(rule, scope).map_err(|reason| ConfigError::Invalid {
+                    reason: format!("permission policy {}: {}", path.display(), reason)
+                })?;
+                policy.rules.push(compiled);
+            }
+        }
+        Ok(policy)
+    }
+
+    /// A policy without rules, whose files are never read; "always allow" rules are saved below `project_root`.
+    #[cfg(test)]
+    pub fn empty(project_root: &Path) -> Self {
+        Self {
+            rules: Vec::new(),
+            project_root: resolve_path(project_root),
+            project_path: project_root.join(POLICY_FILE),
+            user_path: None,
+        }
+    }
+
+    /// Returns the most restrictive action of all rules matching the call, together with the file
+    /// it came from, or `None` if no rule matches. Rules from both files are weighed equally, so a
+    /// project file can tighten but never loosen what the user-level file denies.
+    pub fn evaluate(&self, tool_name: &str, arguments: &serde_json::Value) -> Option<(PolicyAction, PolicyScope)> {
+        self.rules
+            .iter()
+            .filter(|compiled| compiled.matches(tool_name, arguments, &self.project_root))
+            .max_by_key(|compiled| compiled.rule.action)
+            .map(|compiled| (compiled.rule.action, compiled.scope))
+    }
+
+    /// Builds the rule that "always allow" should save for a call: a command prefix for tools
+    /// that run commands, the exact paths of the call for tools that take paths, and a tool-wide
+    /// rule otherwise.
+    pub fn rule_for(&self, tool_name: &str, permission_id: &str, arguments: &serde_json::Value) -> PolicyRule {
+        let command = arguments
+            .get("command")
+            .and_then(|c| c.as_str())
+            .map(|_| permission_id.to_string());
+        let paths: Vec<ArgumentPath> = argument_paths(arguments)
+            .iter()
+            .map(|path| ArgumentPath::new(path, &self.project_root))
+            .collect();
+        // relative and absolute patterns cannot be mixed, so one path outside the project makes
+        // them all absolute
+        let outside = paths.iter().any(|path| path.relative.is_none());
+        let mut patterns: Vec<String> = paths
+            .into_iter()
+            .map(|path| match (outside, path.relative) {
+                (false, Some(relative)) => relative,
+                _ => path.resolved.to_string_lossy().trim_start_matches('/').to_string(),
+            })
+            .map(|path| globset::escape(&path).replace(',', "[,]"))
+            .collect();
+        patterns.sort();
+        patterns.dedup();
+        let prefix = if outside { "/" } else { "" };
+        let path = match patterns.len() {
+            0 => None,
+            1 => Some(format!("{}{}", prefix, patterns[0])),
+            _ => Some(format!("{}{{{}}}", prefix, patterns.join(","))),
+        };
+        PolicyRule {
+            tool: tool_name.to_string(),
+            action: PolicyAction::Allow,
+            path,
+            command,
+            command_regex: None,
+        }
+    }
+
+    /// Appends a rule to the policy file of the given scope and applies it immediately.
+    /// Returns the path of the file that was written.
+    pub fn persist(&mut self, rule: PolicyRule, scope: PolicyScope) -> Result<PathBuf> {
+        let path = match scope {
+            PolicyScope::Project => self.project_path.clone(),
+            PolicyScope::User => self.user_path.clone().ok_or_else(|| ConfigError::Missing {
+                reason: "permission policy: home directory".to_string()
+            })?,
+        };
+        let compiled = Self::compile(rule.clone(), scope).map_err(|reason| ConfigError::Invalid {
+            reason: format!("permission policy {}: {}", path.display(), reason)
+        })?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let entry = toml::to_string(&PolicyFile { rules: vec![rule] }).map_err(|e| ConfigError::Invalid {
+            reason: format!("permission policy {}: {}", path.display(), e)
+        })?;
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        write!(file, "\n{}", entry)?;
+
+        self.rules.push(compiled);
+        Ok(path)
+    }
+
+    fn compile(rule: PolicyRule, scope: PolicyScope) -> std::result::Result<CompiledRule, String> {
+        let path = rule
+            .path
+            .as_deref()
+            .map(|pattern| {
+                GlobBuilder::new(pattern)
+                    .literal_separator(true)
+                    .build()
+                    .map(|glob| glob.compile_matcher())
+                    .map_err(|e| format!("rule for {}: {}", rule.tool, e))
+            })
+            .transpose()?;
+        let command_regex = rule
+            .command_regex
+            .as_deref()
+            .map(|pattern| Regex::new(pattern).map_err(|e| format!("rule for {}: {}", rule.tool, e)))
+            .transpose()?;
+        Ok(CompiledRule { rule, scope, path, command_regex })
+    }
+}
+
+impl CompiledRule {
+    fn matches(&self, tool_name: &str, arguments: &serde_json::Value, project_root: &Path) -> bool {
+        if self.rule.tool != "*" && self.rule.tool != tool_name {
+            return false;
+        }
+
+        if let Some(glob) = &self.path {
+            let paths: Vec<ArgumentPath> = argument_paths(arguments)
+                .iter()
+                .map(|path| ArgumentPath::new(path, project_root))
+                .collect();
+            if paths.is_empty() {
+                return false;
+            }
+            let absolute = Path::new(glob.glob().glob()).is_absolute();
+            // an allow rule must cover every path of the call, and never one that climbs with `..`;
+            // restricting rules apply if any path matches
+            let matched = match self.rule.action {
+                PolicyAction::Allow => paths.iter().all(|path| {
+                    !path.climbs
+                        && match (absolute, &path.relative) {
+                            (true, _) => glob.is_match(&path.resolved),
+                            (false, Some(relative)) => glob.is_match(relative),
+                            (false, None) => false,
+                        }
+                }),
+                PolicyAction::Ask | PolicyAction::Deny => paths.iter().any(|path| match (absolute, &path.relative) {
+                    (false, Some(relative)) => glob.is_match(relative),
+                    // paths outside the project are still matched by relative patterns such as `**/*.pem`
+                    _ => glob.is_match(&path.resolved),
+                }),
+            };
+            if !matched {
+                return false;
+            }
+        }
+
+        if self.rule.command.is_some() || self.command_regex.is_some() {
+            let Some(command) = arguments.get("command").and_then(|c| c.as_str()) else {
+                return false;
+            };
+            // compound commands are matched per simple command, so that an allowed prefix does
+            // not also cover whatever follows `&&` or `|`
+            let segments = shell::commands(command);
+            let matched = match self.rule.action {
+                PolicyAction::Allow => segments.iter().all(|segment| self.matches_command(segment)),
+                PolicyAction::Ask | PolicyAction::Deny => {
+                    segments.iter().any(|segment| self.matches_command(segment))
+                        || (self.rule.command.is_none()
+                            && self.command_regex.as_ref().is_some_and(|regex| regex.is_match(command.trim())))
+                }
+            };
+            if !matched {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn matches_command(&self, segment: &ShellCommand) -> bool {
+        if let Some(prefix) = &self.rule.command {
+            let prefix = prefix.trim();
+            let on_boundary = segment
+                .text
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace));
+            if !on_boundary && segment.permission_id != prefix {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.command_regex
+            && !regex.is_match(&segment.text)
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// Collects the file paths a tool call refers to, from either a `path` or a `paths` argument.
+fn argument_paths(arguments: &serde_json::Value) -> Vec<String> {
+    let mut paths = Vec::new();
+    if let Some(path) = arguments.get("path").and_then(|p| p.as_str()) {
+        paths.push(path.to_string());
+    }
+    if let Some(list) = arguments.get("paths").and_then(|p| p.as_array()) {
+        paths.extend(list.iter().filter_map(|p| p.as_str()).map(str::to_string));
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{TempProject, temp_project};
+
+    fn project() -> TempProject {
+        temp_project(&[("src/main.rs", "fn main() {}"), ("secrets/.keep", "")])
+    }
+
+    fn policy(root: &Path, rules: &str) -> PermissionPolicy {
+        let mut policy = PermissionPolicy::empty(root);
+        let file: PolicyFile = toml::from_str(rules).unwrap();
+        for rule in file.rules {
+            policy.rules.push(PermissionPolicy::compile(rule, PolicyScope::Project).unwrap());
+        }
+        policy
+    }
+
+    fn action(policy: &PermissionPolicy, tool: &str, arguments: serde_json::Value) -> Option<PolicyAction> {
+        policy.evaluate(tool, &arguments).map(|(action, _)| action)
+    }
+
+    #[test]
+    fn paths_are_matched_relative_to_the_project_root() {
+        let root = project();
+        let policy = policy(&root, r#"
+            [[rules]]
+            tool = "read_files"
+            action = "allow"
+            path = "src/**"
+        "#);
+
+        assert_eq!(action(&policy, "read_files", json!({ "paths": ["src/main.rs"] })), Some(PolicyAction::Allow));
+        assert_eq!(action(&policy, "read_files", json!({ "paths": ["./src/main.rs"] })), Some(PolicyAction::Allow));
+        let absolute = root.join("src/main.rs");
+        assert_eq!(action(&policy, "read_files", json!({ "paths": [absolute] })), Some(PolicyAction::Allow));
+        // every path must be covered
+        assert_eq!(action(&policy, "read_files", json!({ "paths": ["src/main.rs", "Cargo.toml"] })), None);
+        assert_eq!(action(&policy, "write_file", json!({ "path": "src/main.rs" })), None);
+    }
+
+    #[test]
+    fn paths_that_climb_never_satisfy_an_allow_rule() {
+        let root = project();
+        let policy = policy(&root, r#"
+            [[rules]]
+            tool = "*"
+            action = "allow"
+            path = "**"
+        "#);
+
+        assert_eq!(action(&policy, "read_files", json!({ "path": "src/main.rs" })), Some(PolicyAction::Allow));
+        assert_eq!(action(&policy, "read_files", json!({ "path": "src/../src/main.rs" })), None);
+        assert_eq!(action(&policy, "read_files", json!({ "path": "../../etc/passwd" })), None);
+        assert_eq!(action(&policy, "read_files", json!({ "path": "/etc/passwd" })), None);
+    }
+
+    #[test]
+    fn symlinks_leaving_the_project_do_not_satisfy_an_allow_rule() {
+        let root = project();
+        std::os::unix::fs::symlink("/etc", root.join("src/etc")).unwrap();
+        let policy = policy(&root, r#"
+            [[rules]]
+            tool = "read_files"
+            action = "allow"
+            path = "src/**"
+        "#);
+
+        assert_eq!(action(&policy, "read_files", json!({ "path": "src/etc/passwd" })), None);
+    }
+
+    #[test]
+    fn deny_rules_catch_every_spelling_of_a_path() {
+        let root = project();
+        let policy = policy(&root, r#"
+            [[rules]]
+            tool = "*"
+            action = "allow"
+            path = "**"
+
+            [[rules]]
+            tool = "*"
+            action = "deny"
+            path = "secrets/**"
+
+            [[rules]]
+            tool = "*"
+            action = "deny"
+            path = "**/*.pem"
+        "#);
+
+        let denied = [
+            json!("secrets/token"),
+            json!(root.join("secrets/token")),
+            json!("src/../secrets/token"),
+            json!("./secrets/token"),
+            json!("/home/someone/server.pem"),
+        ];
+        for path in denied {
+            assert_eq!(action(&policy, "write_file", json!({ "path": path })), Some(PolicyAction::Deny), "{}", path);
+        }
+        // one denied path is enough
+        assert_eq!(action(&policy, "read_files", json!({ "paths": ["src/main.rs", "secrets/token"] })), Some(PolicyAction::Deny));
+        assert_eq!(action(&policy, "read_files", json!({ "paths": ["src/main.rs"] })), Some(PolicyAction::Allow));
+    }
+
+    #[test]
+    fn absolute_patterns_match_absolute_paths() {
+        let root = project();
+        let policy = policy(&root, r#"
+            [[rules]]
+            tool = "read_files"
+            action = "allow"
+            path = "/usr/share/doc/**"
+        "#);
+
+        assert_eq!(action(&policy, "read_files", json!({ "path": "/usr/share/doc/README" })), Some(PolicyAction::Allow));
+        assert_eq!(action(&policy, "read_files", json!({ "path": "/usr/share/doc/../../../etc/passwd" })), None);
+    }
+
+    #[test]
+    fn command_rules_apply_to_each_part_of_a_compound_command() {
+        let root = project();
+        let policy = policy(&root, r#"
+            [[rules]]
+            tool = "exec_command"
+            action = "allow"
+            command = "cargo test"
+
+            [[rules]]
+            tool = "exec_command"
+            action = "deny"
+            command_regex = "rm\\s+-rf"
+        "#);
+
+        assert_eq!(action(&policy, "exec_command", json!({ "command": "cargo test --workspace" })), Some(PolicyAction::Allow));
+        assert_eq!(action(&policy, "exec_command", json!({ "command": "cargo testing" })), None);
+        assert_eq!(action(&policy, "exec_command", json!({ "command": "cargo test && git push" })), None);
+        assert_eq!(action(&policy, "exec_command", json!({ "command": "cargo test; rm -rf /" })), Some(PolicyAction::Deny));
+    }
+
+    #[test]
+    fn command_rules_do_not_cover_redefined_or_reconfigured_programs() {
+        let root = project();
+        let policy = policy(&root, r#"
+            [[rules]]
+            tool = "exec_command"
+            action = "allow"
+            command = "ls"
+
+            [[rules]]
+            tool = "exec_command"
+            action = "allow"
+            command = "git log"
+        "#);
+
+        assert_eq!(action(&policy, "exec_command", json!({ "command": "ls -la" })), Some(PolicyAction::Allow));
+        assert_eq!(action(&policy, "exec_command", json!({ "command": "function ls { rm -rf ~; }; ls" })), None);
+        assert_eq!(action(&policy, "exec_command", json!({ "command": "ls() { rm -rf ~; }; ls" })), None);
+        assert_eq!(action(&policy, "exec_command", json!({ "command": "git log --oneline" })), Some(PolicyAction::Allow));
+        assert_eq!(action(&policy, "exec_command", json!({ "command": "git -c core.pager='rm -rf ~' log" })), None);
+    }
+
+    #[test]
+    fn saved_rules_cover_only_the_paths_of_the_call() {
+        let root = project();
+        let policy = policy(&root, "");
+
+        let single = policy.rule_for("write_file", "write_file", &json!({ "path": "./src/main.rs" }));
+        assert_eq!(single.path.as_deref(), Some("src/main.rs"));
+        assert_eq!(single.command, None);
+
+        let arguments = json!({ "paths": ["src/main.rs", "notes, draft [1].md", "/etc/hosts"] });
+        let several = policy.rule_for("read_files", "read_files", &arguments);
+        let mut saved = PermissionPolicy::empty(&root);
+        saved.rules.push(PermissionPolicy::compile(several, PolicyScope::Project).unwrap());
+        assert_eq!(action(&saved, "read_files", arguments), Some(PolicyAction::Allow));
+        assert_eq!(action(&saved, "read_files", json!({ "paths": ["src/main.rs"] })), Some(PolicyAction::Allow));
+        assert_eq!(action(&saved, "read_files", json!({ "paths": ["src/lib.rs"] })), None);
+        assert_eq!(action(&saved, "read_files", json!({ "paths": ["notes"] })), None);
+
+        let outside = policy.rule_for("read_files", "outside the project: /etc/hosts", &json!({ "path": "/etc/hosts" }));
+        assert_eq!(outside.path.as_deref(), Some("/etc/hosts"));
+
+        let command = policy.rule_for("exec_command", "cargo", &json!({ "command": "cargo build" }));
+        assert_eq!(command.command.as_deref(), Some("cargo"));
+        assert_eq!(command.path, None);
+    }
+}
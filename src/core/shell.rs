@@ -0,0 +1,816 @@
+//! A small, conservative shell parser used to work out which programs a command would run, so
+//! that each of them can be approved on its own. It understands quoting, escapes, pipelines,
+//! lists (`;`, `&&`, `||`, `&`), subshells, command, process and arithmetic substitution,
+//! redirections and heredocs. It does not evaluate anything; when in doubt it reports more commands, not fewer.
+
+/// Nesting depth (substitutions, `sh -c`, `eval`, ...) beyond which a script is rejected.
+const MAX_DEPTH: usize = 8;
+
+/// Words that introduce or close compound commands and are not programs themselves.
+const KEYWORDS: &[&str] = &[
+    "if", "then", "else", "elif", "fi", "do", "done", "while", "until", "esac", "!", "{", "}",
+];
+
+/// Words that introduce a compound command header that runs nothing by itself.
+const HEADERS: &[&str] = &["for", "case", "select"];
+
+/// Programs that run another command given as their arguments, with the options of each that
+/// take a value and the number of positional arguments that precede the wrapped command.
+const WRAPPERS: &[(&str, &[&str], usize)] = &[
+    ("sudo", &["-u", "-g", "-C", "-h", "-p", "-U"], 0),
+    ("doas", &["-u", "-C"], 0),
+    ("env", &["-u", "-C"], 0),
+    ("nohup", &[], 0),
+    ("time", &["-f", "-o"], 0),
+    ("nice", &["-n"], 0),
+    ("ionice", &["-c", "-n", "-p"], 0),
+    ("timeout", &["-s", "-k"], 1),
+    ("exec", &["-a"], 0),
+    ("command", &[], 0),
+    ("builtin", &[], 0),
+    ("xargs", &["-a", "-d", "-E", "-I", "-L", "-n", "-P", "-s"], 0),
+    ("watch", &["-n", "-d"], 0),
+    ("stdbuf", &["-i", "-o", "-e"], 0),
+];
+
+/// Programs whose first positional argument selects what they do, with their options that take
+/// a value. `git push` is identified as such rather than as `git`.
+const SUBCOMMAND_PROGRAMS: &[(&str, &[&str])] = &[
+    ("git", &["-C", "-c", "--git-dir", "--work-tree", "--namespace"]),
+    ("cargo", &["-C", "-Z", "--config", "--color"]),
+    ("rustup", &["--toolchain"]),
+    ("npm", &["--prefix", "-w", "--workspace"]),
+    ("npx", &["-p", "--package"]),
+    ("pnpm", &["-C", "--dir", "-F", "--filter"]),
+    ("yarn", &["--cwd"]),
+    ("bun", &["--cwd"]),
+    ("deno", &[]),
+    ("go", &["-C"]),
+    ("pip", &[]),
+    ("pip3", &[]),
+    ("uv", &["--directory", "--project"]),
+    ("poetry", &["-C", "--directory"]),
+    ("docker", &["-H", "--host", "-c", "--context", "--config", "-l", "--log-level"]),
+    ("podman", &["--connection", "--url"]),
+    ("kubectl", &["-n", "--namespace", "--context", "--kubeconfig", "--cluster", "--user"]),
+    ("helm", &["-n", "--namespace", "--kube-context", "--kubeconfig"]),
+    ("terraform", &["-chdir"]),
+    ("gh", &["-R", "--repo"]),
+    ("make", &["-C", "-f", "--file", "--directory", "-j", "-l", "-o", "-W"]),
+    ("brew", &[]),
+    ("apt", &["-o", "-t"]),
+    ("apt-get", &["-o", "-t"]),
+    ("dnf", &[]),
+    ("systemctl", &["-H", "--host", "-M"]),
+    ("dotnet", &[]),
+    ("mvn", &["-f", "-P"]),
+    ("gradle", &["-p"]),
+];
+
+/// Options that load configuration, or make a program load it from elsewhere, and so can make it
+/// run arbitrary commands, as `git -c core.pager=...` or `cargo --config target.*.runner=...` do.
+/// A command that uses one is identified by its full text, so that approving `git log` does not
+/// approve it.
+const CONFIG_OPTIONS: &[&str] = &["-c", "-C", "-Z", "--config", "--config-env", "--exec-path", "--git-dir"];
+
+/// Shells whose `-c` argument is a script of its own.
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "fish"];
+
+/// A simple command that a script would invoke.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShellCommand {
+    /// The command's words joined by spaces, without leading variable assignments and redirections.
+    pub text: String,
+    /// The program, followed by its subcommand for tools such as `git` or `cargo`.
+    pub permission_id: String,
+}
+
+/// Returns every simple command a script would invoke. Commands nested in substitutions,
+/// `sh -c`, `eval`, `find -exec` and wrappers such as `sudo` are listed separately. Scripts that
+/// cannot be parsed are returned as a single command identified by their full text, so that
+/// they can only be approved as a whole.
+pub fn commands(script: &str) -> Vec<ShellCommand> {
+    match parse(script) {
+        Ok(commands) if !commands.is_empty() => commands
+            .iter()
+            .map(|words| ShellCommand { text: words.join(" "), permission_id: command_id(words) })
+            .collect(),
+        _ => vec![ShellCommand {
+            text: script.trim().to_string(),
+            permission_id: script.trim().to_string(),
+        }],
+    }
+}
+
+/// Returns the permission ids of every command a script would invoke, in order and without
+/// duplicates.
+pub fn permission_ids(script: &str) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+    for command in commands(script) {
+        if !ids.contains(&command.permission_id) {
+            ids.push(command.permission_id);
+        }
+    }
+    ids
+}
+
+fn parse(script: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut commands = Vec::new();
+    parse_into(script, &mut commands, 0)?;
+    Ok(commands)
+}
+
+fn parse_into(script: &str, commands: &mut Vec<Vec<String>>, depth: usize) -> Result<(), String> {
+    if depth > MAX_DEPTH {
+        return Err("command is nested too deeply".to_string());
+    }
+
+    let (tokens, nested) = Lexer::new(script).run()?;
+    for segment in tokens.split(|token| *token == Token::Separator) {
+        let words: Vec<String> = segment
+            .iter()
+            .filter_map(|token| match token {
+                Token::Word(word) => Some(word.clone()),
+                Token::Separator => None,
+            })
+            .collect();
+        expand(words, commands, depth)?;
+    }
+    for script in nested {
+        parse_into(&script, commands, depth + 1)?;
+    }
+    Ok(())
+}
+
+/// Records a simple command and any command it runs in turn.
+fn expand(words: Vec<String>, commands: &mut Vec<Vec<String>>, depth: usize) -> Result<(), String> {
+    let words = normalize(words);
+    if words.is_empty() {
+        return Ok(());
+    }
+    commands.push(words.clone());
+
+    let name = program_name(&words[0]);
+    let args = &words[1..];
+    if name == "env"
+        && let Some(args) = split_string(args)?
+    {
+        // `env -S 'prog args'` runs the command line held in its argument
+        let start = skip_options(&args, &["-u", "-C"], true);
+        if start < args.len() {
+            expand(args[start..].to_vec(), commands, depth)?;
+        }
+    } else if name == "coproc" {
+        // `coproc NAME { ...; }` names the coprocess; `coproc NAME (...)` leaves only the name here,
+        // which is reported as a command of its own rather than guessed to be one
+        let start = match args.iter().position(|arg| arg == "{") {
+            Some(brace) if brace <= 1 => brace + 1,
+            _ => 0,
+        };
+        if start < args.len() {
+            expand(args[start..].to_vec(), commands, depth)?;
+        }
+    } else if let Some((_, value_options, positional)) = WRAPPERS.iter().find(|(wrapper, ..)| *wrapper == name) {
+        let start = skip_options(args, value_options, name == "env") + positional;
+        if start < args.len() {
+            expand(args[start..].to_vec(), commands, depth)?;
+        }
+    } else if SHELLS.contains(&name) {
+        let script_index = args
+            .iter()
+            .position(|arg| arg.starts_with('-') && !arg.starts_with("--") && arg.contains('c'))
+            .map(|index| index + 1);
+        if let Some(script) = script_index.and_then(|index| args.get(index)) {
+            parse_into(script, commands, depth + 1)?;
+        }
+    } else if name == "eval" {
+        parse_into(&args.join(" "), commands, depth + 1)?;
+    } else if name == "trap" {
+        // `trap SCRIPT SIGNAL...` runs the script later; `trap - SIGNAL...` resets the signals
+        let start = skip_options(args, &[], false);
+        if !args[..start].iter().any(|arg| arg == "-")
+            && let Some(script) = args.get(start)
+        {
+            parse_into(script, commands, depth + 1)?;
+        }
+    } else if name == "find" {
+        let mut rest = args;
+        while let Some(start) = rest
+            .iter()
+            .position(|arg| matches!(arg.as_str(), "-exec" | "-execdir" | "-ok" | "-okdir"))
+        {
+            rest = &rest[start + 1..];
+            let end = rest.iter().position(|arg| arg == ";" || arg == "+").unwrap_or(rest.len());
+            expand(rest[..end].to_vec(), commands, depth)?;
+            rest = &rest[end..];
+        }
+    }
+    Ok(())
+}
+
+/// Strips compound command keywords, function definition headers and leading variable
+/// assignments from a simple command.
+fn normalize(words: Vec<String>) -> Vec<String> {
+    let mut start = 0;
+    loop {
+        while start < words.len() && KEYWORDS.contains(&words[start].as_str()) {
+            start += 1;
+        }
+        // `function NAME { ...` runs nothing itself, but the body that follows the name does
+        if words.get(start).is_some_and(|word| word == "function") {
+            start = (start + 2).min(words.len());
+        } else {
+            break;
+        }
+    }
+    if words.get(start).is_some_and(|word| HEADERS.contains(&word.as_str())) {
+        return Vec::new();
+    }
+    while start < words.len() && is_assignment(&words[start]) {
+        start += 1;
+    }
+    words[start..].to_vec()
+}
+
+fn command_id(words: &[String]) -> String {
+    let program = &words[0];
+    let name = program_name(program);
+    let args = &words[1..];
+
+    if let Some((_, value_options)) = SUBCOMMAND_PROGRAMS.iter().find(|(p, _)| *p == name) {
+        let index = skip_options(args, value_options, false);
+        // cargo also accepts its global options after the subcommand
+        let global = if name == "cargo" { args } else { &args[..index] };
+        if global.iter().any(|arg| is_config_option(arg)) {
+            return words.join(" ");
+        }
+        if let Some(subcommand) = args.get(index) {
+            return format!("{} {}", program, subcommand);
+        }
+    } else if name == "find" && args.iter().any(|arg| arg == "-delete") {
+        return format!("{} -delete", program);
+    }
+    program.clone()
+}
+
+/// Replaces the `-S`/`--split-string` option of `env` and its value by the words of the value.
+/// Returns `None` if the option is not given.
+fn split_string(args: &[String]) -> Result<Option<Vec<String>>, String> {
+    for (index, arg) in args.iter().enumerate() {
+        let (value, end) = match arg.as_str() {
+            "-S" | "--split-string" => match args.get(index + 1) {
+                Some(value) => (value.as_str(), index + 2),
+                None => return Ok(None),
+            },
+            _ => match arg.strip_prefix("--split-string=").or_else(|| arg.strip_prefix("-S")) {
+                Some(value) => (value, index + 1),
+                None if arg.starts_with('-') => continue,
+                None => return Ok(None),
+            },
+        };
+        let (tokens, _) = Lexer::new(value).run()?;
+        let mut split = args[..index].to_vec();
+        split.extend(tokens.into_iter().filter_map(|token| match token {
+            Token::Word(word) => Some(word),
+            Token::Separator => None,
+        }));
+        split.extend_from_slice(&args[end..]);
+        return Ok(Some(split));
+    }
+    Ok(None)
+}
+
+/// Returns the index of the first argument that is not an option (or an option's value).
+fn skip_options(args: &[String], value_options: &[&str], skip_assignments: bool) -> usize {
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        if arg == "--" {
+            return index + 1;
+        }
+        if value_options.contains(&arg.as_str()) {
+            index += 2;
+        } else if arg.starts_with('-') || arg.starts_with('+') || (skip_assignments && is_assignment(arg)) {
+            index += 1;
+        } else {
+            break;
+        }
+    }
+    index
+}
+
+/// Whether an argument is one of the `CONFIG_OPTIONS`, on its own or with its value attached.
+fn is_config_option(arg: &str) -> bool {
+    CONFIG_OPTIONS.iter().any(|option| match arg.strip_prefix(option) {
+        Some(rest) => rest.is_empty() || rest.starts_with('=') || !option.starts_with("--"),
+        None => false,
+    })
+}
+
+/// The name used to look up a program's behaviour; the id itself keeps the path as written, so
+/// that approving `git` does not approve `./git`.
+fn program_name(program: &str) -> &str {
+    program.rsplit('/').next().unwrap_or(program)
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Separator,
+}
+
+/// What the next word of the script refers to when it follows a redirection operator.
+enum Redirect {
+    Target,
+    Heredoc { strip_tabs: bool },
+}
+
+/// A heredoc whose body follows the line that started it.
+struct Heredoc {
+    delimiter: String,
+    strip_tabs: bool,
+    /// Whether the delimiter was quoted, which leaves the body unexpanded.
+    quoted: bool,
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    tokens: Vec<Token>,
+    word: String,
+    in_word: bool,
+    /// Whether part of the current word was quoted or escaped.
+    quoted: bool,
+    redirect: Option<Redirect>,
+    heredocs: Vec<Heredoc>,
+    nested: Vec<String>,
+}
+
+impl Lexer {
+    fn new(script: &str) -> Self {
+        Self {
+            chars: script.chars().collect(),
+            pos: 0,
+            tokens: Vec::new(),
+            word: String::new(),
+            in_word: false,
+            quoted: false,
+            redirect: None,
+            heredocs: Vec::new(),
+            nested: Vec::new(),
+        }
+    }
+
+    /// Splits the script into words and separators, and collects the scripts of command and
+    /// process substitutions, which run as commands of their own.
+    fn run(mut self) -> Result<(Vec<Token>, Vec<String>), String> {
+        while let Some(c) = self.peek(0) {
+            match c {
+                ' ' | '\t' | '\r' => {
+                    self.finish_word();
+                    self.pos += 1;
+                }
+                '\n' => {
+                    self.separator();
+                    self.pos += 1;
+                    self.skip_heredocs()?;
+                }
+                '#' if !self.in_word => {
+                    while self.peek(0).is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                '\\' => {
+                    match self.peek(1) {
+                        Some('\n') => {}
+                        Some(escaped) => {
+                            self.push(escaped);
+                            self.quoted = true;
+                        }
+                        None => self.push('\\'),
+                    }
+                    self.pos += 2;
+                }
+                '\'' => {
+                    self.in_word = true;
+                    self.quoted = true;
+                    self.pos += 1;
+                    loop {
+                        match self.peek(0) {
+                            None => return Err("unterminated single quote".to_string()),
+                            Some('\'') => break,
+                            Some(c) => self.push(c),
+                        }
+                        self.pos += 1;
+                    }
+                    self.pos += 1;
+                }
+                '"' => self.double_quoted()?,
+                '`' => self.backticks()?,
+                '$' if self.peek(1) == Some('(') => self.substitution(self.pos + 1)?,
+                '<' | '>' if self.peek(1) == Some('(') => self.substitution(self.pos + 1)?,
+                '<' | '>' => self.redirection(),
+                '&' if self.peek(1) == Some('>') => {
+                    self.finish_word();
+                    self.pos += 2;
+                    if self.peek(0) == Some('>') {
+                        self.pos += 1;
+                    }
+                    self.redirect = Some(Redirect::Target);
+                }
+                ';' | '&' | '|' | '(' | ')' => {
+                    self.separator();
+                    self.pos += 1;
+                }
+                c => {
+                    self.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        self.finish_word();
+        Ok((self.tokens, self.nested))
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn push(&mut self, c: char) {
+        self.word.push(c);
+        self.in_word = true;
+    }
+
+    fn finish_word(&mut self) {
+        if !self.in_word {
+            return;
+        }
+        let word = std::mem::take(&mut self.word);
+        let quoted = std::mem::take(&mut self.quoted);
+        self.in_word = false;
+        match self.redirect.take() {
+            Some(Redirect::Target) => {}
+            Some(Redirect::Heredoc { strip_tabs }) => self.heredocs.push(Heredoc { delimiter: word, strip_tabs, quoted }),
+            None => self.tokens.push(Token::Word(word)),
+        }
+    }
+
+    fn separator(&mut self) {
+        self.finish_word();
+        self.tokens.push(Token::Separator);
+    }
+
+    fn double_quoted(&mut self) -> Result<(), String> {
+        self.in_word = true;
+        self.quoted = true;
+        self.pos += 1;
+        loop {
+            match self.peek(0) {
+                None => return Err("unterminated double quote".to_string()),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                Some('\\') => {
+                    match self.peek(1) {
+                        Some('\n') => {}
+                        Some(escaped @ ('$' | '`' | '"' | '\\')) => self.push(escaped),
+                        _ => {
+                            self.push('\\');
+                            self.pos += 1;
+                            continue;
+                        }
+                    }
+                    self.pos += 2;
+                }
+                Some('$') if self.peek(1) == Some('(') => self.substitution(self.pos + 1)?,
+                Some('`') => self.backticks()?,
+                Some(c) => {
+                    self.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Handles `$(...)`, `$((...))`, `<(...)` and `>(...)`, where `open` is the position of the
+    /// parenthesis. The substitution is kept verbatim in the current word and its script, or the
+    /// substitutions within an arithmetic expression, are parsed later.
+    fn substitution(&mut self, open: usize) -> Result<(), String> {
+        let arithmetic = self.chars.get(open + 1) == Some(&'(');
+        let close = self.matching_paren(open)?;
+        if arithmetic {
+            self.scan_substitutions(open + 2, close)?;
+        } else {
+            self.nested.push(self.chars[open + 1..close].iter().collect());
+        }
+        let text: String = self.chars[self.pos..=close].iter().collect();
+        self.word.push_str(&text);
+        self.in_word = true;
+        self.pos = close + 1;
+        Ok(())
+    }
+
+    fn backticks(&mut self) -> Result<(), String> {
+        let start = self.pos;
+        let (inner, close) = self.backquoted(start)?;
+        self.nested.push(inner);
+        let text: String = self.chars[start..=close].iter().collect();
+        self.word.push_str(&text);
+        self.in_word = true;
+        self.pos = close + 1;
+        Ok(())
+    }
+
+    /// Returns the script of the backquoted substitution starting at `start`, with its escapes
+    /// removed, and the position of the closing backquote.
+    fn backquoted(&self, start: usize) -> Result<(String, usize), String> {
+        let mut inner = String::new();
+        let mut pos = start + 1;
+        loop {
+            match self.chars.get(pos) {
+                None => return Err("unterminated backquote".to_string()),
+                Some('`') => return Ok((inner, pos)),
+                Some('\\') if matches!(self.chars.get(pos + 1), Some('`' | '\\' | '$')) => {
+                    inner.push(self.chars[pos + 1]);
+                    pos += 2;
+                }
+                Some(c) => {
+                    inner.push(*c);
+                    pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Collects the command substitutions in text that is expanded but not split into commands,
+    /// such as an arithmetic expression or an unquoted heredoc body, between `start` and `end`.
+    fn scan_substitutions(&mut self, start: usize, end: usize) -> Result<(), String> {
+        let mut pos = start;
+        while pos < end {
+            match self.chars[pos] {
+                '\\' => pos += 2,
+                // the body of a nested arithmetic expression is scanned as part of this text
+                '$' if self.chars.get(pos + 1) == Some(&'(') && self.chars.get(pos + 2) == Some(&'(') => pos += 3,
+                '$' if self.chars.get(pos + 1) == Some(&'(') => {
+                    let close = self.matching_paren(pos + 1)?;
+                    if close >= end {
+                        return Err("unterminated substitution".to_string());
+                    }
+                    self.nested.push(self.chars[pos + 2..close].iter().collect());
+                    pos = close + 1;
+                }
+                '`' => {
+                    let (inner, close) = self.backquoted(pos)?;
+                    if close >= end {
+                        return Err("unterminated backquote".to_string());
+                    }
+                    self.nested.push(inner);
+                    pos = close + 1;
+                }
+                _ => pos += 1,
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the parenthesis closing the one at `open`, skipping over quoted text.
+    fn matching_paren(&self, open: usize) -> Result<usize, String> {
+        let mut depth = 0;
+        let mut pos = open;
+        while let Some(c) = self.chars.get(pos) {
+            match c {
+                '\\' => pos += 1,
+                '\'' => {
+                    pos += 1;
+                    while self.chars.get(pos).is_some_and(|c| *c != '\'') {
+                        pos += 1;
+                    }
+                }
+                '"' => {
+                    pos += 1;
+                    while let Some(c) = self.chars.get(pos) {
+                        match c {
+                            '\\' => pos += 1,
+                            '"' => break,
+                            _ => {}
+                        }
+                        pos += 1;
+                    }
+                }
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(pos);
+                    }
+                }
+                _ => {}
+            }
+            pos += 1;
+        }
+        Err("unterminated substitution".to_string())
+    }
+
+    fn redirection(&mut self) {
+        // a file descriptor number directly before the operator belongs to it, as in `2>&1`
+        if self.in_word && self.word.chars().all(|c| c.is_ascii_digit()) {
+            self.word.clear();
+            self.in_word = false;
+        } else {
+            self.finish_word();
+        }
+
+        let operator = self.chars[self.pos];
+        self.pos += 1;
+        if operator == '<' && self.peek(0) == Some('<') {
+            self.pos += 1;
+            if self.peek(0) == Some('<') {
+                self.pos += 1;
+                self.redirect = Some(Redirect::Target);
+            } else {
+                let strip_tabs = self.peek(0) == Some('-');
+                if strip_tabs {
+                    self.pos += 1;
+                }
+                self.redirect = Some(Redirect::Heredoc { strip_tabs });
+            }
+            return;
+        }
+        if matches!(self.peek(0), Some('>' | '&' | '|')) {
+            self.pos += 1;
+        }
+        self.redirect = Some(Redirect::Target);
+    }
+
+    /// Skips the bodies of heredocs started on the line that just ended; they are data, but the
+    /// command substitutions in the body of a heredoc with an unquoted delimiter still run.
+    fn skip_heredocs(&mut self) -> Result<(), String> {
+        for heredoc in std::mem::take(&mut self.heredocs) {
+            let body_start = self.pos;
+            let mut body_end = self.chars.len();
+            while self.pos < self.chars.len() {
+                let end = self.chars[self.pos..]
+                    .iter()
+                    .position(|c| *c == '\n')
+                    .map_or(self.chars.len(), |offset| self.pos + offset);
+                let line: String = self.chars[self.pos..end].iter().collect();
+                let line_start = self.pos;
+                self.pos = (end + 1).min(self.chars.len());
+                let line = if heredoc.strip_tabs { line.trim_start_matches('\t') } else { line.as_str() };
+                if line == heredoc.delimiter {
+                    body_end = line_start;
+                    break;
+                }
+            }
+            if !heredoc.quoted {
+                self.scan_substitutions(body_start, body_end)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(script: &str) -> Vec<String> {
+        permission_ids(script)
+    }
+
+    fn texts(script: &str) -> Vec<String> {
+        commands(script).into_iter().map(|command| command.text).collect()
+    }
+
+    #[test]
+    fn splits_pipelines_and_lists() {
+        assert_eq!(ids("ls -la | grep foo"), ["ls", "grep"]);
+        assert_eq!(ids("cargo build && cargo test || echo failed; make"), ["cargo build", "cargo test", "echo", "make"]);
+        assert_eq!(ids("sleep 1 & wait"), ["sleep", "wait"]);
+        assert_eq!(ids("(cd src && rm -rf target)"), ["cd", "rm"]);
+        assert_eq!(ids("if true; then echo yes; fi"), ["true", "echo"]);
+        assert_eq!(ids("for f in *.rs; do wc -l $f; done"), ["wc"]);
+    }
+
+    #[test]
+    fn respects_quoting_and_escapes() {
+        assert_eq!(texts("echo 'a && b' \"c | d\""), ["echo a && b c | d"]);
+        assert_eq!(texts(r"echo a\;b"), ["echo a;b"]);
+        assert_eq!(ids("echo '$(rm -rf ~)'"), ["echo"]);
+        assert_eq!(texts("echo \"don't\" # && rm -rf ~"), ["echo don't"]);
+        assert_eq!(ids("echo 'unterminated"), ["echo 'unterminated"]);
+    }
+
+    #[test]
+    fn identifies_subcommands_but_not_lookalikes() {
+        assert_eq!(ids("git --no-pager push origin main"), ["git push"]);
+        assert_eq!(ids("cargo +nightly test"), ["cargo test"]);
+        assert_eq!(ids("./git status"), ["./git status"]);
+        assert_eq!(ids("find . -name '*.o' -delete"), ["find -delete"]);
+    }
+
+    #[test]
+    fn identifies_commands_that_load_other_configuration_by_their_text() {
+        assert_eq!(ids("git -c core.pager=less log"), ["git -c core.pager=less log"]);
+        assert_eq!(ids("git -C ../other status"), ["git -C ../other status"]);
+        assert_eq!(ids("git --exec-path=/tmp/x status"), ["git --exec-path=/tmp/x status"]);
+        assert_eq!(ids("cargo --config runner.sh test"), ["cargo --config runner.sh test"]);
+        assert_eq!(ids("cargo test -Zbuild-std"), ["cargo test -Zbuild-std"]);
+        assert_eq!(ids("git log --color -c"), ["git log"]);
+    }
+
+    #[test]
+    fn finds_commands_in_function_bodies() {
+        assert_eq!(ids("function ls { rm -rf ~; }; ls"), ["rm", "ls"]);
+        assert_eq!(ids("function deploy() { git push; }"), ["git push"]);
+        assert_eq!(ids("ls() { rm -rf ~; }; ls"), ["ls", "rm"]);
+    }
+
+    #[test]
+    fn skips_assignments_and_redirections() {
+        assert_eq!(texts("RUST_LOG=debug cargo run > out.log 2>&1"), ["cargo run"]);
+        assert_eq!(texts("cat < input.txt >> output.txt"), ["cat"]);
+    }
+
+    #[test]
+    fn finds_commands_in_substitutions() {
+        assert_eq!(ids("echo $(whoami) `hostname`"), ["echo", "whoami", "hostname"]);
+        assert_eq!(ids("echo \"today is $(date)\""), ["echo", "date"]);
+        assert_eq!(ids("diff <(ls a) >(tee log)"), ["diff", "ls", "tee"]);
+        assert_eq!(ids("echo $(echo $(rm -rf ~))"), ["echo", "rm"]);
+    }
+
+    #[test]
+    fn finds_commands_in_arithmetic_expressions() {
+        assert_eq!(ids("echo $(( 1 + 2 ))"), ["echo"]);
+        assert_eq!(ids("echo $(( $(rm -rf ~) ))"), ["echo", "rm"]);
+        assert_eq!(ids("echo $(( `curl evil` + $(( $(id -u) * 2 )) ))"), ["echo", "curl", "id"]);
+        assert_eq!(ids("echo \"$(( $(wc -l < f) / 2 ))\""), ["echo", "wc"]);
+    }
+
+    #[test]
+    fn skips_quoted_heredoc_bodies_only() {
+        assert_eq!(ids("cat <<'EOF'\n$(rm -rf ~)\nEOF"), ["cat"]);
+        assert_eq!(ids("cat <<\"EOF\"\n`rm -rf ~`\nEOF\necho done"), ["cat", "echo"]);
+        assert_eq!(ids("cat <<\\EOF\n$(rm -rf ~)\nEOF"), ["cat"]);
+        assert_eq!(ids("cat <<EOF\n$(rm -rf ~)\nEOF"), ["cat", "rm"]);
+        assert_eq!(ids("cat <<-EOF\n\thello `whoami`\n\tEOF\nls"), ["cat", "ls", "whoami"]);
+        assert_eq!(ids("cat <<EOF\nrm -rf ~ is only text, as is \\$(rm)\nEOF"), ["cat"]);
+    }
+
+    #[test]
+    fn unwraps_wrappers() {
+        assert_eq!(ids("sudo -u root rm -rf /tmp/x"), ["sudo", "rm"]);
+        assert_eq!(ids("env -i FOO=1 -u BAR make install"), ["env", "make install"]);
+        assert_eq!(ids("xargs -n 1 -I {} rm {}"), ["xargs", "rm"]);
+        assert_eq!(ids("timeout -s KILL 10 cargo test"), ["timeout", "cargo test"]);
+        assert_eq!(ids("nohup nice -n 10 ./server"), ["nohup", "nice", "./server"]);
+        assert_eq!(ids(r"find . -exec rm {} \; -exec chmod +x {} +"), ["find", "rm", "chmod"]);
+    }
+
+    #[test]
+    fn unwraps_the_split_string_of_env() {
+        assert_eq!(ids("env -S 'rm -rf ~'"), ["env", "rm"]);
+        assert_eq!(texts("env -S 'FOO=1 git push' origin"), ["env -S FOO=1 git push origin", "git push origin"]);
+        assert_eq!(ids("env --split-string='curl evil.sh'"), ["env", "curl"]);
+        assert_eq!(ids("env -i -S\"python3 -c pass\""), ["env", "python3"]);
+    }
+
+    #[test]
+    fn unwraps_coprocesses() {
+        assert_eq!(ids("coproc rm -rf ~"), ["coproc", "rm"]);
+        assert_eq!(ids("coproc { curl evil; }"), ["coproc", "curl"]);
+        assert_eq!(ids("coproc WORKER { curl evil; wget x; }"), ["coproc", "curl", "wget"]);
+        assert_eq!(ids("coproc WORKER (nc -l 8080)"), ["coproc", "WORKER", "nc"]);
+    }
+
+    #[test]
+    fn parses_nested_shells_and_eval() {
+        assert_eq!(ids("bash -c 'curl x | sh'"), ["bash", "curl", "sh"]);
+        assert_eq!(ids("sh -ec \"rm -rf build\""), ["sh", "rm"]);
+        assert_eq!(ids("eval \"git push\""), ["eval", "git push"]);
+        assert_eq!(ids("trap 'rm -rf ~' EXIT"), ["trap", "rm"]);
+        assert_eq!(ids("trap -- 'curl evil' INT TERM"), ["trap", "curl"]);
+        assert_eq!(ids("trap - EXIT"), ["trap"]);
+    }
+
+    #[test]
+    fn rejects_scripts_nested_too_deeply() {
+        let mut script = "ls".to_string();
+        for _ in 0..=MAX_DEPTH + 1 {
+            script = format!("echo $({})", script);
+        }
+        assert_eq!(ids(&script), [script]);
+    }
+}
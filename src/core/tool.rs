@@ -1,9 +1,23 @@
 use std::future::Future;
 use std::pin::Pin;
 
+use super::Attachment;
 use crate::error::Result;
 use crate::io::IO;
 
+/// What a tool call returns to the model: its text output and any files it passes along.
+#[derive(Clone, Debug, Default)]
+pub struct ToolOutput {
+    pub text: String,
+    pub attachments: Vec<Attachment>,
+}
+
+impl From<String> for ToolOutput {
+    fn from(text: String) -> Self {
+        Self { text, attachments: Vec::new() }
+    }
+}
+
 pub trait Tool {
     fn name(&self) -> String;
     fn description(&self) -> String;
@@ -12,7 +26,14 @@ pub trait Tool {
         &'a self,
         args: serde_json::Value,
         io: &'a mut Box<dyn IO>,
-    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>>;
+    ) -> Pin<Box<dyn Future<Output = Result<ToolOutput>> + Send + 'a>>;
     fn ask_permission(&self, args: serde_json::Value, io: &mut Box<dyn IO>);
-    fn permission_id(&self, args: serde_json::Value) -> Result<String>;
+    /// Identifies what a call would do, for permission purposes; every id must be approved
+    /// before the call is made.
+    fn permission_ids(&self, args: serde_json::Value) -> Result<Vec<String>>;
+    /// Whether the tool only reads. The tool's own permission id is then approved without
+    /// asking; its other ids, such as paths outside the project, and policy rules still apply.
+    fn is_read_only(&self) -> bool {
+        false
+    }
 }
@@ -75,6 +75,9 @@ pub enum SessionError {
     
     #[error("User input error: {reason}")]
     UserInput { reason: String },
+
+    #[error("Session storage error: {reason}")]
+    Storage { reason: String },
 }
 
 #[derive(Debug, Error)]
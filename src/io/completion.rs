@@ -0,0 +1,51 @@
+use rustyline::{
+    Context, Helper,
+    completion::{Completer, Pair},
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+};
+
+/// Completes slash command names at the start of the prompt.
+#[derive(Default)]
+pub struct CommandCompleter {
+    commands: Vec<String>,
+}
+
+impl CommandCompleter {
+    pub fn set_commands(&mut self, commands: Vec<String>) {
+        self.commands = commands;
+    }
+}
+
+impl Completer for CommandCompleter {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let typed = &line[..pos];
+        if !typed.starts_with('/') || typed.contains(char::is_whitespace) {
+            return Ok((pos, Vec::new()));
+        }
+
+        let candidates = self
+            .commands
+            .iter()
+            .filter(|command| command.starts_with(typed))
+            .map(|command| Pair {
+                display: command.clone(),
+                replacement: format!("{} ", command),
+            })
+            .collect();
+        Ok((0, candidates))
+    }
+}
+
+impl Hinter for CommandCompleter {
+    type Hint = String;
+}
+
+impl Highlighter for CommandCompleter {}
+
+impl Validator for CommandCompleter {}
+
+impl Helper for CommandCompleter {}
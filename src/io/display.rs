@@ -1,3 +1,5 @@
+use std::io::Write;
+
 pub struct Display {
     terminal_width: usize,
 }
@@ -29,6 +31,24 @@ impl Display {
         println!("└─");
     }
 
+    pub fn begin_stream(&self, title: &str) {
+        print!("\n┌─ {}\n│ ", title);
+        Self::flush();
+    }
+
+    pub fn stream_text(&self, text: &str) {
+        print!("{}", text.replace('\n', "\n│ "));
+        Self::flush();
+    }
+
+    pub fn end_stream(&self) {
+        println!("\n└─");
+    }
+
+    fn flush() {
+        let _ = std::io::stdout().flush();
+    }
+
     fn wrap_text(&self, text: &str, width: usize) -> Vec<String> {
         let mut wrapped_lines = Vec::new();
 
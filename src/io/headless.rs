@@ -0,0 +1,42 @@
+use crate::{error::Result, session::RunSummary};
+
+use super::IO;
+
+/// IO for non-interactive runs: progress goes to stderr so stdout only carries the final answer,
+/// and there is never anyone to answer a prompt.
+pub struct HeadlessIO;
+
+impl HeadlessIO {
+    pub fn new() -> Self {
+        HeadlessIO
+    }
+}
+
+impl IO for HeadlessIO {
+    fn show_message(&self, title: &str, text: &str) {
+        eprintln!("[{}]\n{}", title, text);
+    }
+
+    fn show_snippet(&self, title: &str, _text: &str) {
+        eprintln!("[{}]", title);
+    }
+
+    fn begin_stream(&self, _title: &str) {}
+
+    fn stream_text(&self, _text: &str) {}
+
+    fn end_stream(&self) {}
+
+    fn get_user_input(&mut self, _prompt: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn show_summary(&self, summary: &RunSummary) {
+        if let Some(text) = &summary.final_text {
+            println!("{}", text);
+        }
+        if let Some(error) = &summary.error {
+            eprintln!("Error: {}", error);
+        }
+    }
+}
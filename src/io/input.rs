@@ -2,14 +2,18 @@ use rustyline::{Editor, error::ReadlineError};
 use std::path::PathBuf;
 use crate::error::{Result, SessionError};
 
+use super::completion::CommandCompleter;
+
 pub struct InputHandler {
-    editor: Editor<(), rustyline::history::FileHistory>,
+    editor: Editor<CommandCompleter, rustyline::history::FileHistory>,
 }
 
 impl InputHandler {
     pub fn new() -> Result<Self> {
         let mut editor = Editor::new().map_err(|e| SessionError::Processing { reason: format!("Failed to create editor: {}", e) })?;
 
+        editor.set_helper(Some(CommandCompleter::default()));
+
         let history_file = Self::get_history_file();
         if history_file.exists() {
             let _ = editor.load_history(&history_file);
@@ -35,6 +39,12 @@ impl InputHandler {
         }
     }
 
+    pub fn set_command_completions(&mut self, commands: Vec<String>) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.set_commands(commands);
+        }
+    }
+
     pub fn save_history(&mut self) -> Result<()> {
         let history_file = Self::get_history_file();
         if let Some(parent) = history_file.parent() {
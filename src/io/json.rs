@@ -0,0 +1,218 @@
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::{core::Message, error::Result, session::RunSummary};
+
+use super::{HeadlessIO, IO, OutputFormat};
+
+/// A machine-readable event written to stdout.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event<'a> {
+    User {
+        text: &'a str,
+    },
+    Model {
+        text: &'a str,
+    },
+    ToolCall {
+        id: &'a Option<String>,
+        tool_name: &'a str,
+        arguments: &'a serde_json::Value,
+    },
+    ToolResult {
+        id: &'a Option<String>,
+        output: &'a str,
+        /// Paths of the files passed along with the output; their content is left out.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        attachments: Vec<&'a str>,
+        is_error: bool,
+    },
+    Result {
+        #[serde(flatten)]
+        summary: &'a RunSummary,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        messages: Option<&'a [serde_json::Value]>,
+    },
+}
+
+impl<'a> From<&'a Message> for Event<'a> {
+    fn from(message: &'a Message) -> Self {
+        match message {
+            Message::User(text) => Event::User { text },
+            Message::Model(text) => Event::Model { text },
+            Message::ToolCall { id, tool_name, arguments } => Event::ToolCall { id, tool_name, arguments },
+            Message::ToolResult { id, output, attachments, is_error } => Event::ToolResult {
+                id,
+                output,
+                attachments: attachments.iter().map(|attachment| attachment.path.as_str()).collect(),
+                is_error: *is_error,
+            },
+        }
+    }
+}
+
+/// IO for automation: conversation messages are emitted as JSON on stdout, while progress
+/// and permission notices go to stderr like `HeadlessIO`.
+pub struct JsonIO {
+    inner: HeadlessIO,
+    streaming: bool,
+    messages: Mutex<Vec<serde_json::Value>>,
+}
+
+impl JsonIO {
+    pub fn new(format: OutputFormat) -> Self {
+        Self {
+            inner: HeadlessIO::new(),
+            streaming: format == OutputFormat::StreamJson,
+            messages: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn emit(event: &Event) {
+        match serde_json::to_string(event) {
+            Ok(line) => println!("{}", line),
+            Err(e) => eprintln!("Failed to serialize output event: {}", e),
+        }
+    }
+}
+
+impl IO for JsonIO {
+    fn show_message(&self, title: &str, text: &str) {
+        self.inner.show_message(title, text);
+    }
+
+    fn show_snippet(&self, title: &str, text: &str) {
+        self.inner.show_snippet(title, text);
+    }
+
+    fn begin_stream(&self, _title: &str) {}
+
+    fn stream_text(&self, _text: &str) {}
+
+    fn end_stream(&self) {}
+
+    fn get_user_input(&mut self, prompt: &str) -> Result<Option<String>> {
+        self.inner.get_user_input(prompt)
+    }
+
+    fn record_message(&self, message: &Message) {
+        let event = Event::from(message);
+        if self.streaming {
+            Self::emit(&event);
+        } else if let Ok(value) = serde_json::to_value(&event) {
+            self.messages.lock().unwrap().push(value);
+        }
+    }
+
+    fn show_summary(&self, summary: &RunSummary) {
+        let messages = self.messages.lock().unwrap();
+        Self::emit(&Event::Result {
+            summary,
+            messages: if self.streaming { None } else { Some(&messages) },
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{core::{Attachment, Usage}, session::TurnOutcome};
+
+    fn event_json(message: &Message) -> serde_json::Value {
+        serde_json::to_value(Event::from(message)).unwrap()
+    }
+
+    #[test]
+    fn messages_become_tagged_events() {
+        assert_eq!(event_json(&Message::User("hi".to_string())), json!({ "type": "user", "text": "hi" }));
+        assert_eq!(event_json(&Message::Model("hello".to_string())), json!({ "type": "model", "text": "hello" }));
+        assert_eq!(
+            event_json(&Message::ToolCall {
+                id: Some("call_1".to_string()),
+                tool_name: "read_files".to_string(),
+                arguments: json!({ "paths": ["a.rs"] }),
+            }),
+            json!({ "type": "tool_call", "id": "call_1", "tool_name": "read_files", "arguments": { "paths": ["a.rs"] } })
+        );
+    }
+
+    #[test]
+    fn tool_results_list_attachment_paths_only() {
+        let result = Message::ToolResult {
+            id: Some("call_1".to_string()),
+            output: "ok".to_string(),
+            attachments: Vec::new(),
+            is_error: false,
+        };
+        assert_eq!(
+            event_json(&result),
+            json!({ "type": "tool_result", "id": "call_1", "output": "ok", "is_error": false })
+        );
+
+        let result = Message::ToolResult {
+            id: None,
+            output: "1 image".to_string(),
+            attachments: vec![Attachment {
+                path: "logo.png".to_string(),
+                media_type: "image/png".to_string(),
+                data: "iVBORw0KGgo=".to_string(),
+            }],
+            is_error: false,
+        };
+        assert_eq!(
+            event_json(&result),
+            json!({ "type": "tool_result", "id": null, "output": "1 image", "attachments": ["logo.png"], "is_error": false })
+        );
+    }
+
+    #[test]
+    fn result_event_flattens_the_summary() {
+        let summary = RunSummary {
+            session_id: "abc".to_string(),
+            outcome: TurnOutcome::Denied,
+            turns: 2,
+            final_text: None,
+            usage: Usage { input_tokens: 10, output_tokens: 5 },
+            cost_usd: None,
+            error: None,
+        };
+        let streamed = serde_json::to_value(Event::Result { summary: &summary, messages: None }).unwrap();
+        assert_eq!(
+            streamed,
+            json!({
+                "type": "result",
+                "session_id": "abc",
+                "outcome": "denied",
+                "turns": 2,
+                "final_text": null,
+                "usage": { "input_tokens": 10, "output_tokens": 5 },
+                "cost_usd": null,
+            })
+        );
+
+        let messages = [json!({ "type": "user", "text": "hi" })];
+        let buffered = serde_json::to_value(Event::Result { summary: &summary, messages: Some(&messages) }).unwrap();
+        assert_eq!(buffered["messages"], json!([{ "type": "user", "text": "hi" }]));
+    }
+
+    #[test]
+    fn result_event_reports_a_failed_turn() {
+        let summary = RunSummary {
+            session_id: "abc".to_string(),
+            outcome: TurnOutcome::Failed,
+            turns: 1,
+            final_text: None,
+            usage: Usage::default(),
+            cost_usd: None,
+            error: Some("Model API error: overloaded".to_string()),
+        };
+        let event = serde_json::to_value(Event::Result { summary: &summary, messages: None }).unwrap();
+        assert_eq!(event["type"], "result");
+        assert_eq!(event["outcome"], "failed");
+        assert_eq!(event["error"], "Model API error: overloaded");
+    }
+}
@@ -1,16 +1,45 @@
+pub mod completion;
 pub mod display;
+pub mod headless;
 pub mod input;
+pub mod json;
 
 use std::sync::{Arc, Mutex};
-use crate::error::Result;
+use clap::ValueEnum;
+use crate::{core::Message, error::Result, session::RunSummary};
 
 pub use display::Display;
+pub use headless::HeadlessIO;
+pub use json::JsonIO;
 pub use input::InputHandler;
 
 pub trait IO: Send + Sync {
     fn show_message(&self, title: &str, text: &str);
     fn show_snippet(&self, title: &str, text: &str);
+    fn begin_stream(&self, title: &str);
+    fn stream_text(&self, text: &str);
+    fn end_stream(&self);
     fn get_user_input(&mut self, prompt: &str) -> Result<Option<String>>;
+
+    /// Registers the slash commands that can be tab-completed at the prompt.
+    fn set_command_completions(&mut self, _commands: Vec<String>) {}
+
+    /// Called for every message as it is added to the conversation.
+    fn record_message(&self, _message: &Message) {}
+
+    /// Called once a non-interactive run has finished.
+    fn show_summary(&self, _summary: &RunSummary) {}
+}
+
+/// Output format for non-interactive runs.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Print the final response as plain text
+    Text,
+    /// Print a single JSON document with every message and a summary once the run has finished
+    Json,
+    /// Print one JSON event per line as messages are produced, followed by a summary event
+    StreamJson,
 }
 
 pub struct TerminalIO {
@@ -44,8 +73,24 @@ impl IO for TerminalIO {
             .print_message_box(title, &formatted_output);
     }
 
+    fn begin_stream(&self, title: &str) {
+        self.display.begin_stream(title);
+    }
+
+    fn stream_text(&self, text: &str) {
+        self.display.stream_text(text);
+    }
+
+    fn end_stream(&self) {
+        self.display.end_stream();
+    }
+
     fn get_user_input(&mut self, prompt: &str) -> Result<Option<String>> {
         let mut input = self.input.lock().unwrap();
         input.read_line(prompt)
     }
+
+    fn set_command_completions(&mut self, commands: Vec<String>) {
+        self.input.lock().unwrap().set_command_completions(commands);
+    }
 }
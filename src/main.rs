@@ -1,19 +1,28 @@
 use crate::{
     context::{Context, ModelConfig, SessionConfig},
-    error::Result,
-    io::{IO, TerminalIO},
+    core::UnattendedPolicy,
+    error::{ConfigError, Result, SessionError},
+    io::{HeadlessIO, IO, JsonIO, OutputFormat, TerminalIO},
     provider::{Provider, session_factory::SessionFactory},
-    tools::ToolRegistry,
+    session::{SessionStore, StoredSession, TurnOutcome},
+    tools::{SandboxConfig, ToolRegistry},
 };
 use clap::Parser;
-use std::{env, path::PathBuf};
+use std::{
+    env,
+    io::{IsTerminal, Read},
+    path::PathBuf,
+};
 
+mod commands;
 mod context;
 mod core;
 mod error;
 mod io;
 mod provider;
 mod session;
+#[cfg(test)]
+mod testing;
 mod tools;
 
 #[derive(Parser)]
@@ -22,7 +31,7 @@ mod tools;
 #[command(version)]
 struct Args {
     /// Provider to use (anthropic or open-ai or ollama)
-    #[arg(short, long, value_enum, default_value_t = Provider::Anthropic)]
+    #[arg(long, value_enum, default_value_t = Provider::Anthropic)]
     provider: Provider,
 
     /// Model to use (provider-specific, e.g. claude-sonnet-4-20250514 for Anthropic, gpt-4o for OpenAI)
@@ -46,6 +55,50 @@ struct Args {
     /// Custom configuration file path (when provided, only this file will be read instead of the default priority order)
     #[arg(short, long)]
     config: Option<PathBuf>,
+
+    /// Number of tokens the conversation may occupy before older turns are summarised (defaults depend on the provider)
+    #[arg(long)]
+    context_budget: Option<u32>,
+
+    /// Resume a saved session by id; without an id, choose from the sessions saved for the current directory
+    #[arg(long, num_args = 0..=1, default_missing_value = "", conflicts_with = "continue_session")]
+    resume: Option<String>,
+
+    /// Continue the most recent session saved for the current directory
+    #[arg(long = "continue")]
+    continue_session: bool,
+
+    /// Run a single prompt non-interactively, print the final response to stdout and exit. Piped stdin is appended to the prompt.
+    #[arg(short, long, value_name = "PROMPT")]
+    print: Option<String>,
+
+    /// How tool calls that would require a permission prompt are handled in --print mode
+    #[arg(long, value_enum, default_value_t = UnattendedPolicy::Deny, requires = "print")]
+    permissions: UnattendedPolicy,
+
+    /// Run shell commands in a bubblewrap sandbox that can only write to the working directory and a private /tmp
+    #[arg(long)]
+    sandbox: bool,
+
+    /// Additional path that sandboxed commands may write to (can be repeated)
+    #[arg(long, value_name = "PATH", requires = "sandbox")]
+    sandbox_writable: Vec<PathBuf>,
+
+    /// Disable network access for sandboxed commands
+    #[arg(long, requires = "sandbox")]
+    no_network: bool,
+
+    /// Give the model a persistent shell that keeps its working directory and environment between commands
+    #[arg(long)]
+    persistent_shell: bool,
+
+    /// Additional directory that the file tools may access without asking (can be repeated)
+    #[arg(long, value_name = "PATH")]
+    allow_dir: Vec<PathBuf>,
+
+    /// Output format for --print mode
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, requires = "print")]
+    output_format: OutputFormat,
 }
 
 impl Args {
@@ -66,18 +119,119 @@ impl Args {
     }
 }
 
+/// Exit status used when the turn failed, as for any other error.
+const EXIT_FAILED: i32 = 1;
+
+/// Exit status used when a tool call was denied before the model could finish.
+const EXIT_DENIED: i32 = 2;
+
+/// Builds the prompt for --print mode, appending anything piped in on stdin.
+fn build_print_prompt(prompt: String) -> Result<String> {
+    let stdin = std::io::stdin();
+    let mut piped = String::new();
+    if !stdin.is_terminal() {
+        stdin.lock().read_to_string(&mut piped)?;
+    }
+    combine_print_prompt(prompt, piped)
+}
+
+/// Appends piped input to the prompt given on the command line; either may be empty, but not both.
+fn combine_print_prompt(prompt: String, piped: String) -> Result<String> {
+    let prompt = match (prompt.trim().is_empty(), piped.trim().is_empty()) {
+        (true, true) => {
+            return Err(ConfigError::Missing {
+                reason: "--print requires a prompt or input on stdin".to_string()
+            }.into());
+        }
+        (false, true) => prompt,
+        (true, false) => piped,
+        (false, false) => format!("{}\n\n{}", prompt, piped),
+    };
+    Ok(prompt)
+}
+
+/// Determines which saved session, if any, should be restored for this run.
+fn select_stored_session(
+    resume: Option<&str>,
+    continue_session: bool,
+    cwd: &str,
+    io: &mut Box<dyn IO>,
+) -> Result<Option<StoredSession>> {
+    let store = SessionStore::new();
+
+    if let Some(id) = resume.filter(|id| !id.is_empty()) {
+        return Ok(Some(store.load(id)?));
+    }
+    if resume.is_none() && !continue_session {
+        return Ok(None);
+    }
+
+    let sessions = store.list_for(cwd)?;
+    if sessions.is_empty() {
+        io.show_message("No saved sessions", &format!("There are no saved sessions for {}; starting a new one.", cwd));
+        return Ok(None);
+    }
+    if continue_session {
+        return Ok(sessions.into_iter().next());
+    }
+
+    let mut sessions: Vec<_> = sessions.into_iter().take(10).collect();
+    let listing = sessions
+        .iter()
+        .enumerate()
+        .map(|(i, s)| format!("{}. {} - {} ({} messages)", i + 1, s.id, s.title(), s.messages.len()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    io.show_message("Saved sessions", &listing);
+
+    let choice = io.get_user_input("Session to resume (leave empty to start a new one) > ")?;
+    match choice.as_deref() {
+        None | Some("") => Ok(None),
+        Some(choice) => match choice.parse::<usize>() {
+            Ok(n) if (1..=sessions.len()).contains(&n) => Ok(Some(sessions.swap_remove(n - 1))),
+            _ => Err(SessionError::UserInput {
+                reason: format!("invalid session choice: {}", choice)
+            }.into()),
+        },
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    core::interrupt::install();
     let base_url = args.resolved_base_url();
+    let print_prompt = args.print.map(build_print_prompt).transpose()?;
 
     // Create context with all configuration
-    let model_config = ModelConfig::new(args.provider.clone(), args.model, args.yolo, base_url)?;
+    let mut model_config = ModelConfig::new(args.provider.clone(), args.model, args.yolo, base_url)?;
+    if let Some(context_budget) = args.context_budget {
+        model_config = model_config.with_context_budget(context_budget);
+    }
+    if print_prompt.is_some() {
+        model_config = model_config.with_unattended_policy(args.permissions);
+    }
+    if args.sandbox {
+        model_config = model_config.with_sandbox(SandboxConfig {
+            allow_network: !args.no_network,
+            writable_paths: args.sandbox_writable,
+        });
+    }
+    if !args.allow_dir.is_empty() {
+        model_config = model_config.with_allowed_roots(args.allow_dir);
+    }
+    if args.persistent_shell {
+        model_config = model_config.with_persistent_shell();
+    }
     let session_config = SessionConfig::from_env(args.config)?;
     let context = Context::new(model_config, session_config);
 
-    let tools = ToolRegistry::with_default_tools().into_tools();
-    let mut io: Box<dyn IO> = Box::new(TerminalIO::new()?);
+    let tools = ToolRegistry::with_default_tools(&context)?.into_tools();
+    let mut io: Box<dyn IO> = match (&print_prompt, args.output_format) {
+        (None, _) => Box::new(TerminalIO::new()?),
+        (Some(_), OutputFormat::Text) => Box::new(HeadlessIO::new()),
+        (Some(_), format) => Box::new(JsonIO::new(format)),
+    };
 
     if context.model_config.yolo_mode {
         io.show_message(
@@ -86,27 +240,100 @@ async fn main() -> Result<()> {
         );
     }
 
-    io.show_message(
-        &format!(
-            "Deputy ready! Using provider: {}, model: {}{}{}",
-            context.model_config.provider,
-            context.model_config.model_name,
-            if context.model_config.yolo_mode {
-                " (YOLO MODE)"
-            } else {
-                ""
-            },
-            if let Some(ref url) = context.model_config.base_url_override {
-                format!(", base url: {}", url)
-            } else {
-                String::from("")
-            }
-        ),
-        "Type your commands below. Type 'exit' to exit (or use Ctrl-C).",
-    );
+    if print_prompt.is_none() {
+        io.show_message(
+            &format!(
+                "Deputy ready! Using provider: {}, model: {}{}{}{}",
+                context.model_config.provider,
+                context.model_config.model_name,
+                if context.model_config.yolo_mode {
+                    " (YOLO MODE)"
+                } else {
+                    ""
+                },
+                if context.model_config.sandbox.is_some() {
+                    " (sandboxed)"
+                } else {
+                    ""
+                },
+                if let Some(ref url) = context.model_config.base_url_override {
+                    format!(", base url: {}", url)
+                } else {
+                    String::from("")
+                }
+            ),
+            "Type your commands below. Type /help to see the available commands, or 'exit' to exit (or use Ctrl-C).",
+        );
+    }
+
+    let stored_session = select_stored_session(
+        args.resume.as_deref(),
+        args.continue_session,
+        context.session_config.cwd(),
+        &mut io,
+    )?;
 
     let mut session = SessionFactory::build_session(tools, &mut io, &context)?;
+    if let Some(stored) = stored_session {
+        session.resume(stored);
+    }
+
+    if let Some(prompt) = print_prompt {
+        let summary = session.run_once(prompt).await?;
+        drop(session);
+        io.show_summary(&summary);
+        match summary.outcome {
+            TurnOutcome::Completed => return Ok(()),
+            TurnOutcome::Denied => std::process::exit(EXIT_DENIED),
+            TurnOutcome::Failed => std::process::exit(EXIT_FAILED),
+        }
+    }
+
     session.run().await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn print_prompt_appends_piped_input() {
+        let prompt = combine_print_prompt("explain this".to_string(), "fn main() {}\n".to_string()).unwrap();
+        assert_eq!(prompt, "explain this\n\nfn main() {}\n");
+    }
+
+    #[test]
+    fn print_prompt_uses_whichever_input_is_given() {
+        assert_eq!(combine_print_prompt("hello".to_string(), String::new()).unwrap(), "hello");
+        assert_eq!(combine_print_prompt("  ".to_string(), "piped".to_string()).unwrap(), "piped");
+    }
+
+    #[test]
+    fn print_prompt_requires_some_input() {
+        assert!(combine_print_prompt(" ".to_string(), "\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn p_is_short_for_print() {
+        let args = Args::try_parse_from(["deputy", "-p", "hi", "--provider", "ollama"]).unwrap();
+        assert_eq!(args.print.as_deref(), Some("hi"));
+        assert!(matches!(args.provider, Provider::Ollama));
+    }
+
+    #[test]
+    fn print_mode_denies_by_default() {
+        let args = Args::try_parse_from(["deputy", "--print", "hi"]).unwrap();
+        assert_eq!(args.print.as_deref(), Some("hi"));
+        assert_eq!(args.permissions, UnattendedPolicy::Deny);
+
+        let args = Args::try_parse_from(["deputy", "--print", "hi", "--permissions", "allow"]).unwrap();
+        assert_eq!(args.permissions, UnattendedPolicy::Allow);
+    }
+
+    #[test]
+    fn permissions_flag_requires_print_mode() {
+        assert!(Args::try_parse_from(["deputy", "--permissions", "allow"]).is_err());
+    }
+}
@@ -3,11 +3,15 @@ use std::time::Duration;
 use reqwest::{Response, StatusCode};
 
 use crate::{
-    core::{Message, Model},
+    core::{Message, Model, ModelResponse, Usage},
     error::{ErrorResponse, ModelError, Result},
-    provider::anthropic::types::{
-        ContentBlock, CreateMessageRequest, CreateMessageResponse,
-        Message as AnthropicMessage, Tool,
+    io::IO,
+    provider::{
+        anthropic::types::{
+            ContentBlock, ContentBlockDelta, CreateMessageRequest, DocumentSource, ImageSource,
+            Message as AnthropicMessage, StreamEvent, Tool, ToolResultContent,
+        },
+        sse::{SseDecoder, SseEvent},
     },
 };
 
@@ -89,6 +93,133 @@ impl AnthropicModel {
     }
 }
 
+/// A content block that is still being assembled from stream deltas.
+enum PendingBlock {
+    Text(String),
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+        partial_json: String,
+    },
+}
+
+/// Accumulates the content blocks of a streamed response, rendering text as it arrives.
+#[derive(Default)]
+struct StreamState {
+    blocks: Vec<PendingBlock>,
+    streaming_text: bool,
+    usage: Option<Usage>,
+}
+
+impl StreamState {
+    fn handle(&mut self, event: SseEvent, io: &dyn IO) -> Result<()> {
+        let event: StreamEvent = serde_json::from_str(&event.data).map_err(|e| ModelError::Request {
+            reason: format!("invalid response from anthropic: Failed to parse stream event: {}", e)
+        })?;
+
+        match event {
+            StreamEvent::MessageStart { message } => {
+                self.usage = Some(Usage {
+                    input_tokens: message.usage.input_tokens,
+                    output_tokens: message.usage.output_tokens,
+                });
+            }
+            StreamEvent::ContentBlockStart { content_block, .. } => {
+                let block = match content_block {
+                    ContentBlock::Text { text } => {
+                        if !text.is_empty() {
+                            self.render_text(&text, io);
+                        }
+                        PendingBlock::Text(text)
+                    }
+                    ContentBlock::ToolUse { id, name, input } => PendingBlock::ToolUse {
+                        id,
+                        name,
+                        input,
+                        partial_json: String::new(),
+                    },
+                    _ => {
+                        return Err(ModelError::Request {
+                            reason: "invalid response from anthropic: Only Text and ToolUse blocks are supported".to_string()
+                        }.into());
+                    }
+                };
+                self.blocks.push(block);
+            }
+            StreamEvent::ContentBlockDelta { delta, .. } => match (self.blocks.last_mut(), delta) {
+                (Some(PendingBlock::Text(text)), ContentBlockDelta::TextDelta { text: delta }) => {
+                    text.push_str(&delta);
+                    self.render_text(&delta, io);
+                }
+                (
+                    Some(PendingBlock::ToolUse { partial_json, .. }),
+                    ContentBlockDelta::InputJsonDelta { partial_json: delta },
+                ) => partial_json.push_str(&delta),
+                _ => {}
+            },
+            StreamEvent::ContentBlockStop { .. } if self.streaming_text => {
+                self.streaming_text = false;
+                io.end_stream();
+            }
+            StreamEvent::MessageDelta { usage: Some(delta), .. } => {
+                let usage = self.usage.get_or_insert_with(Usage::default);
+                usage.output_tokens = delta.output_tokens;
+            }
+            StreamEvent::Error { error } => {
+                return Err(ModelError::Request {
+                    reason: format!("provider: anthropic, type: {}, message: {}", error.error_type, error.message)
+                }.into());
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn render_text(&mut self, text: &str, io: &dyn IO) {
+        if !self.streaming_text {
+            self.streaming_text = true;
+            io.begin_stream("Deputy");
+        }
+        io.stream_text(text);
+    }
+
+    fn into_response(self, io: &dyn IO) -> Result<ModelResponse> {
+        if self.streaming_text {
+            io.end_stream();
+        }
+
+        let mut result = vec![];
+        for block in self.blocks {
+            match block {
+                PendingBlock::Text(text) => {
+                    if !text.is_empty() {
+                        result.push(Message::Model(text));
+                    }
+                }
+                PendingBlock::ToolUse { id, name, input, partial_json } => {
+                    let arguments = if partial_json.trim().is_empty() {
+                        input
+                    } else {
+                        serde_json::from_str(&partial_json).map_err(|e| ModelError::Request {
+                            reason: format!("invalid response from anthropic: Failed to parse tool arguments: {}", e)
+                        })?
+                    };
+                    result.push(Message::ToolCall {
+                        id: Some(id),
+                        tool_name: name,
+                        arguments,
+                    });
+                }
+            }
+        }
+        Ok(ModelResponse {
+            messages: result,
+            usage: self.usage,
+        })
+    }
+}
+
 impl From<Message> for AnthropicMessage {
     fn from(message: Message) -> Self {
         match message {
@@ -115,11 +246,28 @@ impl From<Message> for AnthropicMessage {
             Message::ToolResult {
                 id,
                 output,
+                attachments,
                 is_error,
             } => AnthropicMessage {
                 content: vec![ContentBlock::ToolResult {
                     tool_use_id: id.expect("all tool results are expected to have an id"),
-                    content: output,
+                    content: if attachments.is_empty() {
+                        ToolResultContent::Text(output)
+                    } else {
+                        let files = attachments.into_iter().map(|attachment| {
+                            if attachment.is_image() {
+                                ContentBlock::Image {
+                                    source: ImageSource::Base64 { media_type: attachment.media_type, data: attachment.data },
+                                }
+                            } else {
+                                ContentBlock::Document {
+                                    source: DocumentSource::Base64 { media_type: attachment.media_type, data: attachment.data },
+                                    context: None,
+                                }
+                            }
+                        });
+                        ToolResultContent::Blocks(std::iter::once(ContentBlock::Text { text: output }).chain(files).collect())
+                    },
                     is_error: if is_error { Some(true) } else { Some(false) },
                 }],
                 role: crate::provider::anthropic::types::Role::User,
@@ -129,11 +277,20 @@ impl From<Message> for AnthropicMessage {
 }
 
 impl Model for AnthropicModel {
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    fn set_model_name(&mut self, model_name: String) {
+        self.model_name = model_name;
+    }
+
     async fn send_message(
         &self,
         message: Message,
         message_history: Vec<Message>,
-    ) -> Result<Vec<Message>> {
+        io: &dyn IO,
+    ) -> Result<ModelResponse> {
         let all_messages: Vec<AnthropicMessage> = message_history
             .into_iter()
             .chain(std::iter::once(message))
@@ -148,13 +305,13 @@ impl Model for AnthropicModel {
             temperature: None,
             top_p: None,
             top_k: None,
-            stream: None,
+            stream: Some(true),
             stop_sequences: None,
             metadata: None,
         };
 
         let api_url = format!("{}/messages", self.base_url);
-        let result = self.post_with_retry(&api_url, &request).await?;
+        let mut result = self.post_with_retry(&api_url, &request).await?;
         if !result.status().is_success() {
             let status_code = result.status().as_u16();
             
@@ -183,35 +340,115 @@ impl Model for AnthropicModel {
             }.into());
         }
 
-        let body = result
-            .json::<CreateMessageResponse>()
-            .await
-            .map_err(|e| ModelError::Request {
-                reason: format!("invalid response from anthropic: Failed to parse response: {}", e)
-            })?;
+        let mut decoder = SseDecoder::new();
+        let mut stream = StreamState::default();
+        while let Some(chunk) = result.chunk().await.map_err(|e| ModelError::Network {
+            reason: format!("anthropic: {}", e)
+        })? {
+            for event in decoder.push(&chunk) {
+                stream.handle(event, io)?;
+            }
+        }
+        if let Some(event) = decoder.finish() {
+            stream.handle(event, io)?;
+        }
 
-        let mut result = vec![];
-        for block in body.content {
-            match block {
-                ContentBlock::Text { text } => {
-                    let message = Message::Model(text);
-                    result.push(message);
-                }
-                ContentBlock::ToolUse { id, name, input } => {
-                    let message = Message::ToolCall {
-                        id: Some(id),
-                        tool_name: name,
-                        arguments: input,
-                    };
-                    result.push(message);
-                }
-                _ => {
-                    return Err(ModelError::Request {
-                        reason: "invalid response from anthropic: Only Text and ToolUse blocks are supported".to_string()
-                    }.into());
-                }
+        stream.into_response(io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{io::HeadlessIO, testing::serve_once};
+
+    fn handle_all(state: &mut StreamState, events: &[&str]) {
+        for data in events {
+            state.handle(SseEvent { event: None, data: data.to_string() }, &HeadlessIO::new()).unwrap();
+        }
+    }
+
+    #[test]
+    fn assembles_text_and_tool_input_from_deltas() {
+        let mut state = StreamState::default();
+        handle_all(&mut state, &[
+            r#"{"type":"message_start","message":{"id":"m","type":"message","role":"assistant","content":[],"model":"x","stop_reason":null,"stop_sequence":null,"usage":{"input_tokens":12,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hel"}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"lo"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"t1","name":"read_files","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"pa"}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"ths\": [\"a\"]}"}}"#,
+            r#"{"type":"content_block_stop","index":1}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use","stop_sequence":null},"usage":{"output_tokens":7}}"#,
+            r#"{"type":"message_stop"}"#,
+        ]);
+
+        let response = state.into_response(&HeadlessIO::new()).unwrap();
+        assert_eq!(response.usage, Some(Usage { input_tokens: 12, output_tokens: 7 }));
+        assert!(matches!(&response.messages[0], Message::Model(text) if text == "Hello"));
+        match &response.messages[1] {
+            Message::ToolCall { id, tool_name, arguments } => {
+                assert_eq!(id.as_deref(), Some("t1"));
+                assert_eq!(tool_name, "read_files");
+                assert_eq!(arguments, &serde_json::json!({ "paths": ["a"] }));
             }
+            other => panic!("expected a tool call, got {:?}", other),
         }
-        Ok(result)
+    }
+
+    #[test]
+    fn keeps_the_initial_input_when_no_deltas_arrive() {
+        let mut state = StreamState::default();
+        handle_all(&mut state, &[
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"tool_use","id":"t1","name":"list","input":{"path":""}}}"#,
+        ]);
+        let response = state.into_response(&HeadlessIO::new()).unwrap();
+        assert!(matches!(&response.messages[0], Message::ToolCall { arguments, .. } if arguments == &serde_json::json!({ "path": "" })));
+    }
+
+    fn model(base_url: String) -> AnthropicModel {
+        AnthropicModel::new("key".to_string(), "claude-test".to_string(), 1024, None, None, Some(base_url))
+    }
+
+    #[tokio::test]
+    async fn streams_a_response_over_http() {
+        let body = [
+            r#"{"type":"message_start","message":{"id":"m","type":"message","role":"assistant","content":[],"model":"x","stop_reason":null,"stop_sequence":null,"usage":{"input_tokens":3,"output_tokens":1}}}"#,
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi there"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"end_turn","stop_sequence":null},"usage":{"output_tokens":4}}"#,
+            r#"{"type":"message_stop"}"#,
+        ]
+        .map(|data| format!("event: x\ndata: {}\n\n", data))
+        .concat();
+        let (base_url, server) = serve_once(200, "text/event-stream", &body).await;
+
+        let response = model(base_url).send_message(Message::User("hello".to_string()), Vec::new(), &HeadlessIO::new()).await.unwrap();
+
+        assert!(matches!(response.messages.as_slice(), [Message::Model(text)] if text == "Hi there"));
+        assert_eq!(response.usage, Some(Usage { input_tokens: 3, output_tokens: 4 }));
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /v1/messages "), "{}", request);
+        assert!(request.contains(r#""stream":true"#), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn reports_error_statuses() {
+        let body = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let (base_url, _) = serve_once(529, "application/json", body).await;
+
+        let error = model(base_url).send_message(Message::User("hello".to_string()), Vec::new(), &HeadlessIO::new()).await.unwrap_err();
+
+        assert_eq!(error.to_string(), "Model API error: API request failed: provider: anthropic, status: 529, message: Overloaded");
+    }
+
+    #[test]
+    fn reports_stream_errors() {
+        let mut state = StreamState::default();
+        let error = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        assert!(state.handle(SseEvent { event: None, data: error.to_string() }, &HeadlessIO::new()).is_err());
     }
 }
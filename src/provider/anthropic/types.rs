@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::error::ApiError;
+
 /// The role of the message author
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
@@ -30,7 +32,7 @@ pub enum ContentBlock {
     },
     ToolResult {
         tool_use_id: String,
-        content: String,
+        content: ToolResultContent,
         is_error: Option<bool>,
     },
     Thinking {
@@ -76,6 +78,14 @@ pub enum ContentBlock {
     },
 }
 
+/// Content of a tool result: plain text, or text along with images and documents
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ToolResultContent {
+    Text(String),
+    Blocks(Vec<ContentBlock>),
+}
+
 /// Image source for image content blocks
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -163,3 +173,57 @@ pub struct CreateMessageResponse {
     pub usage: Usage,
 }
 
+
+/// Incremental change to a content block while streaming
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlockDelta {
+    TextDelta { text: String },
+    InputJsonDelta { partial_json: String },
+    #[serde(other)]
+    Other,
+}
+
+/// Top-level message changes reported near the end of a stream
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MessageDelta {
+    pub stop_reason: Option<StopReason>,
+    pub stop_sequence: Option<String>,
+}
+
+/// Cumulative usage reported alongside a message delta
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MessageDeltaUsage {
+    pub output_tokens: u32,
+}
+
+/// Server-sent event emitted when creating a message with `stream: true`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    MessageStart {
+        message: CreateMessageResponse,
+    },
+    ContentBlockStart {
+        index: usize,
+        content_block: ContentBlock,
+    },
+    ContentBlockDelta {
+        index: usize,
+        delta: ContentBlockDelta,
+    },
+    ContentBlockStop {
+        index: usize,
+    },
+    MessageDelta {
+        delta: MessageDelta,
+        usage: Option<MessageDeltaUsage>,
+    },
+    MessageStop,
+    Ping,
+    Error {
+        error: ApiError,
+    },
+    #[serde(other)]
+    Other,
+}
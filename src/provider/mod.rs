@@ -1,6 +1,8 @@
 pub mod anthropic;
 pub mod openai;
+pub mod pricing;
 pub mod session_factory;
+pub mod sse;
 
 use clap::ValueEnum;
 use crate::error::{ConfigError, Result};
@@ -31,6 +33,16 @@ impl Provider {
         }
     }
 
+    /// Default number of tokens a conversation may occupy before older turns get compacted.
+    /// Deliberately below each provider's typical context window to leave room for the response.
+    pub fn default_context_budget(&self) -> u32 {
+        match self {
+            Provider::Anthropic => 150_000,
+            Provider::OpenAI => 100_000,
+            Provider::Ollama => 24_000,
+        }
+    }
+
     pub fn validate_configuration(&self) -> Result<()> {
         let required_vars = self.required_env_vars();
         let missing_vars: Vec<_> = required_vars
@@ -1,13 +1,17 @@
-use std::time::Duration;
+use std::{collections::BTreeMap, time::Duration};
 
 use reqwest::{Response, StatusCode};
 
 use crate::{
-    core::{Message, Model},
+    core::{Attachment, Message, Model, ModelResponse, Usage},
     error::{ErrorResponse, ModelError, Result},
-    provider::openai::types::{
-        ChatCompletionRequest, ChatCompletionResponse, Message as OpenAIMessage,
-        Tool, ToolCall, FunctionCall, Role,
+    io::IO,
+    provider::{
+        openai::types::{
+            ChatCompletionChunk, ChatCompletionRequest, ContentPart, FileData, ImageUrl,
+            Message as OpenAIMessage, MessageContent, StreamOptions, Tool, ToolCall, FunctionCall, Role,
+        },
+        sse::{SseDecoder, SseEvent},
     },
 };
 
@@ -84,18 +88,114 @@ impl OpenAIModel {
     }
 }
 
+/// A tool call that is still being assembled from stream deltas.
+#[derive(Default)]
+struct PendingToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Accumulates the content and tool calls of a streamed response, rendering text as it arrives.
+#[derive(Default)]
+struct StreamState {
+    content: String,
+    tool_calls: BTreeMap<usize, PendingToolCall>,
+    streaming_text: bool,
+    usage: Option<Usage>,
+}
+
+impl StreamState {
+    fn handle(&mut self, event: SseEvent, io: &dyn IO) -> Result<()> {
+        if event.data.trim() == "[DONE]" {
+            return Ok(());
+        }
+
+        let chunk: ChatCompletionChunk = serde_json::from_str(&event.data).map_err(|e| ModelError::Request {
+            reason: format!("invalid response from openai: Failed to parse stream chunk: {}", e)
+        })?;
+
+        if let Some(usage) = chunk.usage {
+            self.usage = Some(Usage {
+                input_tokens: usage.prompt_tokens,
+                output_tokens: usage.completion_tokens,
+            });
+        }
+
+        for choice in chunk.choices {
+            if let Some(content) = choice.delta.content.filter(|c| !c.is_empty()) {
+                if !self.streaming_text {
+                    self.streaming_text = true;
+                    io.begin_stream("Deputy");
+                }
+                io.stream_text(&content);
+                self.content.push_str(&content);
+            }
+
+            for tool_call in choice.delta.tool_calls.unwrap_or_default() {
+                let pending = self.tool_calls.entry(tool_call.index).or_default();
+                if let Some(id) = tool_call.id {
+                    pending.id = id;
+                }
+                if let Some(function) = tool_call.function {
+                    if let Some(name) = function.name {
+                        pending.name.push_str(&name);
+                    }
+                    if let Some(arguments) = function.arguments {
+                        pending.arguments.push_str(&arguments);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn into_response(self, io: &dyn IO) -> Result<ModelResponse> {
+        if self.streaming_text {
+            io.end_stream();
+        }
+
+        let mut result = vec![];
+        if !self.content.is_empty() {
+            result.push(Message::Model(self.content));
+        }
+
+        for tool_call in self.tool_calls.into_values() {
+            let arguments: serde_json::Value = if tool_call.arguments.trim().is_empty() {
+                serde_json::json!({})
+            } else {
+                serde_json::from_str(&tool_call.arguments)
+                    .map_err(|e| ModelError::Request {
+                        reason: format!("invalid response from openai: Failed to parse tool arguments: {}", e)
+                    })?
+            };
+
+            result.push(Message::ToolCall {
+                id: Some(tool_call.id),
+                tool_name: tool_call.name,
+                arguments,
+            });
+        }
+
+        Ok(ModelResponse {
+            messages: result,
+            usage: self.usage,
+        })
+    }
+}
+
 impl From<Message> for OpenAIMessage {
     fn from(message: Message) -> Self {
         match message {
             Message::User(text) => OpenAIMessage {
                 role: Role::User,
-                content: Some(text),
+                content: Some(MessageContent::Text(text)),
                 tool_calls: None,
                 tool_call_id: None,
             },
             Message::Model(text) => OpenAIMessage {
                 role: Role::Assistant,
-                content: Some(text),
+                content: Some(MessageContent::Text(text)),
                 tool_calls: None,
                 tool_call_id: None,
             },
@@ -118,7 +218,7 @@ impl From<Message> for OpenAIMessage {
             },
             Message::ToolResult { id, output, .. } => OpenAIMessage {
                 role: Role::Tool,
-                content: Some(output),
+                content: Some(MessageContent::Text(output)),
                 tool_calls: None,
                 tool_call_id: Some(id.expect("all tool results are expected to have an id")),
             },
@@ -126,17 +226,59 @@ impl From<Message> for OpenAIMessage {
     }
 }
 
+/// Tool messages can only hold text, so the files that tool results pass along are sent in a
+/// user message following them.
+fn attachments_message(attachments: Vec<Attachment>) -> OpenAIMessage {
+    let mut parts = vec![ContentPart::Text {
+        text: "Files passed along by the tool results above:".to_string(),
+    }];
+    for attachment in attachments {
+        let url = format!("data:{};base64,{}", attachment.media_type, attachment.data);
+        parts.push(ContentPart::Text { text: attachment.path.clone() });
+        parts.push(if attachment.is_image() {
+            ContentPart::ImageUrl { image_url: ImageUrl { url } }
+        } else {
+            ContentPart::File { file: FileData { filename: attachment.path, file_data: url } }
+        });
+    }
+    OpenAIMessage {
+        role: Role::User,
+        content: Some(MessageContent::Parts(parts)),
+        tool_calls: None,
+        tool_call_id: None,
+    }
+}
+
 impl Model for OpenAIModel {
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    fn set_model_name(&mut self, model_name: String) {
+        self.model_name = model_name;
+    }
+
     async fn send_message(
         &self,
         message: Message,
         message_history: Vec<Message>,
-    ) -> Result<Vec<Message>> {
-        let all_messages: Vec<OpenAIMessage> = message_history
-            .into_iter()
-            .chain(std::iter::once(message))
-            .map(|message| message.into())
-            .collect();
+        io: &dyn IO,
+    ) -> Result<ModelResponse> {
+        let mut all_messages: Vec<OpenAIMessage> = Vec::new();
+        let mut attachments = Vec::new();
+        for mut message in message_history.into_iter().chain(std::iter::once(message)) {
+            // a run of tool results must not be interrupted, so their files follow at its end
+            if !matches!(message, Message::ToolResult { .. }) && !attachments.is_empty() {
+                all_messages.push(attachments_message(std::mem::take(&mut attachments)));
+            }
+            if let Message::ToolResult { attachments: files, .. } = &mut message {
+                attachments.append(files);
+            }
+            all_messages.push(message.into());
+        }
+        if !attachments.is_empty() {
+            all_messages.push(attachments_message(attachments));
+        }
 
         let request = ChatCompletionRequest {
             model: self.model_name.clone(),
@@ -147,11 +289,12 @@ impl Model for OpenAIModel {
             top_p: None,
             max_tokens: self.max_tokens,
             stop: None,
-            stream: None,
+            stream: Some(true),
+            stream_options: Some(StreamOptions { include_usage: true }),
         };
 
         let api_url = format!("{}/chat/completions", self.base_url);
-        let result = self.post_with_retry(&api_url, &request).await?;
+        let mut result = self.post_with_retry(&api_url, &request).await?;
         
         if !result.status().is_success() {
             let status_code = result.status().as_u16();
@@ -180,38 +323,115 @@ impl Model for OpenAIModel {
             }.into());
         }
 
-        let body = result
-            .json::<ChatCompletionResponse>()
-            .await
-            .map_err(|e| ModelError::Request {
-                reason: format!("invalid response from openai: Failed to parse response: {}", e)
-            })?;
-
-        let mut result = vec![];
-        
-        for choice in body.choices {
-            let message = &choice.message;
-            
-            if let Some(content) = &message.content {
-                result.push(Message::Model(content.clone()));
+        let mut decoder = SseDecoder::new();
+        let mut stream = StreamState::default();
+        while let Some(chunk) = result.chunk().await.map_err(|e| ModelError::Network {
+            reason: format!("openai: {}", e)
+        })? {
+            for event in decoder.push(&chunk) {
+                stream.handle(event, io)?;
             }
-            
-            if let Some(tool_calls) = &message.tool_calls {
-                for tool_call in tool_calls {
-                    let arguments: serde_json::Value = serde_json::from_str(&tool_call.function.arguments)
-                        .map_err(|e| ModelError::Request {
-                            reason: format!("invalid response from openai: Failed to parse tool arguments: {}", e)
-                        })?;
-                    
-                    result.push(Message::ToolCall {
-                        id: Some(tool_call.id.clone()),
-                        tool_name: tool_call.function.name.clone(),
-                        arguments,
-                    });
-                }
+        }
+        if let Some(event) = decoder.finish() {
+            stream.handle(event, io)?;
+        }
+
+        stream.into_response(io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{io::HeadlessIO, testing::serve_once};
+
+    fn handle_all(state: &mut StreamState, chunks: &[&str]) {
+        for data in chunks {
+            state.handle(SseEvent { event: None, data: data.to_string() }, &HeadlessIO::new()).unwrap();
+        }
+    }
+
+    #[test]
+    fn assembles_text_and_tool_arguments_from_fragments() {
+        let mut state = StreamState::default();
+        handle_all(&mut state, &[
+            r#"{"choices":[{"index":0,"delta":{"content":"Hel"}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"lo"}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"read_files","arguments":"{\"pa"}}]}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"function":{"arguments":"ths\": [\"a\"]}"}}]}}]}"#,
+            r#"{"choices":[],"usage":{"prompt_tokens":10,"completion_tokens":5,"total_tokens":15}}"#,
+            "[DONE]",
+        ]);
+
+        let response = state.into_response(&HeadlessIO::new()).unwrap();
+        assert_eq!(response.usage, Some(Usage { input_tokens: 10, output_tokens: 5 }));
+        assert!(matches!(&response.messages[0], Message::Model(text) if text == "Hello"));
+        match &response.messages[1] {
+            Message::ToolCall { id, tool_name, arguments } => {
+                assert_eq!(id.as_deref(), Some("call_1"));
+                assert_eq!(tool_name, "read_files");
+                assert_eq!(arguments, &serde_json::json!({ "paths": ["a"] }));
             }
+            other => panic!("expected a tool call, got {:?}", other),
         }
-        
-        Ok(result)
+    }
+
+    #[test]
+    fn keeps_parallel_tool_calls_apart_by_index() {
+        let mut state = StreamState::default();
+        handle_all(&mut state, &[
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":1,"id":"b","function":{"name":"two","arguments":""}},{"index":0,"id":"a","function":{"name":"one","arguments":"{}"}}]}}]}"#,
+        ]);
+
+        let response = state.into_response(&HeadlessIO::new()).unwrap();
+        let names: Vec<&str> = response.messages.iter().map(|message| match message {
+            Message::ToolCall { tool_name, .. } => tool_name.as_str(),
+            _ => "",
+        }).collect();
+        assert_eq!(names, vec!["one", "two"]);
+    }
+
+    fn model(base_url: String) -> OpenAIModel {
+        OpenAIModel::new("key".to_string(), "gpt-test".to_string(), None, None, Some(base_url))
+    }
+
+    #[tokio::test]
+    async fn streams_a_response_over_http() {
+        let body = [
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":"Hi"}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":" there"}}]}"#,
+            r#"{"choices":[],"usage":{"prompt_tokens":3,"completion_tokens":4,"total_tokens":7}}"#,
+            "[DONE]",
+        ]
+        .map(|data| format!("data: {}\n\n", data))
+        .concat();
+        let (base_url, server) = serve_once(200, "text/event-stream", &body).await;
+
+        let response = model(base_url).send_message(Message::User("hello".to_string()), Vec::new(), &HeadlessIO::new()).await.unwrap();
+
+        assert!(matches!(response.messages.as_slice(), [Message::Model(text)] if text == "Hi there"));
+        assert_eq!(response.usage, Some(Usage { input_tokens: 3, output_tokens: 4 }));
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /v1/chat/completions "), "{}", request);
+        assert!(request.contains(r#""stream":true"#), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn reports_error_statuses() {
+        let body = r#"{"error":{"type":"server_error","message":"The server had an error"}}"#;
+        let (base_url, _) = serve_once(500, "application/json", body).await;
+
+        let error = model(base_url).send_message(Message::User("hello".to_string()), Vec::new(), &HeadlessIO::new()).await.unwrap_err();
+
+        assert_eq!(error.to_string(), "Model API error: API request failed: provider: openai, status: 500, message: The server had an error");
+    }
+
+    #[test]
+    fn rejects_malformed_tool_arguments() {
+        let mut state = StreamState::default();
+        handle_all(&mut state, &[
+            r#"{"choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"a","function":{"name":"one","arguments":"{\"x\""}}]}}]}"#,
+        ]);
+        assert!(state.into_response(&HeadlessIO::new()).is_err());
     }
 }
@@ -14,13 +14,43 @@ pub enum Role {
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Message {
     pub role: Role,
-    pub content: Option<String>,
+    pub content: Option<MessageContent>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_call_id: Option<String>,
 }
 
+/// Content of a message: plain text, or parts mixing text with images and files
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+/// A part of a multi-part message
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+    File { file: FileData },
+}
+
+/// Image passed by URL, which may be a base64 `data:` URL
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImageUrl {
+    pub url: String,
+}
+
+/// File passed inline, such as a PDF, as a base64 `data:` URL
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FileData {
+    pub filename: String,
+    pub file_data: String,
+}
+
 /// Tool call in a message
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ToolCall {
@@ -72,6 +102,14 @@ pub struct ChatCompletionRequest {
     pub stop: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+}
+
+/// Options for streamed completions
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StreamOptions {
+    pub include_usage: bool,
 }
 
 /// Tool choice for controlling function calling
@@ -109,22 +147,46 @@ pub enum FinishReason {
     FunctionCall,
 }
 
-/// A choice in the completion response
+/// Incremental function call details in a streamed chunk
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FunctionCallDelta {
+    pub name: Option<String>,
+    pub arguments: Option<String>,
+}
+
+/// Incremental tool call in a streamed chunk; fragments are correlated by `index`
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct Choice {
+pub struct ToolCallDelta {
+    pub index: usize,
+    pub id: Option<String>,
+    #[serde(rename = "type")]
+    pub call_type: Option<String>,
+    pub function: Option<FunctionCallDelta>,
+}
+
+/// Incremental message content in a streamed chunk
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Delta {
+    pub role: Option<Role>,
+    pub content: Option<String>,
+    pub tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+/// A choice in a streamed chunk
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChunkChoice {
     pub index: u32,
-    pub message: Message,
+    pub delta: Delta,
     pub finish_reason: Option<FinishReason>,
 }
 
-/// Response from creating a chat completion
+/// Chunk of a chat completion streamed with `stream: true`
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
-pub struct ChatCompletionResponse {
+pub struct ChatCompletionChunk {
+    #[serde(default)]
     pub id: String,
-    pub object: String,
-    pub created: u64,
+    #[serde(default)]
     pub model: String,
-    pub choices: Vec<Choice>,
-    pub usage: Usage,
+    pub choices: Vec<ChunkChoice>,
+    pub usage: Option<Usage>,
 }
-
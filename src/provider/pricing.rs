@@ -0,0 +1,87 @@
+use crate::{core::Usage, provider::Provider};
+
+/// Price of a model in US dollars per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pricing {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+impl Pricing {
+    const fn new(input_per_mtok: f64, output_per_mtok: f64) -> Self {
+        Self { input_per_mtok, output_per_mtok }
+    }
+
+    /// Estimated cost of `usage` in US dollars.
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        (usage.input_tokens as f64 * self.input_per_mtok
+            + usage.output_tokens as f64 * self.output_per_mtok)
+            / 1_000_000.0
+    }
+}
+
+/// Model name prefixes and their list prices. More specific prefixes must come first.
+const ANTHROPIC_PRICES: &[(&str, Pricing)] = &[
+    ("claude-opus-4", Pricing::new(15.0, 75.0)),
+    ("claude-sonnet-4", Pricing::new(3.0, 15.0)),
+    ("claude-3-7-sonnet", Pricing::new(3.0, 15.0)),
+    ("claude-3-5-sonnet", Pricing::new(3.0, 15.0)),
+    ("claude-3-5-haiku", Pricing::new(0.8, 4.0)),
+    ("claude-3-opus", Pricing::new(15.0, 75.0)),
+    ("claude-3-haiku", Pricing::new(0.25, 1.25)),
+];
+
+const OPENAI_PRICES: &[(&str, Pricing)] = &[
+    ("gpt-5-nano", Pricing::new(0.05, 0.4)),
+    ("gpt-5-mini", Pricing::new(0.25, 2.0)),
+    ("gpt-5", Pricing::new(1.25, 10.0)),
+    ("gpt-4.1-nano", Pricing::new(0.1, 0.4)),
+    ("gpt-4.1-mini", Pricing::new(0.4, 1.6)),
+    ("gpt-4.1", Pricing::new(2.0, 8.0)),
+    ("gpt-4o-mini", Pricing::new(0.15, 0.6)),
+    ("gpt-4o", Pricing::new(2.5, 10.0)),
+    ("o4-mini", Pricing::new(1.1, 4.4)),
+    ("o3-mini", Pricing::new(1.1, 4.4)),
+    ("o3", Pricing::new(2.0, 8.0)),
+];
+
+/// Looks up the price of `model_name` for `provider`. Returns `None` when the model is unknown;
+/// models served through Ollama run locally and are always free.
+pub fn pricing_for(provider: &Provider, model_name: &str) -> Option<Pricing> {
+    let table = match provider {
+        Provider::Anthropic => ANTHROPIC_PRICES,
+        Provider::OpenAI => OPENAI_PRICES,
+        Provider::Ollama => return Some(Pricing::new(0.0, 0.0)),
+    };
+    table
+        .iter()
+        .find(|(prefix, _)| model_name.starts_with(prefix))
+        .map(|(_, pricing)| *pricing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_specific_prefix_wins() {
+        assert_eq!(pricing_for(&Provider::OpenAI, "gpt-4o-mini-2024-07-18"), Some(Pricing::new(0.15, 0.6)));
+        assert_eq!(pricing_for(&Provider::OpenAI, "gpt-4o"), Some(Pricing::new(2.5, 10.0)));
+        assert_eq!(pricing_for(&Provider::OpenAI, "gpt-5-mini"), Some(Pricing::new(0.25, 2.0)));
+        assert_eq!(pricing_for(&Provider::Anthropic, "claude-sonnet-4-20250514"), Some(Pricing::new(3.0, 15.0)));
+    }
+
+    #[test]
+    fn unknown_models_have_no_price_and_local_models_are_free() {
+        assert_eq!(pricing_for(&Provider::Anthropic, "gpt-4o"), None);
+        assert_eq!(pricing_for(&Provider::OpenAI, "mystery-model"), None);
+        assert_eq!(pricing_for(&Provider::Ollama, "gpt-oss:20b"), Some(Pricing::new(0.0, 0.0)));
+    }
+
+    #[test]
+    fn cost_is_priced_per_million_tokens() {
+        let usage = Usage { input_tokens: 2_000_000, output_tokens: 500_000 };
+        assert_eq!(Pricing::new(3.0, 15.0).cost(&usage), 13.5);
+        assert_eq!(Pricing::new(3.0, 15.0).cost(&Usage::default()), 0.0);
+    }
+}
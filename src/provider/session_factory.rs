@@ -8,7 +8,7 @@ use crate::{
         anthropic::{anthropic_model::AnthropicModel, session_builder::AnthropicSessionBuilder},
         openai::{openai_model::OpenAIModel, session_builder::OpenAISessionBuilder},
     },
-    session::Session,
+    session::{RunSummary, Session, StoredSession},
 };
 
 pub struct SessionFactory;
@@ -25,7 +25,7 @@ impl SessionFactory {
             builder = builder.tool(tool);
         }
 
-        Ok(builder.build()?)
+        builder.build()
     }
 
     fn build_openai_session<'a>(
@@ -40,7 +40,7 @@ impl SessionFactory {
             builder = builder.tool(tool);
         }
 
-        Ok(builder.build(api_key)?)
+        builder.build(api_key)
     }
 
     pub fn build_session<'a>(
@@ -78,4 +78,18 @@ impl<'a> SessionWrapper<'a> {
             SessionWrapper::OpenAI(session) => session.run().await,
         }
     }
+
+    pub async fn run_once(&mut self, prompt: String) -> Result<RunSummary> {
+        match self {
+            SessionWrapper::Anthropic(session) => session.run_once(prompt).await,
+            SessionWrapper::OpenAI(session) => session.run_once(prompt).await,
+        }
+    }
+
+    pub fn resume(&mut self, stored: StoredSession) {
+        match self {
+            SessionWrapper::Anthropic(session) => session.resume(stored),
+            SessionWrapper::OpenAI(session) => session.resume(stored),
+        }
+    }
 }
@@ -0,0 +1,116 @@
+/// A single server-sent event, as defined by the `text/event-stream` format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// Incrementally decodes a `text/event-stream` body into events.
+///
+/// Bytes can be pushed in arbitrarily sized chunks (as they arrive from the network);
+/// an event is only emitted once its terminating blank line has been seen.
+#[derive(Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk of bytes into the decoder and returns every event completed by it.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(position) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=position).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                if let Some(event) = self.dispatch() {
+                    events.push(event);
+                }
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data
+                    .push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            } else if let Some(value) = line.strip_prefix("event:") {
+                self.event = Some(value.trim().to_string());
+            }
+            // comments (lines starting with ':') and unknown fields are ignored
+        }
+        events
+    }
+
+    /// Flushes a trailing event that was not followed by a blank line before the stream ended.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let mut events = self.push(b"\n");
+            if let Some(event) = events.pop() {
+                return Some(event);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+        let data = self.data.join("\n");
+        self.data.clear();
+        Some(SseEvent { event, data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(event: Option<&str>, data: &str) -> SseEvent {
+        SseEvent { event: event.map(str::to_string), data: data.to_string() }
+    }
+
+    #[test]
+    fn decodes_events_split_across_chunks() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"event: ping\nda").is_empty());
+        assert!(decoder.push(b"ta: {\"a\":").is_empty());
+        let events = decoder.push(b" 1}\n\ndata: second\n\n");
+        assert_eq!(events, vec![event(Some("ping"), "{\"a\": 1}"), event(None, "second")]);
+    }
+
+    #[test]
+    fn accepts_crlf_line_endings() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.push(b"event: message\r\ndata: hello\r\n\r\n");
+        assert_eq!(events, vec![event(Some("message"), "hello")]);
+    }
+
+    #[test]
+    fn joins_multi_line_data() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.push(b"data: first\ndata:second\n\n");
+        assert_eq!(events, vec![event(None, "first\nsecond")]);
+    }
+
+    #[test]
+    fn ignores_comments_and_events_without_data() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.push(b": keep-alive\n\nevent: empty\n\ndata: [DONE]\n\n");
+        assert_eq!(events, vec![event(None, "[DONE]")]);
+    }
+
+    #[test]
+    fn flushes_a_trailing_event_on_finish() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: last").is_empty());
+        assert_eq!(decoder.finish(), Some(event(None, "last")));
+        assert_eq!(decoder.finish(), None);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::{
+    core::{Attachment, Message, Model, Usage},
+    error::{Result, SessionError},
+    io::HeadlessIO,
+};
+
+use super::Session;
+
+/// Share of the context budget that is kept verbatim when older turns are compacted.
+const KEEP_RATIO: f64 = 0.25;
+
+/// Base64-encoded attachments that one request may carry, in bytes. Providers refuse requests
+/// larger than about 32 MB, and every request resends the whole history.
+const MAX_HISTORY_ATTACHMENT_BYTES: usize = 24 * 1024 * 1024;
+
+const SUMMARY_PROMPT: &str = "The conversation so far is about to be compacted to free up context. \
+Summarise it for your own future reference: the user's goals and instructions, decisions made, \
+files read or changed (with paths), commands run and their outcomes, and any open tasks or questions. \
+Be thorough but concise. Do not call any tools; respond with the summary only.";
+
+/// Opens the user message that stands in for compacted turns.
+const SUMMARY_TAG: &str = "<conversation_summary>";
+
+/// Whether `message` is the summary of compacted turns rather than something the user wrote.
+pub(super) fn is_summary(message: &Message) -> bool {
+    matches!(message, Message::User(text) if text.starts_with(SUMMARY_TAG))
+}
+
+/// Rough token estimate for a message, based on the common ~4 characters per token heuristic.
+/// Only used to decide where to split the history; the budget check itself relies on the usage
+/// reported by the provider whenever it is available.
+fn estimate_tokens(message: &Message) -> usize {
+    let chars = match message {
+        Message::User(text) | Message::Model(text) => text.len(),
+        Message::ToolCall { tool_name, arguments, .. } => tool_name.len() + arguments.to_string().len(),
+        Message::ToolResult { output, .. } => output.len(),
+    };
+    let attachments = match message {
+        Message::ToolResult { attachments, .. } => attachments.iter().map(estimate_attachment_tokens).sum(),
+        _ => 0,
+    };
+    chars / 4 + 1 + attachments
+}
+
+/// Images are scaled down by the providers and cost at most ~1,600 tokens; documents are
+/// estimated from their base64-encoded size.
+fn estimate_attachment_tokens(attachment: &Attachment) -> usize {
+    if attachment.is_image() {
+        1_600
+    } else {
+        attachment.data.len() / 32
+    }
+}
+
+/// Finds the index at which `history` should be split so that the messages from that index on
+/// fit within `keep_tokens`. A tool result is never separated from its call, and splitting right
+/// before a user message is preferred so that whole turns are kept together.
+fn find_split(history: &[Message], keep_tokens: usize) -> Option<usize> {
+    let mut suffix_tokens = 0;
+    let mut fallback = None;
+    let mut turn_boundary = None;
+
+    for index in (1..history.len()).rev() {
+        suffix_tokens += estimate_tokens(&history[index]);
+        let is_boundary = !matches!(history[index], Message::ToolResult { .. });
+        if !is_boundary {
+            continue;
+        }
+        if fallback.is_none() || suffix_tokens <= keep_tokens {
+            fallback = Some(index);
+        }
+        if suffix_tokens <= keep_tokens && matches!(history[index], Message::User(_)) {
+            turn_boundary = Some(index);
+        }
+        if suffix_tokens > keep_tokens {
+            break;
+        }
+    }
+
+    turn_boundary.or(fallback)
+}
+
+fn attachment_bytes(message: &Message) -> usize {
+    match message {
+        Message::ToolResult { attachments, .. } => attachments.iter().map(|attachment| attachment.data.len()).sum(),
+        _ => 0,
+    }
+}
+
+/// Removes the oldest attachments from `history` until the rest, together with those of
+/// `pending`, fit within `max_bytes`. A tool result that loses an attachment says so, so that
+/// the model can read the file again. Returns the number of attachments removed.
+fn drop_old_attachments(history: &mut [Message], pending: &Message, max_bytes: usize) -> usize {
+    let mut total: usize = history.iter().chain([pending]).map(attachment_bytes).sum();
+    let mut dropped = 0;
+    for message in history.iter_mut() {
+        if total <= max_bytes {
+            break;
+        }
+        let Message::ToolResult { output, attachments, .. } = message else {
+            continue;
+        };
+        while total > max_bytes && !attachments.is_empty() {
+            let attachment = attachments.remove(0);
+            total -= attachment.data.len();
+            dropped += 1;
+            output.push_str(&format!(
+                "<note>\n{} is no longer attached, to keep the conversation within the size limit for attachments; read it again if you need it\n</note>\n",
+                attachment.path
+            ));
+        }
+    }
+    dropped
+}
+
+impl<'a, M: Model> Session<'a, M> {
+    /// Number of tokens the conversation currently occupies. Uses the usage reported for the last
+    /// request when available and falls back to estimates otherwise.
+    pub fn context_tokens(&self) -> usize {
+        match self.last_usage {
+            Some(usage) => usage.total() as usize,
+            None => self.message_history.iter().map(estimate_tokens).sum(),
+        }
+    }
+
+    /// Compacts the history if it has grown beyond the configured budget.
+    pub(super) async fn compact_if_needed(&mut self, pending: &Message) -> Result<()> {
+        let budget = self.context.model_config.context_budget as usize;
+        if self.context_tokens() + estimate_tokens(pending) > budget
+            && let Some(usage) = self.summarise_older_turns().await?
+        {
+            // compacting mid-turn is part of the cost of the turn
+            self.record_usage(Some(usage));
+        }
+        Ok(())
+    }
+
+    /// Drops the oldest images and PDFs from the history once the attachments of the next
+    /// request would exceed what the providers accept.
+    pub(super) fn limit_attachments(&mut self, pending: &Message) {
+        let dropped = drop_old_attachments(&mut self.message_history, pending, MAX_HISTORY_ATTACHMENT_BYTES);
+        if dropped > 0 {
+            self.io.show_message(
+                "Attachments dropped",
+                &format!("Removed {} earlier images or PDFs from the conversation to stay within the size limit.", dropped),
+            );
+        }
+    }
+
+    /// Replaces older turns with a model-written summary, keeping the most recent messages intact.
+    /// Returns `false` if there was not enough history to compact.
+    ///
+    /// The tokens spent on the summary count towards the session, but not towards the last turn.
+    pub async fn compact(&mut self) -> Result<bool> {
+        let Some(usage) = self.summarise_older_turns().await? else {
+            return Ok(false);
+        };
+        self.session_usage += usage;
+        Ok(true)
+    }
+
+    /// Does the work of [`Session::compact`] and returns the tokens the summary request consumed,
+    /// or `None` if there was not enough history to compact.
+    async fn summarise_older_turns(&mut self) -> Result<Option<Usage>> {
+        let keep_tokens = (self.context.model_config.context_budget as f64 * KEEP_RATIO) as usize;
+        let Some(split) = find_split(&self.message_history, keep_tokens) else {
+            return Ok(None);
+        };
+
+        let tokens_before = self.context_tokens();
+        let older = self.message_history[..split].to_vec();
+
+        // the summary is for internal use only, so it is not streamed to the user
+        let response = self
+            .model
+            .send_message(Message::User(SUMMARY_PROMPT.to_string()), older, &HeadlessIO::new())
+            .await?;
+        let summary = response
+            .messages
+            .iter()
+            .filter_map(|m| match m {
+                Message::Model(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        if summary.trim().is_empty() {
+            return Err(SessionError::Processing {
+                reason: "compaction failed: the model returned an empty summary".to_string()
+            }.into());
+        }
+
+        let mut compacted = vec![Message::User(format!(
+            "{}\nEarlier parts of this conversation were compacted to save context. Summary:\n\n{}\n</conversation_summary>",
+            SUMMARY_TAG, summary
+        ))];
+        compacted.extend(self.message_history.drain(split..));
+        self.message_history = compacted;
+        // turns that started before the split can no longer be rewound to
+        self.turn_starts.retain(|(_, start)| *start >= split);
+        for (_, start) in &mut self.turn_starts {
+            *start = *start - split + 1;
+        }
+        self.last_usage = None;
+
+        self.io.show_message(
+            "Context compacted",
+            &format!(
+                "Summarised {} earlier messages (~{} tokens before, ~{} tokens after).",
+                split,
+                tokens_before,
+                self.context_tokens()
+            ),
+        );
+        Ok(Some(response.usage.unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn user(chars: usize) -> Message {
+        Message::User("u".repeat(chars))
+    }
+
+    fn model(chars: usize) -> Message {
+        Message::Model("m".repeat(chars))
+    }
+
+    fn call(id: &str) -> Message {
+        Message::ToolCall { id: Some(id.to_string()), tool_name: "exec_command".to_string(), arguments: json!({}) }
+    }
+
+    fn result(id: &str, chars: usize) -> Message {
+        Message::ToolResult { id: Some(id.to_string()), output: "r".repeat(chars), attachments: Vec::new(), is_error: false }
+    }
+
+    #[test]
+    fn estimates_four_characters_per_token() {
+        assert_eq!(estimate_tokens(&user(0)), 1);
+        assert_eq!(estimate_tokens(&model(400)), 101);
+
+        let image = Attachment { path: "a.png".to_string(), media_type: "image/png".to_string(), data: "x".repeat(1_000_000) };
+        let document = Attachment { path: "a.pdf".to_string(), media_type: "application/pdf".to_string(), data: "x".repeat(3_200) };
+        let with_attachments = Message::ToolResult {
+            id: None,
+            output: String::new(),
+            attachments: vec![image, document],
+            is_error: false,
+        };
+        assert_eq!(estimate_tokens(&with_attachments), 1 + 1_600 + 100);
+    }
+
+    #[test]
+    fn nothing_to_split_in_a_single_message() {
+        assert_eq!(find_split(&[user(4_000)], 10), None);
+        assert_eq!(find_split(&[], 10), None);
+    }
+
+    #[test]
+    fn prefers_splitting_before_a_user_message() {
+        // ~101 tokens per message
+        let history = [user(400), model(400), user(400), model(400), call("1"), result("1", 400), model(400)];
+        // the last three messages fit, but the split is moved back to the start of the turn
+        assert_eq!(find_split(&history, 450), Some(2));
+        // only the last turn's tail fits and no user message does: split at the latest fitting boundary
+        assert_eq!(find_split(&history, 250), Some(4));
+    }
+
+    #[test]
+    fn never_separates_a_tool_result_from_its_call() {
+        let history = [user(400), call("1"), result("1", 4_000)];
+        // the result alone exceeds the budget, so the split falls before its call
+        assert_eq!(find_split(&history, 50), Some(1));
+    }
+
+    #[test]
+    fn keeps_at_least_the_last_message_when_nothing_fits() {
+        let history = [user(400), model(4_000), user(4_000)];
+        assert_eq!(find_split(&history, 10), Some(2));
+    }
+
+    fn attached(id: &str, paths: &[&str], bytes: usize) -> Message {
+        let attachments = paths
+            .iter()
+            .map(|path| Attachment { path: path.to_string(), media_type: "image/png".to_string(), data: "x".repeat(bytes) })
+            .collect();
+        Message::ToolResult { id: Some(id.to_string()), output: "read\n".to_string(), attachments, is_error: false }
+    }
+
+    #[test]
+    fn drops_the_oldest_attachments_beyond_the_limit() {
+        let mut history = vec![user(10), call("1"), attached("1", &["a.png", "b.png"], 40), call("2")];
+        let pending = attached("2", &["c.png"], 40);
+
+        assert_eq!(drop_old_attachments(&mut history, &pending, 120), 0);
+        assert_eq!(drop_old_attachments(&mut history, &pending, 100), 1);
+
+        let Message::ToolResult { output, attachments, .. } = &history[2] else {
+            panic!("expected a tool result");
+        };
+        assert_eq!(attachments.iter().map(|attachment| attachment.path.as_str()).collect::<Vec<_>>(), vec!["b.png"]);
+        assert!(output.starts_with("read\n<note>\na.png is no longer attached"), "{}", output);
+        // the pending result's own attachments are kept even if they alone exceed the limit
+        assert_eq!(drop_old_attachments(&mut history, &pending, 10), 1);
+        assert_eq!(attachment_bytes(&history[2]), 0);
+    }
+}
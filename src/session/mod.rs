@@ -1,19 +1,95 @@
+mod compaction;
+mod store;
+
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+pub use store::{SessionStore, StoredSession};
 
 use crate::{
+    commands::{CommandOutcome, CommandRegistry},
     context::Context,
-    core::{Message, Model, PermissionMode, Tool},
+    core::{CheckpointLog, Message, Model, PermissionMode, PermissionPolicy, PolicyAction, PolicyScope, Tool, UnattendedPolicy, Usage},
     error::{SessionError, ToolError, Result},
     io::IO,
+    provider::pricing::pricing_for,
 };
 
+/// How a turn ended.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TurnOutcome {
+    /// The model finished responding without any tool call being denied.
+    Completed,
+    /// A tool call was denied, so control was handed back before the model finished.
+    Denied,
+    /// The turn ended with an error, such as a failed request to the model.
+    Failed,
+}
+
+/// What [`Session::rewind`] undid.
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    pub messages_removed: usize,
+    /// One line per file whose changes were reverted.
+    pub files: Vec<String>,
+    /// Whether turns from before the session was resumed were removed; the file changes made
+    /// during them were not recorded, so they were not reverted.
+    pub before_resume: bool,
+}
+
+/// The result of [`Session::rewind`].
+#[derive(Debug, Clone)]
+pub enum RewindOutcome {
+    /// The conversation has no such turn.
+    NoSuchTurn,
+    /// Files were changed outside deputy since it last wrote them and the user chose to keep
+    /// them; nothing was reverted or removed.
+    Cancelled,
+    Rewound(Rewind),
+}
+
+/// Summary of a non-interactive run, reported once the turn has ended.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub session_id: String,
+    pub outcome: TurnOutcome,
+    /// Number of requests made to the model during the run.
+    pub turns: usize,
+    pub final_text: Option<String>,
+    pub usage: Usage,
+    /// Estimated cost in US dollars, if the model's pricing is known.
+    pub cost_usd: Option<f64>,
+    /// Why the turn failed, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
 pub struct Session<'a, M: Model> {
     model: M,
     message_history: Vec<Message>,
     tools: HashMap<String, Box<dyn Tool>>,
     tool_permissions: HashMap<String, PermissionMode>,
+    policy: PermissionPolicy,
+    checkpoints: Arc<CheckpointLog>,
+    /// Number of each user turn in the conversation and the index of its first message.
+    turn_starts: Vec<(usize, usize)>,
+    next_turn: usize,
+    /// Number of turns restored when the session was resumed, whose file changes were made by an
+    /// earlier run and cannot be reverted.
+    resumed_turns: usize,
     io: &'a mut Box<dyn IO>,
     context: &'a Context,
+    store: SessionStore,
+    session_id: String,
+    created_at: u64,
+    turns: usize,
+    last_usage: Option<Usage>,
+    turn_usage: Usage,
+    session_usage: Usage,
 }
 
 impl<'a, M: Model> Session<'a, M> {
@@ -28,12 +104,220 @@ impl<'a, M: Model> Session<'a, M> {
             message_history: Vec::new(),
             tools,
             tool_permissions: HashMap::new(),
+            policy: context.session_config.permission_policy().clone(),
+            checkpoints: context.session_config.checkpoints().clone(),
+            turn_starts: Vec::new(),
+            next_turn: 1,
+            resumed_turns: 0,
             io,
             context,
+            store: SessionStore::new(),
+            session_id: SessionStore::new_id(),
+            created_at: SessionStore::now(),
+            turns: 0,
+            last_usage: None,
+            turn_usage: Usage::default(),
+            session_usage: Usage::default(),
+        }
+    }
+
+    /// Restores a previously saved conversation; subsequent turns are saved back under its id.
+    pub fn resume(&mut self, stored: StoredSession) {
+        self.io.show_message(
+            &format!("Resumed session {}", stored.id),
+            &format!(
+                "{} messages restored from {}.",
+                stored.messages.len(),
+                stored.cwd
+            ),
+        );
+        if let Some(Message::Model(text)) = stored
+            .messages
+            .iter()
+            .rev()
+            .find(|m| matches!(m, Message::Model(_)))
+        {
+            self.io.show_message("Deputy", text);
+        }
+
+        self.session_id = stored.id;
+        self.created_at = stored.created_at;
+        self.message_history = stored.messages;
+        self.turn_starts = self
+            .message_history
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| matches!(m, Message::User(_)) && !compaction::is_summary(m))
+            .enumerate()
+            .map(|(turn, (index, _))| (turn + 1, index))
+            .collect();
+        self.next_turn = self.turn_starts.len() + 1;
+        self.resumed_turns = self.turn_starts.len();
+        self.last_usage = None;
+        self.session_usage = stored.usage;
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    pub fn io(&self) -> &dyn IO {
+        self.io.as_ref()
+    }
+
+    pub fn messages(&self) -> &[Message] {
+        &self.message_history
+    }
+
+    pub fn tools(&self) -> &HashMap<String, Box<dyn Tool>> {
+        &self.tools
+    }
+
+    pub fn tool_permissions(&self) -> &HashMap<String, PermissionMode> {
+        &self.tool_permissions
+    }
+
+    /// Returns the session's approvals and denials for a tool, creating an empty set if needed.
+    pub fn tool_permissions_mut(&mut self, tool_name: &str) -> &mut PermissionMode {
+        self.tool_permissions.entry(tool_name.to_string()).or_default()
+    }
+
+    pub fn model_name(&self) -> &str {
+        self.model.model_name()
+    }
+
+    /// Switches to another model of the same provider for subsequent requests.
+    pub fn set_model_name(&mut self, model_name: String) {
+        self.model.set_model_name(model_name);
+        self.last_usage = None;
+    }
+
+    /// Starts a fresh conversation under a new session id; the previous one stays saved.
+    pub fn clear(&mut self) {
+        self.message_history.clear();
+        self.turn_starts.clear();
+        self.next_turn = 1;
+        self.resumed_turns = 0;
+        self.checkpoints.clear();
+        self.session_id = SessionStore::new_id();
+        self.created_at = SessionStore::now();
+        self.last_usage = None;
+        self.turn_usage = Usage::default();
+        self.session_usage = Usage::default();
+    }
+
+    /// The user turns of the conversation, as their number, the user's message and the number of
+    /// file changes made during them.
+    pub fn turns(&self) -> Vec<(usize, &str, usize)> {
+        self.turn_starts
+            .iter()
+            .filter_map(|(turn, index)| match self.message_history.get(*index) {
+                Some(Message::User(text)) => Some((*turn, text.as_str(), self.checkpoints.changes_in(*turn))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn last_turn(&self) -> Option<usize> {
+        self.turn_starts.last().map(|(turn, _)| *turn)
+    }
+
+    /// Reverts the file changes made during `turn` and every later turn, and removes those turns
+    /// from the conversation. Files changed outside deputy since it last wrote them are only
+    /// overwritten if the user agrees. Changes made before the session was resumed are not known,
+    /// so they stay as they are.
+    pub fn rewind(&mut self, turn: usize) -> Result<RewindOutcome> {
+        let Some(position) = self.turn_starts.iter().position(|(t, _)| *t == turn) else {
+            return Ok(RewindOutcome::NoSuchTurn);
+        };
+        let turn_start = self.turn_starts[position].1;
+
+        let modified = self.checkpoints.modified_since(turn);
+        if !modified.is_empty() && !self.confirm_overwrite(&modified)? {
+            return Ok(RewindOutcome::Cancelled);
+        }
+        let files = self.checkpoints.revert_since(turn)?;
+        let messages_removed = self.message_history.len() - turn_start;
+        self.message_history.truncate(turn_start);
+        self.turn_starts.truncate(position);
+        self.next_turn = turn;
+        let before_resume = turn <= self.resumed_turns;
+        self.resumed_turns = self.resumed_turns.min(turn - 1);
+        self.last_usage = None;
+        Ok(RewindOutcome::Rewound(Rewind { messages_removed, files, before_resume }))
+    }
+
+    /// Asks whether reverting may discard changes made to `files` outside deputy.
+    fn confirm_overwrite(&mut self, files: &[PathBuf]) -> Result<bool> {
+        let list: Vec<String> = files.iter().map(|path| format!("  {}", path.display())).collect();
+        self.io.show_message(
+            "Files changed outside deputy",
+            &format!(
+                "These files were changed since deputy last wrote them; reverting them discards those changes:\n{}",
+                list.join("\n")
+            ),
+        );
+        let response = self
+            .io
+            .get_user_input("Revert them anyway? [y/N] > ")
+            .map_err(|e| SessionError::UserInput {
+                reason: format!("Failed to read user input: {}", e)
+            })?;
+        Ok(matches!(response.as_deref().map(str::trim), Some("y" | "Y")))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if self.message_history.is_empty() {
+            // an emptied conversation (e.g. after /undo) is not worth resuming
+            return self.store.remove(&self.session_id);
+        }
+        self.store.save(&StoredSession {
+            version: store::FORMAT_VERSION,
+            id: self.session_id.clone(),
+            cwd: self.context.session_config.cwd().to_string(),
+            provider: self.context.model_config.provider.to_string(),
+            model: self.model_name().to_string(),
+            created_at: self.created_at,
+            updated_at: SessionStore::now(),
+            usage: self.session_usage,
+            messages: self.message_history.clone(),
+        })
+    }
+
+    /// Adds the tokens consumed by a model request to the turn and session totals.
+    fn record_usage(&mut self, usage: Option<Usage>) {
+        if let Some(usage) = usage {
+            self.turn_usage += usage;
+            self.session_usage += usage;
         }
     }
 
-    fn prompt_for_permission(&mut self, tool_name: &str, permission_id: &str) -> Result<bool> {
+    /// Estimated cost of `usage` in US dollars, if the pricing of the current model is known.
+    fn cost_of(&self, usage: &Usage) -> Option<f64> {
+        pricing_for(&self.context.model_config.provider, self.model_name()).map(|pricing| pricing.cost(usage))
+    }
+
+    pub fn usage_report(&self) -> String {
+        let describe = |usage: &Usage| {
+            let cost = match self.cost_of(usage) {
+                Some(cost) => format!("~${:.4}", cost),
+                None => "cost unknown".to_string(),
+            };
+            format!("{} input / {} output tokens ({})", usage.input_tokens, usage.output_tokens, cost)
+        };
+        format!(
+            "Last turn: {}\nSession:   {}",
+            describe(&self.turn_usage),
+            describe(&self.session_usage)
+        )
+    }
+
+    fn prompt_for_permission(
+        &mut self,
+        tool_name: &str,
+        permission_id: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<bool> {
         let response = self
             .io
             .get_user_input(&format!(
@@ -48,12 +332,11 @@ impl<'a, M: Model> Session<'a, M> {
             match s.as_str() {
                 "1" => Ok(true),
                 "2" => {
-                    self.tool_permissions.insert(
-                        tool_name.to_string(),
-                        PermissionMode::ApprovedForId {
-                            command_id: permission_id.to_string(),
-                        },
-                    );
+                    self.tool_permissions
+                        .entry(tool_name.to_string())
+                        .or_default()
+                        .approve(permission_id);
+                    self.offer_to_persist_rule(tool_name, permission_id, arguments)?;
                     Ok(true)
                 }
                 _ => Ok(false),
@@ -63,15 +346,41 @@ impl<'a, M: Model> Session<'a, M> {
         }
     }
 
-    fn display_message(&self, message: &Message) {
-        match message {
-            Message::User(text) => self.io.show_message("You", text),
-            Message::Model(text) => self.io.show_message("Deputy", text),
-            _ => {}
+    /// Asks whether an "always allow" answer should be saved to a policy file, so that it also
+    /// applies to future sessions.
+    fn offer_to_persist_rule(
+        &mut self,
+        tool_name: &str,
+        permission_id: &str,
+        arguments: &serde_json::Value,
+    ) -> Result<()> {
+        let response = self
+            .io
+            .get_user_input("Save this rule? [p: project policy, u: user policy, Enter: this session only] > ")
+            .map_err(|e| SessionError::UserInput {
+                reason: format!("Failed to read user input: {}", e)
+            })?;
+        let scope = match response.as_deref().map(str::trim) {
+            Some("p") => PolicyScope::Project,
+            Some("u") => PolicyScope::User,
+            _ => return Ok(()),
+        };
+
+        let rule = self.policy.rule_for(tool_name, permission_id, arguments);
+        match self.policy.persist(rule, scope) {
+            Ok(path) => self.io.show_message(
+                "Permission saved",
+                &format!("{} ({}) is now allowed by {}", tool_name, permission_id, path.display()),
+            ),
+            Err(e) => self.io.show_message("Permission not saved", &e.to_string()),
         }
+        Ok(())
     }
 
     pub async fn run(&mut self) -> Result<()> {
+        let commands = CommandRegistry::with_default_commands();
+        self.io.set_command_completions(commands.names());
+
         while let Some(input) = self.io.get_user_input("> ")? {
             if input.is_empty() {
                 continue;
@@ -79,46 +388,123 @@ impl<'a, M: Model> Session<'a, M> {
             if input == "exit" {
                 break;
             }
+            if commands.is_command(&input) {
+                match commands.execute(self, &input[1..]).await? {
+                    CommandOutcome::Continue => continue,
+                    CommandOutcome::Exit => break,
+                }
+            }
             let message = Message::User(input.clone());
-            self.send_message(message).await?;
+            let result = self.send_message(message).await;
+            if result.is_err() {
+                self.answer_dangling_call();
+            }
+            self.save()?;
+            result?;
         }
 
+        if !self.message_history.is_empty() {
+            self.io.show_message("Token usage", &self.usage_report());
+            self.io.show_message(
+                "Session saved",
+                &format!("Resume this conversation with `deputy --resume {}`.", self.session_id),
+            );
+        }
         Ok(())
     }
 
-    pub async fn send_message(&mut self, message: Message) -> Result<()> {
+    /// Runs a single turn for `prompt` without entering the interactive loop. A turn that fails
+    /// is reported in the summary, so that its usage and error can still be shown; only failing
+    /// to save the session is returned as an error.
+    pub async fn run_once(&mut self, prompt: String) -> Result<RunSummary> {
+        let turn_start = self.message_history.len();
+        let turns_before = self.turns;
+        let result = self.send_message(Message::User(prompt)).await;
+        if result.is_err() {
+            self.answer_dangling_call();
+        }
+        self.save()?;
+        let (outcome, error) = match result {
+            Ok(outcome) => (outcome, None),
+            Err(e) => (TurnOutcome::Failed, Some(e.to_string())),
+        };
+
+        let final_text = self.message_history[turn_start..]
+            .iter()
+            .rev()
+            .find_map(|m| match m {
+                Message::Model(text) => Some(text.clone()),
+                _ => None,
+            });
+        Ok(RunSummary {
+            session_id: self.session_id.clone(),
+            outcome,
+            turns: self.turns - turns_before,
+            final_text,
+            usage: self.turn_usage,
+            cost_usd: self.cost_of(&self.turn_usage),
+            error,
+        })
+    }
+
+    pub async fn send_message(&mut self, message: Message) -> Result<TurnOutcome> {
         let mut current_message = message.clone();
         let debug_mode = std::env::var("DEPUTY_DEBUG").unwrap_or_default() == "true";
         let mut turn_finished = false;
+        let mut outcome = TurnOutcome::Completed;
+        self.turn_usage = Usage::default();
+        self.io.record_message(&message);
+
+        let mut user_turn = matches!(message, Message::User(_)).then_some(self.next_turn);
+        if let Some(turn) = user_turn {
+            self.next_turn += 1;
+            self.checkpoints.begin_turn(turn);
+        }
 
         while !turn_finished {
             turn_finished = true;
 
+            self.compact_if_needed(&current_message).await?;
+            self.limit_attachments(&current_message);
+
+            self.turns += 1;
             let response = self
                 .model
-                .send_message(current_message.clone(), self.message_history.clone())
+                .send_message(current_message.clone(), self.message_history.clone(), self.io.as_ref())
                 .await?;
+            if response.usage.is_some() {
+                self.last_usage = response.usage;
+            }
+            self.record_usage(response.usage);
 
+            // recorded only now, as compaction may have moved the turn's start
+            if let Some(turn) = user_turn.take() {
+                self.turn_starts.push((turn, self.message_history.len()));
+            }
             self.message_history.push(current_message.clone());
 
             let mut tool_calls = Vec::new();
             let mut other_messages = Vec::new();
 
-            for m in response {
+            for m in response.messages {
                 match &m {
                     Message::ToolCall { .. } => tool_calls.push(m),
                     _ => other_messages.push(m),
                 }
             }
 
+            // model text has already been rendered to the user while it was streamed in
             for m in other_messages {
-                self.message_history.push(m.clone());
-                self.display_message(&m);
+                self.io.record_message(&m);
+                self.message_history.push(m);
             }
 
             if !tool_calls.is_empty() {
                 turn_finished = false;
-                let on_rejected = || turn_finished = true;
+                let on_rejected = || {
+                    turn_finished = true;
+                    outcome = TurnOutcome::Denied;
+                };
                 let mut tool_results = self
                     .process_tool_calls(tool_calls, debug_mode, on_rejected)
                     .await?;
@@ -145,7 +531,7 @@ impl<'a, M: Model> Session<'a, M> {
                 }
             }
         }
-        Ok(())
+        Ok(outcome)
     }
 
     async fn process_tool_calls(
@@ -158,6 +544,7 @@ impl<'a, M: Model> Session<'a, M> {
         let mut batch_cancelled = false;
 
         for tool_call in tool_calls {
+            self.io.record_message(&tool_call);
             let result = if batch_cancelled {
                 self.create_cancellation_message(&tool_call)
             } else {
@@ -170,7 +557,8 @@ impl<'a, M: Model> Session<'a, M> {
                     }
                 }
             };
-            
+
+            self.io.record_message(&result);
             results.push((tool_call, result));
         }
 
@@ -188,13 +576,22 @@ impl<'a, M: Model> Session<'a, M> {
         
         self.log_debug(debug_mode, &format!("Tool call: {} with arguments: {}", tool_name, arguments));
 
-        let permission_id = {
+        let permission_ids = {
             let tool = self.tools.get(&tool_name)
                 .ok_or_else(|| ToolError::NotFound { reason: format!("tool: {}", tool_name) })?;
-            tool.permission_id(arguments.clone())?
+            tool.permission_ids(arguments.clone())
+        };
+        // calls that are refused outright, e.g. for invalid arguments, are reported to the model
+        let permission_ids = match permission_ids {
+            Ok(permission_ids) => permission_ids,
+            Err(error) => {
+                self.log_debug(debug_mode, &format!("Tool call refused: {}", error));
+                self.io.show_message("Tool call refused", &error.to_string());
+                return Ok(Some(Message::ToolResult { id, output: error.to_string(), attachments: Vec::new(), is_error: true }));
+            }
         };
         
-        if !self.authorize_tool_execution(&tool_name, &permission_id, &arguments, debug_mode)? {
+        if !self.authorize_tool_execution(&tool_name, &permission_ids, &arguments, debug_mode)? {
             return Ok(None);
         }
 
@@ -202,35 +599,93 @@ impl<'a, M: Model> Session<'a, M> {
         Ok(Some(result))
     }
 
+    /// Decides whether a tool call may run. Every permission id of the call must be allowed,
+    /// either by the policy files, by an earlier approval in this session or by the user.
     fn authorize_tool_execution(
         &mut self,
         tool_name: &str,
-        permission_id: &str,
+        permission_ids: &[String],
         arguments: &serde_json::Value,
         debug_mode: bool,
     ) -> Result<bool> {
+        let ids = permission_ids.join(", ");
+        let policy_action = self.policy.evaluate(tool_name, arguments);
+
+        // deny rules are a hard stop, even in yolo mode
+        if let Some((PolicyAction::Deny, scope)) = policy_action {
+            self.log_debug(debug_mode, &format!("Denied tool {} with permission_ids {} by the {} policy", tool_name, ids, scope));
+            self.io.show_message(
+                "Permission denied",
+                &format!("{} ({}) was denied by the {} permission policy", tool_name, ids, scope),
+            );
+            return Ok(false);
+        }
+
         if self.context.model_config.yolo_mode {
-            self.log_debug(debug_mode, &format!("YOLO MODE: Auto-allowing tool {} with permission_id {}", tool_name, permission_id));
+            self.log_debug(debug_mode, &format!("YOLO MODE: Auto-allowing tool {} with permission_ids {}", tool_name, ids));
             return Ok(true);
         }
 
-        let permission_mode = self.tool_permissions.get(tool_name).unwrap_or(&PermissionMode::Ask);
+        if let Some((PolicyAction::Allow, scope)) = policy_action {
+            self.log_debug(debug_mode, &format!("Allowed tool {} with permission_ids {} by the {} policy", tool_name, ids, scope));
+            return Ok(true);
+        }
+
+        let no_permissions = PermissionMode::default();
+        let permission_mode = self.tool_permissions.get(tool_name).unwrap_or(&no_permissions);
+
+        let denied: Vec<&str> = permission_ids
+            .iter()
+            .filter(|permission_id| permission_mode.is_denied(permission_id))
+            .map(String::as_str)
+            .collect();
+        if !denied.is_empty() {
+            let denied = denied.join(", ");
+            self.log_debug(debug_mode, &format!("Denied tool {} with permission_ids {} for this session", tool_name, denied));
+            self.io.show_message(
+                "Permission denied",
+                &format!("{} ({}) was denied for this session", tool_name, denied),
+            );
+            return Ok(false);
+        }
         
-        let requires_user_prompt = match permission_mode {
-            PermissionMode::Ask => true,
-            PermissionMode::ApprovedForId { command_id } => permission_id != command_id,
-        };
+        // an ask rule overrides approvals given earlier in the session
+        let forced = matches!(policy_action, Some((PolicyAction::Ask, _)));
+        let read_only = self.tools.get(tool_name).is_some_and(|tool| tool.is_read_only());
+        let unapproved: Vec<String> = permission_ids
+            .iter()
+            .filter(|permission_id| forced || !(permission_mode.is_approved(permission_id) || (read_only && *permission_id == tool_name)))
+            .cloned()
+            .collect();
 
-        if requires_user_prompt {
-            {
-                let tool = self.tools.get(tool_name)
-                    .ok_or_else(|| ToolError::NotFound { reason: format!("tool: {}", tool_name) })?;
-                tool.ask_permission(arguments.clone(), self.io);
+        if unapproved.is_empty() {
+            return Ok(true);
+        }
+
+        if let Some(policy) = self.context.model_config.unattended_policy {
+            let allowed = policy == UnattendedPolicy::Allow;
+            self.log_debug(debug_mode, &format!("Unattended policy {:?} applied to tool {} with permission_ids {}", policy, tool_name, ids));
+            if !allowed {
+                self.io.show_message(
+                    "Permission denied",
+                    &format!("{} ({}) was denied by the unattended permission policy", tool_name, unapproved.join(", ")),
+                );
             }
-            self.prompt_for_permission(tool_name, permission_id)
-        } else {
-            Ok(true)
+            return Ok(allowed);
         }
+
+        {
+            let tool = self.tools.get(tool_name)
+                .ok_or_else(|| ToolError::NotFound { reason: format!("tool: {}", tool_name) })?;
+            tool.ask_permission(arguments.clone(), self.io);
+        }
+        // each part of a compound command is approved on its own
+        for permission_id in &unapproved {
+            if !self.prompt_for_permission(tool_name, permission_id, arguments)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
     }
 
     async fn execute_tool_call(
@@ -247,18 +702,33 @@ impl<'a, M: Model> Session<'a, M> {
         };
         let result = match result {
             Ok(output) => {
-                self.log_debug(debug_mode, &format!("Tool result (success): {}", output));
-                Message::ToolResult { id: Some(id), output, is_error: false }
+                self.log_debug(debug_mode, &format!("Tool result (success): {}", output.text));
+                Message::ToolResult { id: Some(id), output: output.text, attachments: output.attachments, is_error: false }
             }
             Err(error) => {
                 self.log_debug(debug_mode, &format!("Tool result (error): {}", error));
-                Message::ToolResult { id: Some(id), output: error.to_string(), is_error: true }
+                Message::ToolResult { id: Some(id), output: error.to_string(), attachments: Vec::new(), is_error: true }
             }
         };
         Ok(result)
     }
 
 
+    /// Answers the tool call left without a result when a turn fails before its result was sent
+    /// back to the model, so that the saved conversation can still be resumed: providers reject a
+    /// tool call that is not followed by its result.
+    fn answer_dangling_call(&mut self) {
+        if let Some(Message::ToolCall { id, .. }) = self.message_history.last() {
+            let result = Message::ToolResult {
+                id: id.clone(),
+                output: "The turn failed before this result was sent back to the model.".to_string(),
+                attachments: Vec::new(),
+                is_error: true,
+            };
+            self.message_history.push(result);
+        }
+    }
+
     fn create_cancellation_message(&self, tool_call: &Message) -> Message {
         let Message::ToolCall { id, .. } = tool_call else {
             panic!("Expected ToolCall message");
@@ -267,6 +737,7 @@ impl<'a, M: Model> Session<'a, M> {
         Message::ToolResult {
             id: id.clone(),
             output: "Tool execution cancelled because the user denied a previous tool call in this batch. Control has been returned to the user to provide guidance on how to proceed.".to_string(),
+            attachments: Vec::new(),
             is_error: true,
         }
     }
@@ -275,6 +746,7 @@ impl<'a, M: Model> Session<'a, M> {
         Message::ToolResult {
             id: Some(id.to_string()),
             output: "The user denied this tool call. Control has been returned to the user to provide guidance on how to proceed differently.".to_string(),
+            attachments: Vec::new(),
             is_error: true,
         }
     }
@@ -292,3 +764,276 @@ impl<'a, M: Model> Session<'a, M> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        context::{ModelConfig, SessionConfig},
+        core::ModelResponse,
+        provider::Provider,
+        testing::temp_project,
+    };
+
+    /// A model that replays canned responses and records the requests it was sent.
+    struct ScriptedModel {
+        responses: Mutex<VecDeque<ModelResponse>>,
+        requests: Arc<Mutex<Vec<Message>>>,
+        /// Whether requests fail once the responses run out, instead of getting a default reply.
+        fail_when_done: bool,
+    }
+
+    impl ScriptedModel {
+        fn new(responses: Vec<ModelResponse>) -> Self {
+            Self { responses: Mutex::new(responses.into()), requests: Arc::default(), fail_when_done: false }
+        }
+    }
+
+    impl Model for ScriptedModel {
+        fn model_name(&self) -> &str {
+            "test-model"
+        }
+
+        fn set_model_name(&mut self, _model_name: String) {}
+
+        async fn send_message(&self, message: Message, _message_history: Vec<Message>, _io: &dyn IO) -> Result<ModelResponse> {
+            self.requests.lock().unwrap().push(message);
+            match self.responses.lock().unwrap().pop_front() {
+                Some(response) => Ok(response),
+                None if self.fail_when_done => Err(SessionError::Processing { reason: "connection reset".to_string() }.into()),
+                None => Ok(reply("done", 0, 0)),
+            }
+        }
+    }
+
+    /// IO that answers prompts from a script and records the messages it was shown.
+    #[derive(Default)]
+    struct ScriptedIO {
+        inputs: VecDeque<String>,
+        shown: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl IO for ScriptedIO {
+        fn show_message(&self, title: &str, text: &str) {
+            self.shown.lock().unwrap().push(format!("{}: {}", title, text));
+        }
+
+        fn show_snippet(&self, _title: &str, _text: &str) {}
+
+        fn begin_stream(&self, _title: &str) {}
+
+        fn stream_text(&self, _text: &str) {}
+
+        fn end_stream(&self) {}
+
+        fn get_user_input(&mut self, _prompt: &str) -> Result<Option<String>> {
+            Ok(self.inputs.pop_front())
+        }
+    }
+
+    fn reply(text: &str, input_tokens: u32, output_tokens: u32) -> ModelResponse {
+        ModelResponse {
+            messages: vec![Message::Model(text.to_string())],
+            usage: Some(Usage { input_tokens, output_tokens }),
+        }
+    }
+
+    fn context(dir: &std::path::Path) -> Context {
+        let model_config = ModelConfig::new(Provider::Ollama, "test-model".to_string(), false, None).unwrap();
+        Context::new(model_config, SessionConfig::for_tests(dir))
+    }
+
+    fn session<'a>(model: ScriptedModel, io: &'a mut Box<dyn IO>, context: &'a Context, dir: &std::path::Path) -> Session<'a, ScriptedModel> {
+        let mut session = Session::new(model, HashMap::new(), io, context);
+        session.store = SessionStore::in_dir(dir.join("sessions"));
+        session
+    }
+
+    #[tokio::test]
+    async fn compact_counts_towards_the_session_but_not_the_last_turn() {
+        let dir = temp_project(&[]);
+        let context = context(&dir);
+        let mut io: Box<dyn IO> = Box::new(ScriptedIO::default());
+        let model = ScriptedModel::new(vec![reply("hello", 10, 5), reply("a summary", 100, 50)]);
+        let mut session = session(model, &mut io, &context, &dir);
+
+        session.run_once("hi".to_string()).await.unwrap();
+        assert!(session.compact().await.unwrap());
+
+        assert_eq!(session.turn_usage, Usage { input_tokens: 10, output_tokens: 5 });
+        assert_eq!(session.session_usage, Usage { input_tokens: 110, output_tokens: 55 });
+        assert!(matches!(&session.messages()[0], Message::User(text) if text.contains("a summary")));
+    }
+
+    #[tokio::test]
+    async fn compacting_during_a_turn_counts_towards_it() {
+        let dir = temp_project(&[]);
+        let mut context = context(&dir);
+        context.model_config.context_budget = 20;
+        let mut io: Box<dyn IO> = Box::new(ScriptedIO::default());
+        let long = "x".repeat(200);
+        let model = ScriptedModel::new(vec![reply(&long, 10, 50), reply("a summary", 100, 5), reply("ok", 30, 1)]);
+        let mut session = session(model, &mut io, &context, &dir);
+
+        session.run_once("first".to_string()).await.unwrap();
+        let summary = session.run_once("second".to_string()).await.unwrap();
+
+        assert_eq!(summary.usage, Usage { input_tokens: 130, output_tokens: 6 });
+        assert_eq!(session.session_usage, Usage { input_tokens: 140, output_tokens: 56 });
+    }
+
+    #[tokio::test]
+    async fn slash_input_that_is_not_a_command_goes_to_the_model() {
+        let dir = temp_project(&[]);
+        let context = context(&dir);
+        let inputs = ["/etc/nginx/nginx.conf fails to parse", "/cost", "exit"];
+        let shown = Arc::default();
+        let mut io: Box<dyn IO> = Box::new(ScriptedIO { inputs: inputs.map(String::from).into(), shown: Arc::clone(&shown) });
+        let model = ScriptedModel::new(vec![reply("let me look", 10, 5)]);
+        let requests = Arc::clone(&model.requests);
+        let mut session = session(model, &mut io, &context, &dir);
+
+        session.run().await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(matches!(&requests[0], Message::User(text) if text == "/etc/nginx/nginx.conf fails to parse"));
+        let shown = shown.lock().unwrap();
+        assert!(shown.iter().any(|message| message.starts_with("Token usage: Last turn: 10 input / 5 output")));
+        assert!(!shown.iter().any(|message| message.starts_with("Unknown command")));
+    }
+
+    #[tokio::test]
+    async fn a_failed_turn_is_saved_without_an_unanswered_tool_call() {
+        let dir = temp_project(&[("file.txt", "x")]);
+        let context = context(&dir);
+        let mut io: Box<dyn IO> = Box::new(ScriptedIO::default());
+        let call = Message::ToolCall {
+            id: Some("call-1".to_string()),
+            tool_name: "find_files".to_string(),
+            arguments: serde_json::json!({ "patterns": ["*.txt"] }),
+        };
+        let mut model = ScriptedModel::new(vec![ModelResponse { messages: vec![call], usage: None }]);
+        model.fail_when_done = true;
+        let mut session = session(model, &mut io, &context, &dir);
+        let guard = crate::tools::PathGuard::new(&dir, &[]).unwrap();
+        session.tools.insert("find_files".to_string(), Box::new(crate::tools::FindFilesTool::new(guard)));
+
+        let summary = session.run_once("find the text files".to_string()).await.unwrap();
+        assert_eq!(summary.outcome, TurnOutcome::Failed);
+        assert_eq!(summary.error.as_deref(), Some("Session management error: Session processing failed: connection reset"));
+
+        let stored = session.store.load(session.session_id()).unwrap();
+        assert!(matches!(stored.messages.as_slice(), [
+            Message::User(_),
+            Message::ToolCall { .. },
+            Message::ToolResult { id: Some(id), is_error: true, .. },
+        ] if id == "call-1"), "{:?}", stored.messages);
+        session.resume(stored);
+        assert_eq!(session.turns(), vec![(1, "find the text files", 0)]);
+    }
+
+    #[tokio::test]
+    async fn resumed_summaries_are_not_numbered_as_turns() {
+        let dir = temp_project(&[]);
+        let context = context(&dir);
+        let mut io: Box<dyn IO> = Box::new(ScriptedIO::default());
+        let mut session = session(ScriptedModel::new(Vec::new()), &mut io, &context, &dir);
+
+        session.resume(StoredSession {
+            version: store::FORMAT_VERSION,
+            id: "stored".to_string(),
+            cwd: dir.display().to_string(),
+            provider: "ollama".to_string(),
+            model: "test-model".to_string(),
+            created_at: 0,
+            updated_at: 0,
+            usage: Usage::default(),
+            messages: vec![
+                Message::User("<conversation_summary>\nEarlier parts...\n</conversation_summary>".to_string()),
+                Message::Model("noted".to_string()),
+                Message::User("second".to_string()),
+                Message::Model("ok".to_string()),
+            ],
+        });
+
+        assert_eq!(session.turns(), vec![(1, "second", 0)]);
+        assert_eq!(session.next_turn, 2);
+    }
+
+    #[tokio::test]
+    async fn rewinding_past_a_resume_reports_that_earlier_changes_stay() {
+        let dir = temp_project(&[]);
+        let context = context(&dir);
+        let mut io: Box<dyn IO> = Box::new(ScriptedIO::default());
+        let mut session = session(ScriptedModel::new(Vec::new()), &mut io, &context, &dir);
+        session.resume(StoredSession {
+            version: store::FORMAT_VERSION,
+            id: "stored".to_string(),
+            cwd: dir.display().to_string(),
+            provider: "ollama".to_string(),
+            model: "test-model".to_string(),
+            created_at: 0,
+            updated_at: 0,
+            usage: Usage::default(),
+            messages: vec![Message::User("first".to_string()), Message::Model("ok".to_string())],
+        });
+        session.run_once("second".to_string()).await.unwrap();
+
+        assert!(matches!(session.rewind(2).unwrap(), RewindOutcome::Rewound(Rewind { before_resume: false, .. })));
+        assert!(matches!(session.rewind(1).unwrap(), RewindOutcome::Rewound(Rewind { before_resume: true, .. })));
+        assert!(session.turns().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rewind_asks_before_discarding_changes_made_outside_deputy() {
+        let dir = temp_project(&[]);
+        let context = context(&dir);
+        let inputs = ["n", "y"];
+        let mut io: Box<dyn IO> = Box::new(ScriptedIO { inputs: inputs.map(String::from).into(), shown: Arc::default() });
+        let mut session = session(ScriptedModel::new(Vec::new()), &mut io, &context, &dir);
+        session.run_once("edit the file".to_string()).await.unwrap();
+        let file = dir.join("file.txt");
+        std::fs::write(&file, "by deputy").unwrap();
+        session.checkpoints.record(&file, Some("original".to_string()), "by deputy".to_string());
+        std::fs::write(&file, "by the user").unwrap();
+
+        assert!(matches!(session.rewind(1).unwrap(), RewindOutcome::Cancelled));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "by the user");
+        assert_eq!(session.turns().len(), 1);
+
+        assert!(matches!(session.rewind(1).unwrap(), RewindOutcome::Rewound(_)));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "original");
+        assert!(session.turns().is_empty());
+    }
+
+    #[tokio::test]
+    async fn read_only_tools_run_unattended_unless_a_rule_denies_them() {
+        let dir = temp_project(&[]);
+        let mut context = context(&dir);
+        context.model_config.unattended_policy = Some(UnattendedPolicy::Deny);
+        let mut io: Box<dyn IO> = Box::new(ScriptedIO::default());
+        let mut session = session(ScriptedModel::new(Vec::new()), &mut io, &context, &dir);
+        let guard = crate::tools::PathGuard::new(&dir, &[]).unwrap();
+        session.tools.insert("search_files".to_string(), Box::new(crate::tools::SearchFilesTool::new(guard.clone())));
+        session.tools.insert("read_files".to_string(), Box::new(crate::tools::ReadFilesTool::new(Arc::default(), guard.clone())));
+        session.tools.insert("write_file".to_string(), Box::new(crate::tools::WriteFileTool::new(Arc::default(), Arc::default(), guard)));
+        let arguments = serde_json::json!({ "pattern": "x", "path": "secrets" });
+
+        assert!(session.authorize_tool_execution("search_files", &["search_files".to_string()], &arguments, false).unwrap());
+        let outside = ["search_files".to_string(), "outside the project: /etc".to_string()];
+        assert!(!session.authorize_tool_execution("search_files", &outside, &arguments, false).unwrap());
+        assert!(session.authorize_tool_execution("read_files", &["read_files".to_string()], &serde_json::json!({ "paths": ["a"] }), false).unwrap());
+        assert!(!session.authorize_tool_execution("write_file", &["write_file".to_string()], &serde_json::json!({ "path": "a" }), false).unwrap());
+
+        let secrets = serde_json::json!({ "pattern": "x", "path": "secrets/keys" });
+        let mut rule = session.policy.rule_for("search_files", "search_files", &secrets);
+        rule.action = PolicyAction::Deny;
+        rule.path = Some("secrets/**".to_string());
+        session.policy.persist(rule, PolicyScope::Project).unwrap();
+        assert!(!session.authorize_tool_execution("search_files", &["search_files".to_string()], &secrets, false).unwrap());
+    }
+}
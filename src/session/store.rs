@@ -0,0 +1,244 @@
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{Message, Usage},
+    error::{Result, SessionError},
+};
+
+/// Version of the on-disk session format; bump this whenever `StoredSession` changes shape.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A conversation as persisted to disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredSession {
+    pub version: u32,
+    pub id: String,
+    pub cwd: String,
+    pub provider: String,
+    pub model: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    /// Tokens used across all runs of this session; absent in files written before it was tracked.
+    #[serde(default)]
+    pub usage: Usage,
+    pub messages: Vec<Message>,
+}
+
+impl StoredSession {
+    /// Returns the first user message of the conversation, for display in session listings.
+    pub fn title(&self) -> String {
+        self.messages
+            .iter()
+            .find_map(|m| match m {
+                Message::User(text) => Some(text.lines().next().unwrap_or_default().to_string()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Reads and writes sessions as JSON files in the deputy config directory.
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        let dir = if let Some(config_dir) = dirs::config_dir() {
+            config_dir.join("deputy").join("sessions")
+        } else {
+            PathBuf::from(".deputy_sessions")
+        };
+        Self { dir }
+    }
+
+    /// Creates a store that keeps its files in `dir`.
+    #[cfg(test)]
+    pub fn in_dir(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Generates a new, time-based session id.
+    pub fn new_id() -> String {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        format!("{:x}", millis)
+    }
+
+    /// Returns the current time as seconds since the unix epoch.
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, session: &StoredSession) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| SessionError::Storage {
+            reason: format!("failed to create {}: {}", self.dir.display(), e)
+        })?;
+
+        let path = self.path_for(&session.id);
+        let tmp_path = path.with_extension("json.tmp");
+        let data = serde_json::to_vec_pretty(session)?;
+        std::fs::write(&tmp_path, data).map_err(|e| SessionError::Storage {
+            reason: format!("failed to write {}: {}", tmp_path.display(), e)
+        })?;
+        std::fs::rename(&tmp_path, &path).map_err(|e| SessionError::Storage {
+            reason: format!("failed to write {}: {}", path.display(), e)
+        })?;
+        Ok(())
+    }
+
+    /// Deletes a saved session; succeeds if it was never saved in the first place.
+    pub fn remove(&self, id: &str) -> Result<()> {
+        match std::fs::remove_file(self.path_for(id)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(SessionError::Storage {
+                reason: format!("session {}: {}", id, e)
+            }.into()),
+        }
+    }
+
+    pub fn load(&self, id: &str) -> Result<StoredSession> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(SessionError::Storage {
+                reason: format!("invalid session id: {}", id)
+            }.into());
+        }
+        let path = self.path_for(id);
+        let data = std::fs::read_to_string(&path).map_err(|e| SessionError::Storage {
+            reason: format!("session {}: {}", id, e)
+        })?;
+        Self::parse(&data).map_err(|reason| SessionError::Storage {
+            reason: format!("session {}: {}", id, reason)
+        }.into())
+    }
+
+    /// Lists the sessions that were started from `cwd`, most recently updated first.
+    /// Files that cannot be read or parsed are skipped.
+    pub fn list_for(&self, cwd: &str) -> Result<Vec<StoredSession>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(SessionError::Storage {
+                    reason: format!("failed to read {}: {}", self.dir.display(), e)
+                }.into());
+            }
+        };
+
+        let mut sessions: Vec<StoredSession> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|path| std::fs::read_to_string(path).ok())
+            .filter_map(|data| Self::parse(&data).ok())
+            .filter(|session| session.cwd == cwd)
+            .collect();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.updated_at));
+        Ok(sessions)
+    }
+
+    fn parse(data: &str) -> std::result::Result<StoredSession, String> {
+        let value: serde_json::Value = serde_json::from_str(data).map_err(|e| e.to_string())?;
+        let version = value
+            .get("version")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| "missing format version".to_string())?;
+        if version != FORMAT_VERSION as u64 {
+            return Err(format!(
+                "unsupported format version {} (expected {})",
+                version, FORMAT_VERSION
+            ));
+        }
+        serde_json::from_value(value).map_err(|e| e.to_string())
+    }
+
+    fn path_for(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TempProject, temp_project};
+
+    /// A store in a temporary directory, which is removed when the first value is dropped.
+    fn temp_store() -> (TempProject, SessionStore) {
+        let dir = temp_project(&[]);
+        let store = SessionStore::in_dir(dir.join("sessions"));
+        (dir, store)
+    }
+
+    fn session(id: &str, cwd: &str, updated_at: u64) -> StoredSession {
+        StoredSession {
+            version: FORMAT_VERSION,
+            id: id.to_string(),
+            cwd: cwd.to_string(),
+            provider: "anthropic".to_string(),
+            model: "model".to_string(),
+            created_at: 1,
+            updated_at,
+            usage: Usage { input_tokens: 3, output_tokens: 4 },
+            messages: vec![
+                Message::User("first line\nsecond line".to_string()),
+                Message::ToolCall { id: Some("t".to_string()), tool_name: "read_files".to_string(), arguments: serde_json::json!({}) },
+                Message::ToolResult { id: Some("t".to_string()), output: "out".to_string(), attachments: Vec::new(), is_error: false },
+                Message::Model("answer".to_string()),
+            ],
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_a_session() {
+        let (_dir, store) = temp_store();
+        store.save(&session("abc1", "/project", 5)).unwrap();
+
+        let loaded = store.load("abc1").unwrap();
+        assert_eq!(loaded.messages.len(), 4);
+        assert_eq!(loaded.usage, Usage { input_tokens: 3, output_tokens: 4 });
+        assert_eq!(loaded.title(), "first line");
+
+        store.remove("abc1").unwrap();
+        assert!(store.load("abc1").is_err());
+        store.remove("abc1").unwrap();
+    }
+
+    #[test]
+    fn lists_sessions_of_a_directory_most_recent_first() {
+        let (_dir, store) = temp_store();
+        store.save(&session("a1", "/project", 5)).unwrap();
+        store.save(&session("a2", "/project", 9)).unwrap();
+        store.save(&session("a3", "/elsewhere", 7)).unwrap();
+        std::fs::write(store.dir.join("broken.json"), "{").unwrap();
+
+        let ids: Vec<String> = store.list_for("/project").unwrap().into_iter().map(|s| s.id).collect();
+        assert_eq!(ids, vec!["a2", "a1"]);
+    }
+
+    #[test]
+    fn refuses_invalid_ids_and_unknown_versions() {
+        let (_dir, store) = temp_store();
+        assert!(store.load("../etc/passwd").is_err());
+        assert!(store.load("").is_err());
+
+        let mut data = serde_json::to_value(session("v", "/p", 1)).unwrap();
+        data["version"] = serde_json::json!(FORMAT_VERSION + 1);
+        assert!(SessionStore::parse(&data.to_string()).unwrap_err().contains("unsupported format version"));
+    }
+
+    #[test]
+    fn reads_files_written_before_usage_was_tracked() {
+        let mut data = serde_json::to_value(session("old", "/p", 1)).unwrap();
+        data.as_object_mut().unwrap().remove("usage");
+        assert_eq!(SessionStore::parse(&data.to_string()).unwrap().usage, Usage::default());
+    }
+}
@@ -0,0 +1,95 @@
+//! Fixtures shared by the unit tests.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::tools::resolve_path;
+
+/// A temporary directory that is removed when dropped, also when a test fails. Dereferences to
+/// its resolved path, so that it compares equal to paths the tools resolve.
+pub struct TempProject {
+    _dir: TempDir,
+    path: PathBuf,
+}
+
+impl Deref for TempProject {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TempProject {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Creates a temporary directory holding `files`, given as paths relative to it and their
+/// content; parent directories are created as needed.
+pub fn temp_project(files: &[(&str, &str)]) -> TempProject {
+    let dir = tempfile::Builder::new().prefix("deputy-test-").tempdir().unwrap();
+    let path = resolve_path(dir.path());
+    for (file, content) in files {
+        let file = path.join(file);
+        std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+        std::fs::write(file, content).unwrap();
+    }
+    TempProject { _dir: dir, path }
+}
+
+/// Answers a single HTTP request on a local port with `status` and `body`, standing in for a
+/// model provider's API. Returns the base url to reach it and a handle that yields the request as
+/// received. The body is written in two parts, so that clients see it arrive in pieces.
+pub async fn serve_once(status: u16, content_type: &str, body: &str) -> (String, tokio::task::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+    let head = format!(
+        "HTTP/1.1 {} Stub\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    let body = body.as_bytes().to_vec();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let request = read_request(&mut stream).await;
+        let (first, rest) = body.split_at(body.len() / 2);
+        for part in [head.as_bytes(), first, rest] {
+            stream.write_all(part).await.unwrap();
+            stream.flush().await.unwrap();
+            tokio::task::yield_now().await;
+        }
+        stream.shutdown().await.unwrap();
+        request
+    });
+    (base_url, server)
+}
+
+/// Reads a request's head and as much of its body as its `content-length` announces.
+async fn read_request(stream: &mut TcpStream) -> String {
+    let mut request = Vec::new();
+    let mut buffer = [0; 8192];
+    loop {
+        let read = stream.read(&mut buffer).await.unwrap();
+        request.extend_from_slice(&buffer[..read]);
+        let text = String::from_utf8_lossy(&request).into_owned();
+        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+            let length = head
+                .lines()
+                .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|value| value.trim().parse().unwrap()))
+                .unwrap_or(0);
+            if body.len() >= length {
+                return text;
+            }
+        }
+        if read == 0 {
+            return text;
+        }
+    }
+}
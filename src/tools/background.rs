@@ -0,0 +1,567 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::process::Command;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, Once};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Child;
+
+use super::Sandbox;
+use super::process::{self, Completion, OutputBuffer};
+use crate::{core::{interrupt, shell, Tool, ToolOutput}, error::{ToolError, Result}, io::IO};
+
+/// Longest a single read_process_output call may wait for new output.
+const MAX_WAIT_SECS: u64 = 60;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Process groups of every background process that is still running, so that they can be killed
+/// when Ctrl-C makes deputy exit without dropping its session.
+static RUNNING_GROUPS: LazyLock<Mutex<BTreeSet<u32>>> = LazyLock::new(|| Mutex::new(BTreeSet::new()));
+static EXIT_HOOK: Once = Once::new();
+
+fn kill_running_groups() {
+    if let Ok(groups) = RUNNING_GROUPS.lock() {
+        groups.iter().copied().for_each(process::kill_group);
+    }
+}
+
+/// Output a background process produced since it was last read.
+#[derive(Default)]
+struct UnreadOutput {
+    stdout: OutputBuffer,
+    stderr: OutputBuffer,
+    open_streams: usize,
+}
+
+struct BackgroundProcess {
+    command: String,
+    child: Child,
+    pid: Option<u32>,
+    started: Instant,
+    output: Arc<Mutex<UnreadOutput>>,
+}
+
+impl BackgroundProcess {
+    /// "running", or how the process ended.
+    fn status(&mut self) -> String {
+        match self.child.try_wait() {
+            Ok(None) => "running".to_string(),
+            Ok(Some(status)) => {
+                self.forget_group();
+                format!("exited (exit code {})", Completion::Exited(status))
+            }
+            Err(e) => format!("unknown ({})", e),
+        }
+    }
+
+    fn kill(&mut self) {
+        process::kill(&mut self.child);
+        self.forget_group();
+    }
+
+    fn forget_group(&self) {
+        if let (Some(pid), Ok(mut groups)) = (self.pid, RUNNING_GROUPS.lock()) {
+            groups.remove(&pid);
+        }
+    }
+}
+
+/// The processes the model started in the background during a session. They are shared by the
+/// background process tools and killed, together with everything they spawned, when the session
+/// ends and drops its tools.
+pub struct BackgroundProcesses {
+    sandbox: Option<Sandbox>,
+    processes: Mutex<BTreeMap<u32, BackgroundProcess>>,
+    next_id: AtomicU32,
+}
+
+impl BackgroundProcesses {
+    pub fn new(sandbox: Option<Sandbox>) -> Self {
+        EXIT_HOOK.call_once(|| interrupt::on_exit(kill_running_groups));
+        Self {
+            sandbox,
+            processes: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU32::new(1),
+        }
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, BTreeMap<u32, BackgroundProcess>>> {
+        self.processes.lock().map_err(|_| ToolError::ExecutionFailed {
+            reason: "background processes: state is poisoned".to_string()
+        }.into())
+    }
+
+    fn start(&self, command: &str) -> Result<(u32, Option<u32>)> {
+        let spawned = match &self.sandbox {
+            Some(sandbox) => sandbox.command(command),
+            None => {
+                let mut spawned = Command::new("sh");
+                spawned.arg("-c").arg(command);
+                spawned
+            }
+        };
+        let mut spawned = tokio::process::Command::from(spawned);
+        process::prepare(&mut spawned);
+        let mut child = spawned.spawn().map_err(|e| ToolError::ExecutionFailed {
+            reason: format!("start_process: {}", e)
+        })?;
+
+        let output = Arc::new(Mutex::new(UnreadOutput::default()));
+        if let Some(stdout) = child.stdout.take() {
+            Self::collect(stdout, output.clone(), |unread| &mut unread.stdout);
+        }
+        if let Some(stderr) = child.stderr.take() {
+            Self::collect(stderr, output.clone(), |unread| &mut unread.stderr);
+        }
+
+        let pid = child.id();
+        if let (Some(pid), Ok(mut groups)) = (pid, RUNNING_GROUPS.lock()) {
+            groups.insert(pid);
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.lock()?.insert(id, BackgroundProcess {
+            command: command.to_string(),
+            child,
+            pid,
+            started: Instant::now(),
+            output,
+        });
+        Ok((id, pid))
+    }
+
+    /// Copies a stream into the process's unread output until it closes.
+    fn collect(
+        mut stream: impl AsyncRead + Unpin + Send + 'static,
+        output: Arc<Mutex<UnreadOutput>>,
+        buffer: fn(&mut UnreadOutput) -> &mut OutputBuffer,
+    ) {
+        if let Ok(mut unread) = output.lock() {
+            unread.open_streams += 1;
+        }
+        tokio::spawn(async move {
+            let mut chunk = [0u8; 8192];
+            while let Ok(read) = stream.read(&mut chunk).await {
+                if read == 0 {
+                    break;
+                }
+                if let Ok(mut unread) = output.lock() {
+                    buffer(&mut unread).push(&chunk[..read]);
+                }
+            }
+            if let Ok(mut unread) = output.lock() {
+                unread.open_streams -= 1;
+            }
+        });
+    }
+
+    /// Waits up to `wait` for output if there is none yet, then takes everything unread.
+    async fn read(&self, id: u32, wait: Duration) -> Result<String> {
+        let output = self.get(id, |process| process.output.clone())?;
+        let deadline = Instant::now() + wait;
+        loop {
+            let ready = output
+                .lock()
+                .map(|unread| unread.open_streams == 0 || !unread.stdout.is_empty() || !unread.stderr.is_empty())
+                .unwrap_or(true);
+            if ready || Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        let (command, status, uptime) = self.get(id, |process| {
+            (process.command.clone(), process.status(), process.started.elapsed())
+        })?;
+        let (stdout, stderr) = output
+            .lock()
+            .map(|mut unread| (std::mem::take(&mut unread.stdout), std::mem::take(&mut unread.stderr)))
+            .unwrap_or_default();
+        Ok(format!(
+            "id: {}\ncommand: {}\nstatus: {}\nuptime: {:.2}s\ntruncated: {}\nstdout:\n{}\nstderr:\n{}",
+            id,
+            command,
+            status,
+            uptime.as_secs_f64(),
+            stdout.is_truncated() || stderr.is_truncated(),
+            stdout.render(),
+            stderr.render()
+        ))
+    }
+
+    fn get<T>(&self, id: u32, f: impl FnOnce(&mut BackgroundProcess) -> T) -> Result<T> {
+        let mut processes = self.lock()?;
+        let process = processes.get_mut(&id).ok_or_else(|| ToolError::InvalidArguments {
+            reason: format!("read_process_output: no background process with id {}; use list_processes to see them", id)
+        })?;
+        Ok(f(process))
+    }
+
+    fn list(&self) -> Result<String> {
+        let mut processes = self.lock()?;
+        if processes.is_empty() {
+            return Ok("No background processes.".to_string());
+        }
+        Ok(processes
+            .iter_mut()
+            .map(|(id, process)| {
+                let status = process.status();
+                format!(
+                    "{}: {} [{}, started {:.0}s ago]",
+                    id,
+                    process.command,
+                    status,
+                    process.started.elapsed().as_secs_f64()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n"))
+    }
+
+    /// Kills the process and forgets it, returning its command and any output it left unread.
+    async fn kill(&self, id: u32) -> Result<(String, String)> {
+        let mut process = {
+            let mut processes = self.lock()?;
+            processes.remove(&id).ok_or_else(|| ToolError::InvalidArguments {
+                reason: format!("kill_process: no background process with id {}; use list_processes to see them", id)
+            })?
+        };
+        process.kill();
+        let _ = process.child.wait().await;
+        // give the readers a moment to drain what the process wrote before it died
+        tokio::time::sleep(POLL_INTERVAL).await;
+        // the readers keep counting their open streams, which outlive the process if something
+        // it started still holds them
+        let (stdout, stderr) = process
+            .output
+            .lock()
+            .map(|mut unread| (std::mem::take(&mut unread.stdout), std::mem::take(&mut unread.stderr)))
+            .unwrap_or_default();
+        Ok((
+            process.command.clone(),
+            format!("stdout:\n{}\nstderr:\n{}", stdout.render(), stderr.render()),
+        ))
+    }
+}
+
+impl Drop for BackgroundProcesses {
+    fn drop(&mut self) {
+        if let Ok(processes) = self.processes.get_mut() {
+            processes.values_mut().for_each(BackgroundProcess::kill);
+        }
+    }
+}
+
+pub struct StartProcessTool {
+    processes: Arc<BackgroundProcesses>,
+}
+
+impl StartProcessTool {
+    pub fn new(processes: Arc<BackgroundProcesses>) -> Self {
+        Self { processes }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StartInput {
+    command: String,
+}
+
+impl Tool for StartProcessTool {
+    fn name(&self) -> String {
+        "start_process".to_owned()
+    }
+
+    fn description(&self) -> String {
+        let mut description = "Start a bash command in the background and return its id without waiting for it to finish, e.g. a dev server, file watcher or database. Read its output with read_process_output and stop it with kill_process once you no longer need it; background processes are also killed when the session ends. Use exec_command for commands that finish on their own.".to_owned();
+        if let Some(sandbox) = &self.processes.sandbox {
+            description.push_str(&format!(" {}", sandbox.describe()));
+        }
+        description
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "command": {
+                    "type": "string",
+                    "description": "The bash command to start. It cannot read from stdin."
+                }
+            },
+            "required": ["command"]
+        })
+    }
+
+    fn ask_permission(&self, args: serde_json::Value, io: &mut Box<dyn IO>) {
+        let input: StartInput = serde_json::from_value(args).unwrap_or(StartInput { command: "<invalid command>".to_string() });
+        io.show_message(
+            "deputy wants to start the following command in the background",
+            &input.command,
+        );
+    }
+
+    fn permission_ids(&self, args: serde_json::Value) -> Result<Vec<String>> {
+        let input: StartInput = serde_json::from_value(args)
+            .map_err(|e| ToolError::InvalidArguments {
+                reason: format!("start_process: {}", e)
+            })?;
+        Ok(shell::permission_ids(&input.command))
+    }
+
+    fn call<'a>(
+        &'a self,
+        args: serde_json::Value,
+        io: &'a mut Box<dyn IO>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ToolOutput>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            let input: StartInput = serde_json::from_value(args)
+                .map_err(|e| ToolError::InvalidArguments {
+                    reason: format!("start_process: {}", e)
+                })?;
+
+            let (id, pid) = self.processes.start(&input.command)?;
+            io.show_snippet(&format!("deputy started {} in the background", &input.command), &format!("process {}", id));
+            Ok(format!(
+                "Started process {}{}. Use read_process_output with this id to see its output.",
+                id,
+                pid.map(|pid| format!(" (pid {})", pid)).unwrap_or_default()
+            ).into())
+        })
+    }
+}
+
+pub struct ReadProcessOutputTool {
+    processes: Arc<BackgroundProcesses>,
+}
+
+impl ReadProcessOutputTool {
+    pub fn new(processes: Arc<BackgroundProcesses>) -> Self {
+        Self { processes }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReadInput {
+    id: u32,
+    wait: Option<u64>,
+}
+
+impl Tool for ReadProcessOutputTool {
+    fn name(&self) -> String {
+        "read_process_output".to_owned()
+    }
+
+    fn description(&self) -> String {
+        "Read the output a background process has produced since the previous read, together with whether it is still running. Each call returns only new output.".to_owned()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "id": {
+                    "type": "integer",
+                    "description": "The id returned by start_process."
+                },
+                "wait": {
+                    "type": "integer",
+                    "description": "If there is no new output yet, wait up to this many seconds for some to arrive (default 0, maximum 60). Useful right after starting a server."
+                }
+            },
+            "required": ["id"]
+        })
+    }
+
+    fn ask_permission(&self, args: serde_json::Value, io: &mut Box<dyn IO>) {
+        let id = args.get("id").map(|id| id.to_string()).unwrap_or_else(|| "<invalid>".to_string());
+        io.show_message(
+            "Permission request",
+            &format!("deputy wants to read the output of background process {}", id),
+        );
+    }
+
+    fn permission_ids(&self, _args: serde_json::Value) -> Result<Vec<String>> {
+        Ok(vec![String::from("read_process_output")])
+    }
+
+    fn call<'a>(
+        &'a self,
+        args: serde_json::Value,
+        io: &'a mut Box<dyn IO>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ToolOutput>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            let input: ReadInput = serde_json::from_value(args)
+                .map_err(|e| ToolError::InvalidArguments {
+                    reason: format!("read_process_output: {}", e)
+                })?;
+
+            let wait = Duration::from_secs(input.wait.unwrap_or(0).min(MAX_WAIT_SECS));
+            let report = self.processes.read(input.id, wait).await?;
+            io.show_snippet(&format!("deputy read the output of process {}", input.id), "");
+            Ok(report.into())
+        })
+    }
+}
+
+pub struct ListProcessesTool {
+    processes: Arc<BackgroundProcesses>,
+}
+
+impl ListProcessesTool {
+    pub fn new(processes: Arc<BackgroundProcesses>) -> Self {
+        Self { processes }
+    }
+}
+
+impl Tool for ListProcessesTool {
+    fn name(&self) -> String {
+        "list_processes".to_owned()
+    }
+
+    fn description(&self) -> String {
+        "List the background processes started with start_process, with their ids, commands and whether they are still running.".to_owned()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {}
+        })
+    }
+
+    fn ask_permission(&self, _args: serde_json::Value, io: &mut Box<dyn IO>) {
+        io.show_message("Permission request", "deputy wants to list its background processes");
+    }
+
+    fn permission_ids(&self, _args: serde_json::Value) -> Result<Vec<String>> {
+        Ok(vec![String::from("list_processes")])
+    }
+
+    fn call<'a>(
+        &'a self,
+        _args: serde_json::Value,
+        io: &'a mut Box<dyn IO>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ToolOutput>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            let list = self.processes.list()?;
+            io.show_snippet("deputy listed its background processes", &list);
+            Ok(list.into())
+        })
+    }
+}
+
+pub struct KillProcessTool {
+    processes: Arc<BackgroundProcesses>,
+}
+
+impl KillProcessTool {
+    pub fn new(processes: Arc<BackgroundProcesses>) -> Self {
+        Self { processes }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct KillInput {
+    id: u32,
+}
+
+impl Tool for KillProcessTool {
+    fn name(&self) -> String {
+        "kill_process".to_owned()
+    }
+
+    fn description(&self) -> String {
+        "Stop a background process, together with everything it started, and forget it. Returns the output it produced since it was last read.".to_owned()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "id": {
+                    "type": "integer",
+                    "description": "The id returned by start_process."
+                }
+            },
+            "required": ["id"]
+        })
+    }
+
+    fn ask_permission(&self, args: serde_json::Value, io: &mut Box<dyn IO>) {
+        let id = args.get("id").map(|id| id.to_string()).unwrap_or_else(|| "<invalid>".to_string());
+        io.show_message(
+            "Permission request",
+            &format!("deputy wants to kill background process {}", id),
+        );
+    }
+
+    fn permission_ids(&self, _args: serde_json::Value) -> Result<Vec<String>> {
+        Ok(vec![String::from("kill_process")])
+    }
+
+    fn call<'a>(
+        &'a self,
+        args: serde_json::Value,
+        io: &'a mut Box<dyn IO>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ToolOutput>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            let input: KillInput = serde_json::from_value(args)
+                .map_err(|e| ToolError::InvalidArguments {
+                    reason: format!("kill_process: {}", e)
+                })?;
+
+            let (command, output) = self.processes.kill(input.id).await?;
+            io.show_snippet(&format!("deputy stopped {}", command), &format!("process {}", input.id));
+            Ok(format!("Killed process {} ({}).\n{}", input.id, command, output).into())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_output_as_it_arrives() {
+        let processes = BackgroundProcesses::new(None);
+        let (id, _) = processes.start("echo first; sleep 0.3; echo second").unwrap();
+
+        let report = processes.read(id, Duration::from_secs(5)).await.unwrap();
+        assert!(report.contains("status: running\n"), "{}", report);
+        assert!(report.contains("stdout:\nfirst\n"), "{}", report);
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        let report = processes.read(id, Duration::from_secs(5)).await.unwrap();
+        assert!(report.contains("status: exited (exit code 0)\n"), "{}", report);
+        assert!(report.contains("stdout:\nsecond\n"), "{}", report);
+    }
+
+    #[tokio::test]
+    async fn killing_leaves_streams_held_by_escaped_children_counted() {
+        let processes = BackgroundProcesses::new(None);
+        // the detached sleep escapes the process group and keeps stdout open after the kill
+        let (id, _) = processes.start("setsid sleep 1 & echo started; sleep 30").unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let output = processes.get(id, |process| process.output.clone()).unwrap();
+
+        let (command, unread) = processes.kill(id).await.unwrap();
+        assert_eq!(command, "setsid sleep 1 & echo started; sleep 30");
+        assert!(unread.starts_with("stdout:\nstarted\n"), "{}", unread);
+        assert!(processes.list().unwrap().starts_with("No background processes."));
+
+        tokio::time::sleep(Duration::from_millis(1_500)).await;
+        let unread = output.lock().expect("a reader panicked while holding the output");
+        assert_eq!(unread.open_streams, 0);
+    }
+
+    #[test]
+    fn refuses_unknown_ids() {
+        let processes = BackgroundProcesses::new(None);
+        assert!(processes.get(7, |_| ()).is_err());
+    }
+}
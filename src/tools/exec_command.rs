@@ -1,13 +1,25 @@
 use serde::Deserialize;
 use std::process::Command;
+use std::time::Duration;
 
-use crate::{core::Tool, error::{ToolError, Result}, io::IO};
+use super::Sandbox;
+use super::process::{self, Completion, DEFAULT_TIMEOUT_SECS, MAX_TIMEOUT_SECS};
+use crate::{core::{shell, Tool, ToolOutput}, error::{ToolError, Result}, io::IO};
 
-pub struct ExecCommandTool;
+pub struct ExecCommandTool {
+    sandbox: Option<Sandbox>,
+}
+
+impl ExecCommandTool {
+    pub fn new(sandbox: Option<Sandbox>) -> Self {
+        Self { sandbox }
+    }
+}
 
 #[derive(Deserialize, Debug)]
 pub struct Input {
     command: String,
+    timeout: Option<u64>,
 }
 
 impl Tool for ExecCommandTool {
@@ -16,7 +28,11 @@ impl Tool for ExecCommandTool {
     }
 
     fn description(&self) -> String {
-        "Execute a bash command in the current working directory.".to_owned()
+        let mut description = "Execute a bash command in the current working directory. The result reports the exit code, duration, stdout and stderr, whether or not the command succeeded.".to_owned();
+        if let Some(sandbox) = &self.sandbox {
+            description.push_str(&format!(" {}", sandbox.describe()));
+        }
+        description
     }
 
     fn input_schema(&self) -> serde_json::Value {
@@ -26,6 +42,10 @@ impl Tool for ExecCommandTool {
                 "command": {
                     "type": "string",
                     "description": "The bash command to execute."
+                },
+                "timeout": {
+                    "type": "integer",
+                    "description": "Seconds after which the command is killed (default 120, maximum 600). Commands that never exit on their own, such as dev servers or `tail -f`, will run into it."
                 }
             },
             "required": ["command"]
@@ -33,26 +53,26 @@ impl Tool for ExecCommandTool {
     }
 
     fn ask_permission(&self, args: serde_json::Value, io: &mut Box<dyn IO>) {
-        let input: Input = serde_json::from_value(args).unwrap_or(Input { command: "<invalid command>".to_string() });
+        let input: Input = serde_json::from_value(args).unwrap_or(Input { command: "<invalid command>".to_string(), timeout: None });
         io.show_message(
             "deputy wants to execute the following command",
             &input.command,
         );
     }
 
-    fn permission_id(&self, args: serde_json::Value) -> Result<String> {
+    fn permission_ids(&self, args: serde_json::Value) -> Result<Vec<String>> {
         let input: Input = serde_json::from_value(args)
             .map_err(|e| ToolError::InvalidArguments {
                 reason: format!("exec_command: {}", e)
             })?;
-        Ok(input.command.split_whitespace().take(1).collect())
+        Ok(shell::permission_ids(&input.command))
     }
 
     fn call<'a>(
         &'a self,
         args: serde_json::Value,
         io: &'a mut Box<dyn IO>,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String>> + Send + 'a>>
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ToolOutput>> + Send + 'a>>
     {
         Box::pin(async move {
             let input: Input = serde_json::from_value(args)
@@ -60,53 +80,33 @@ impl Tool for ExecCommandTool {
                     reason: format!("exec_command: {}", e)
                 })?;
 
-            let output = Command::new("sh")
-                .arg("-c")
-                .arg(&input.command)
-                .output()
+            let timeout_secs = input.timeout.unwrap_or(DEFAULT_TIMEOUT_SECS).clamp(1, MAX_TIMEOUT_SECS);
+
+            let command = match &self.sandbox {
+                Some(sandbox) => sandbox.command(&input.command),
+                None => {
+                    let mut command = Command::new("sh");
+                    command.arg("-c").arg(&input.command);
+                    command
+                }
+            };
+            let mut command = tokio::process::Command::from(command);
+            process::prepare(&mut command);
+            let output = process::run_to_completion(command, Duration::from_secs(timeout_secs))
+                .await
                 .map_err(|e| ToolError::ExecutionFailed {
                     reason: format!("exec_command: {}", e)
                 })?;
 
-            if !output.status.success() {
-                return Err(ToolError::ExecutionFailed {
-                    reason: format!("command '{}' failed with exit code {}: {}", input.command, output.status.code().unwrap_or(-1), String::from_utf8_lossy(&output.stderr))
-                }.into());
-            }
-
-            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-
-            let mut result = String::new();
-            if !stdout.is_empty() {
-                result.push_str(&format!("STDOUT:\n{}", stdout));
-            }
-            if !stderr.is_empty() {
-                if !result.is_empty() {
-                    result.push('\n');
-                }
-                result.push_str(&format!("STDERR:\n{}", stderr));
-            }
-
-            let output = if !stderr.is_empty() {
-                stderr
-                    .lines()
-                    .map(|line| {
-                        let mut s = String::new();
-                        s.push_str("\x1b[31m");
-                        s.push_str(line);
-                        s.push_str("\x1b[0m");
-                        s
-                    })
-                    .collect::<Vec<String>>()
-                    .join("\n")
-            } else {
-                stdout.lines().take(10).collect::<Vec<&str>>().join("\n")
+            let title = match &output.completion {
+                Completion::TimedOut => format!("deputy stopped {} after {}s", &input.command, timeout_secs),
+                Completion::Cancelled => format!("deputy stopped {}", &input.command),
+                _ if output.success() => format!("deputy is running {}", &input.command),
+                completion => format!("deputy ran {} (exit code {})", &input.command, completion),
             };
+            io.show_snippet(&title, &process::preview(&output));
 
-            io.show_snippet(&format!("deputy is running {}", &input.command), &output);
-
-            Ok(result)
+            Ok(output.report().into())
         })
     }
 }
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::error::{ToolError, Result};
+
+/// What a file looked like when the model last read or wrote it.
+#[derive(Debug, Clone, PartialEq)]
+struct FileState {
+    hash: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileState {
+    fn of(path: &Path, content: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        Self {
+            hash: hasher.finish(),
+            modified: std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok(),
+        }
+    }
+}
+
+/// Remembers the content of every file the model has seen, so that writes based on an outdated
+/// view of a file, e.g. after the user edited it in the meantime, can be refused.
+#[derive(Debug, Default)]
+pub struct FileTracker {
+    files: Mutex<HashMap<PathBuf, FileState>>,
+}
+
+impl FileTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `content` as what the model now knows `path` to contain.
+    pub fn record(&self, path: &Path, content: &[u8]) {
+        let state = FileState::of(path, content);
+        if let Ok(mut files) = self.files.lock() {
+            files.insert(Self::key(path), state);
+        }
+    }
+
+    /// Fails if `path` was read or written before and has changed on disk since. Files the model
+    /// has never seen are not checked.
+    pub fn check_unchanged(&self, path: &Path) -> Result<()> {
+        let Some(known) = self.files.lock().ok().and_then(|files| files.get(&Self::key(path)).cloned()) else {
+            return Ok(());
+        };
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        if modified.is_some() && modified == known.modified {
+            return Ok(());
+        }
+
+        // the modification time alone does not prove a change, e.g. after `touch`
+        let current = std::fs::read(path).map(|content| FileState::of(path, &content));
+        match current {
+            Ok(current) if current.hash == known.hash => {
+                if let Ok(mut files) = self.files.lock() {
+                    files.insert(Self::key(path), current);
+                }
+                Ok(())
+            }
+            Ok(_) => Err(ToolError::ExecutionFailed {
+                reason: format!(
+                    "write_file: {} has changed on disk since you last read it; read it again before editing it",
+                    path.display()
+                )
+            }.into()),
+            Err(_) => Err(ToolError::ExecutionFailed {
+                reason: format!(
+                    "write_file: {} was deleted or became unreadable since you last read it; check it again before writing it",
+                    path.display()
+                )
+            }.into()),
+        }
+    }
+
+    fn key(path: &Path) -> PathBuf {
+        path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_project;
+
+    #[test]
+    fn ignores_files_it_has_not_seen() {
+        let dir = temp_project(&[("file", "a")]);
+        let path = dir.join("file");
+        assert!(FileTracker::new().check_unchanged(&path).is_ok());
+    }
+
+    #[test]
+    fn accepts_files_that_were_only_touched() {
+        let dir = temp_project(&[("file", "a")]);
+        let path = dir.join("file");
+        let tracker = FileTracker::new();
+        tracker.record(&path, b"a");
+        // rewriting the same content updates the modification time but not the hash
+        std::fs::write(&path, "a").unwrap();
+        assert!(tracker.check_unchanged(&path).is_ok());
+    }
+
+    #[test]
+    fn refuses_files_changed_on_disk() {
+        let dir = temp_project(&[("file", "a")]);
+        let path = dir.join("file");
+        let tracker = FileTracker::new();
+        tracker.record(&path, b"a");
+        std::fs::write(&path, "b").unwrap();
+        assert!(tracker.check_unchanged(&path).unwrap_err().to_string().contains("changed on disk"));
+        tracker.record(&path, b"b");
+        assert!(tracker.check_unchanged(&path).is_ok());
+    }
+
+    #[test]
+    fn refuses_files_deleted_since() {
+        let dir = temp_project(&[("file", "a")]);
+        let path = dir.join("file");
+        let tracker = FileTracker::new();
+        tracker.record(&path, b"a");
+        std::fs::remove_file(&path).unwrap();
+        assert!(tracker.check_unchanged(&path).unwrap_err().to_string().contains("deleted"));
+    }
+}
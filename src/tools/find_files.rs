@@ -0,0 +1,250 @@
+use globset::{GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::{PathGuard, name_glob, project_walker};
+use crate::{core::{Tool, ToolOutput}, error::{ToolError, Result}, io::IO};
+
+const DEFAULT_MAX_RESULTS: usize = 100;
+const MAX_RESULTS: usize = 1_000;
+
+pub struct FindFilesTool {
+    guard: PathGuard,
+}
+
+impl FindFilesTool {
+    pub fn new(guard: PathGuard) -> Self {
+        Self { guard }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum SortOrder {
+    #[default]
+    Modified,
+    Path,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Input {
+    patterns: Vec<String>,
+    #[serde(default)]
+    path: String,
+    #[serde(default)]
+    sort: SortOrder,
+    max_results: Option<usize>,
+    #[serde(default)]
+    include_hidden: bool,
+}
+
+impl Input {
+    /// Patterns without a `/` match file names in any directory, as in `.gitignore`; the others
+    /// match paths relative to the searched directory.
+    fn globs(&self) -> Result<GlobSet> {
+        if self.patterns.is_empty() {
+            return Err(ToolError::InvalidArguments {
+                reason: "find_files: at least one pattern is required".to_string()
+            }.into());
+        }
+        let mut globs = GlobSetBuilder::new();
+        for pattern in &self.patterns {
+            let glob = name_glob(pattern).map_err(|e| ToolError::InvalidArguments {
+                reason: format!("find_files: invalid pattern {}: {}", pattern, e)
+            })?;
+            globs.add(glob);
+        }
+        globs.build().map_err(|e| ToolError::InvalidArguments {
+            reason: format!("find_files: invalid patterns: {}", e)
+        }.into())
+    }
+}
+
+/// Collects the files below `root` whose relative path matches `globs`, skipping what
+/// `.gitignore` files at any level and `.git/info/exclude` exclude.
+fn find_files(root: &Path, globs: &GlobSet, include_hidden: bool) -> Vec<(PathBuf, Option<SystemTime>)> {
+    project_walker(root, include_hidden)
+        .build()
+        .flatten()
+        .filter(|entry| entry.file_type().is_some_and(|file_type| file_type.is_file()))
+        .filter(|entry| entry.path().strip_prefix(root).is_ok_and(|relative| globs.is_match(relative)))
+        .map(|entry| {
+            let modified = entry.metadata().ok().and_then(|metadata| metadata.modified().ok());
+            (entry.into_path(), modified)
+        })
+        .collect()
+}
+
+impl Tool for FindFilesTool {
+    fn name(&self) -> String {
+        "find_files".to_owned()
+    }
+
+    fn description(&self) -> String {
+        "Find files by name using glob patterns such as \"*.rs\", \"src/**/*.ts\" or \"**/test_*.py\", respecting .gitignore. Patterns without a '/' match file names in any directory; other patterns match paths relative to the searched directory. \
+         Results are sorted by modification time (most recent first) or by path, and capped at max_results. Prefer this over listing whole directory trees.".to_owned()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "patterns": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Glob patterns to match; a file is returned if it matches any of them."
+                },
+                "path": {
+                    "type": "string",
+                    "description": "Directory to search, relative to the current working directory. Defaults to the working directory."
+                },
+                "sort": {
+                    "type": "string",
+                    "enum": ["modified", "path"],
+                    "description": "Sort by modification time, most recent first (default), or alphabetically by path."
+                },
+                "max_results": {
+                    "type": "integer",
+                    "description": "Maximum number of files to return (default 100, maximum 1000)."
+                },
+                "include_hidden": {
+                    "type": "boolean",
+                    "description": "When true, includes hidden files and directories (starting with '.'). Defaults to false."
+                }
+            },
+            "required": ["patterns"]
+        })
+    }
+
+    fn ask_permission(&self, args: serde_json::Value, io: &mut Box<dyn IO>) {
+        let (patterns, path) = match serde_json::from_value::<Input>(args) {
+            Ok(input) => (input.patterns.join(", "), input.path),
+            Err(_) => ("<invalid>".to_string(), String::new()),
+        };
+        io.show_message(
+            "Permission request",
+            &format!(
+                "deputy wants to find files matching {} in {}",
+                patterns,
+                if path.is_empty() { "the working directory" } else { &path }
+            ),
+        );
+    }
+
+    fn permission_ids(&self, args: serde_json::Value) -> Result<Vec<String>> {
+        let input: Input = serde_json::from_value(args)
+            .map_err(|e| ToolError::InvalidArguments {
+                reason: format!("find_files: {}", e)
+            })?;
+        let mut ids = vec![String::from("find_files")];
+        ids.extend(self.guard.permission_ids("find_files", [input.path.as_str()])?);
+        Ok(ids)
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn call<'a>(
+        &'a self,
+        args: serde_json::Value,
+        io: &'a mut Box<dyn IO>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ToolOutput>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            let input: Input = serde_json::from_value(args)
+                .map_err(|e| ToolError::InvalidArguments {
+                    reason: format!("find_files: {}", e)
+                })?;
+
+            let root = self.guard.resolve("find_files", &input.path)?;
+            if !root.is_dir() {
+                return Err(ToolError::InvalidArguments {
+                    reason: format!("find_files: {} is not a directory", input.path)
+                }.into());
+            }
+            let globs = input.globs()?;
+            let max_results = input.max_results.unwrap_or(DEFAULT_MAX_RESULTS).clamp(1, MAX_RESULTS);
+
+            let mut found = find_files(&root, &globs, input.include_hidden);
+            match input.sort {
+                SortOrder::Modified => found.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0))),
+                SortOrder::Path => found.sort_by(|a, b| a.0.cmp(&b.0)),
+            }
+
+            let total = found.len();
+            let mut output: Vec<String> = found
+                .iter()
+                .take(max_results)
+                .map(|(path, _)| self.guard.display(path))
+                .collect();
+            if output.is_empty() {
+                output.push(format!("No files match {}.", input.patterns.join(", ")));
+            } else if total > max_results {
+                output.push(format!("[... {} more; narrow the patterns or raise max_results ...]", total - max_results));
+            }
+            let output = output.join("\n");
+
+            io.show_snippet(
+                &format!("deputy is finding {} in {}", input.patterns.join(", "), self.guard.display(&root)),
+                &output.lines().take(10).collect::<Vec<&str>>().join("\n"),
+            );
+            Ok(output.into())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::io::HeadlessIO;
+    use crate::testing::{temp_project, TempProject};
+
+    /// A project with a `.git` directory, so that `.gitignore` applies.
+    fn project() -> TempProject {
+        let files = ["main.rs", "src/lib.rs", "src/tools/mod.rs", "tests/cli.rs", "README.md", "target/debug/build.rs", ".git/hooks/pre-commit.rs", ".hidden/x.rs"];
+        let mut files: Vec<(&str, &str)> = files.iter().map(|path| (*path, "")).collect();
+        files.push((".gitignore", "target/\n"));
+        temp_project(&files)
+    }
+
+    async fn find(dir: &Path, args: serde_json::Value) -> Result<String> {
+        let tool = FindFilesTool::new(PathGuard::new(dir, &[]).unwrap());
+        let mut io: Box<dyn IO> = Box::new(HeadlessIO::new());
+        tool.call(args, &mut io).await.map(|output| output.text)
+    }
+
+    #[tokio::test]
+    async fn matches_names_anywhere_and_paths_from_the_searched_directory() {
+        let dir = project();
+        assert_eq!(find(&dir, json!({ "patterns": ["*.rs"], "sort": "path" })).await.unwrap(), "main.rs\nsrc/lib.rs\nsrc/tools/mod.rs\ntests/cli.rs");
+        assert_eq!(find(&dir, json!({ "patterns": ["src/*.rs", "./README.md"], "sort": "path" })).await.unwrap(), "README.md\nsrc/lib.rs");
+        assert_eq!(find(&dir, json!({ "patterns": ["src/**/*.rs"], "sort": "path" })).await.unwrap(), "src/lib.rs\nsrc/tools/mod.rs");
+        assert_eq!(find(&dir, json!({ "patterns": ["*.rs"], "path": "src", "sort": "path" })).await.unwrap(), "src/lib.rs\nsrc/tools/mod.rs");
+        assert_eq!(find(&dir, json!({ "patterns": ["*.py"] })).await.unwrap(), "No files match *.py.");
+    }
+
+    #[tokio::test]
+    async fn skips_ignored_files_and_git_internals() {
+        let dir = project();
+        let hidden = find(&dir, json!({ "patterns": ["*.rs"], "include_hidden": true, "sort": "path" })).await.unwrap();
+        assert_eq!(hidden, ".hidden/x.rs\nmain.rs\nsrc/lib.rs\nsrc/tools/mod.rs\ntests/cli.rs");
+    }
+
+    #[tokio::test]
+    async fn caps_the_results() {
+        let dir = project();
+        let found = find(&dir, json!({ "patterns": ["*.rs"], "sort": "path", "max_results": 2 })).await.unwrap();
+        assert_eq!(found, "main.rs\nsrc/lib.rs\n[... 2 more; narrow the patterns or raise max_results ...]");
+    }
+
+    #[tokio::test]
+    async fn refuses_missing_patterns_and_files_as_roots() {
+        let dir = project();
+        assert!(find(&dir, json!({ "patterns": [] })).await.unwrap_err().to_string().contains("at least one pattern"));
+        assert!(find(&dir, json!({ "patterns": ["*"], "path": "main.rs" })).await.unwrap_err().to_string().contains("not a directory"));
+    }
+}
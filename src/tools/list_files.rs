@@ -1,38 +1,84 @@
-use ignore::gitignore::GitignoreBuilder;
 use serde::Deserialize;
 use std::path::{Path, PathBuf};
 
-use crate::{core::Tool, error::{ToolError, Result}, io::IO};
+use super::{PathGuard, project_walker};
+use crate::{core::{Tool, ToolOutput}, error::{ToolError, Result}, io::IO};
 
-pub struct ListFilesTool;
+const DEFAULT_MAX_DEPTH: usize = 3;
+const DEFAULT_MAX_ENTRIES: usize = 200;
+const MAX_ENTRIES: usize = 2_000;
+
+pub struct ListFilesTool {
+    guard: PathGuard,
+}
+
+impl ListFilesTool {
+    pub fn new(guard: PathGuard) -> Self {
+        Self { guard }
+    }
+}
 
 #[derive(Deserialize, Debug)]
 pub struct Input {
     path: String,
     #[serde(default)]
     recursive: bool,
+    max_depth: Option<usize>,
+    max_entries: Option<usize>,
     #[serde(default)]
     include_hidden: bool,
 }
 
-fn build_path(input: &Input) -> Result<PathBuf> {
-    let cwd = std::env::current_dir().map_err(|e| ToolError::ExecutionFailed {
-        reason: format!("list_files_tool: Failed to get current working directory: {}", e)
-    })?;
-    if input.path.is_empty() {
-        Ok(cwd)
-    } else {
-        Ok(cwd.join(&input.path))
+impl Input {
+    fn max_depth(&self) -> usize {
+        match (self.recursive, self.max_depth) {
+            (_, Some(depth)) => depth.max(1),
+            (true, None) => DEFAULT_MAX_DEPTH,
+            (false, None) => 1,
+        }
+    }
+
+    /// Walks the directory down to `max_depth` levels, directories first, honouring `.gitignore` files
+    /// at any level as well as `.git/info/exclude`.
+    fn walker(&self, path: &Path) -> ignore::Walk {
+        let mut builder = project_walker(path, self.include_hidden);
+        builder
+            .max_depth(Some(self.max_depth()))
+            .sort_by_file_path(|a, b| match (a.is_dir(), b.is_dir()) {
+                (true, false) => std::cmp::Ordering::Less,
+                (false, true) => std::cmp::Ordering::Greater,
+                _ => a.file_name().cmp(&b.file_name()),
+            });
+        builder.build()
     }
 }
 
+fn build_path(input: &Input, guard: &PathGuard) -> Result<PathBuf> {
+    // an empty path resolves to the working directory
+    guard.resolve("list_files_tool", &input.path)
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
 impl Tool for ListFilesTool {
     fn name(&self) -> String {
         "list_files_tool".to_owned()
     }
 
     fn description(&self) -> String {
-        "List files in a directory. The directory must be a path relative to the the current working directory. If an empty path is provided, the current working directory will be used. When recursive is true, recursively lists all files and directories in a tree format. Hidden files (starting with '.') are excluded by default unless include_hidden is true. IMPORTANT: Only use include_hidden=true when you have a strong reason to examine hidden files, such as debugging configuration issues or when explicitly asked by the user.".to_owned()
+        "List files in a directory. The directory must be a path relative to the the current working directory. If an empty path is provided, the current working directory will be used. Paths are shown relative to the working directory, directories end with '/' and files show their size. When recursive is true, lists the directory tree down to max_depth levels (default 3). Files ignored by .gitignore are skipped, and the listing stops after max_entries entries. To find files by name in a large tree, prefer find_files. Hidden files (starting with '.') are excluded by default unless include_hidden is true. IMPORTANT: Only use include_hidden=true when you have a strong reason to examine hidden files, such as debugging configuration issues or when explicitly asked by the user.".to_owned()
     }
 
     fn input_schema(&self) -> serde_json::Value {
@@ -45,7 +91,15 @@ impl Tool for ListFilesTool {
                 },
                 "recursive": {
                     "type": "boolean",
-                    "description": "When true, recursively lists all files and directories in a tree format. Defaults to false."
+                    "description": "When true, lists subdirectories as well, in a tree format. Defaults to false."
+                },
+                "max_depth": {
+                    "type": "integer",
+                    "description": "How many levels to list; 1 lists only the directory's own entries. Defaults to 3 when recursive is true."
+                },
+                "max_entries": {
+                    "type": "integer",
+                    "description": "Maximum number of entries to list (default 200, maximum 2000)."
                 },
                 "include_hidden": {
                     "type": "boolean",
@@ -57,8 +111,10 @@ impl Tool for ListFilesTool {
     }
 
     fn ask_permission(&self, args: serde_json::Value, io: &mut Box<dyn IO>) {
-        let input: Input = serde_json::from_value(args).unwrap_or(Input { path: "<invalid>".to_string(), recursive: false, include_hidden: false });
-        let path = build_path(&input).unwrap_or_else(|_| PathBuf::from("<invalid>"));
+        let path = serde_json::from_value::<Input>(args)
+            .ok()
+            .and_then(|input| build_path(&input, &self.guard).ok())
+            .unwrap_or_else(|| PathBuf::from("<invalid>"));
         io.show_message(
             "Permission request",
             &format!(
@@ -68,15 +124,25 @@ impl Tool for ListFilesTool {
         );
     }
 
-    fn permission_id(&self, _args: serde_json::Value) -> Result<String> {
-        Ok(String::from("list_files"))
+    fn permission_ids(&self, args: serde_json::Value) -> Result<Vec<String>> {
+        let input: Input = serde_json::from_value(args)
+            .map_err(|e| ToolError::InvalidArguments {
+                reason: format!("list_files_tool: {}", e)
+            })?;
+        let mut ids = vec![String::from("list_files")];
+        ids.extend(self.guard.permission_ids("list_files_tool", [input.path.as_str()])?);
+        Ok(ids)
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
     }
 
     fn call<'a>(
         &'a self,
         args: serde_json::Value,
         io: &'a mut Box<dyn IO>,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String>> + Send + 'a>>
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ToolOutput>> + Send + 'a>>
     {
         Box::pin(async move {
             let input: Input = serde_json::from_value(args)
@@ -84,139 +150,106 @@ impl Tool for ListFilesTool {
                     reason: format!("list_files_tool: {}", e)
                 })?;
 
-            let path = build_path(&input)?;
-            let gitignore = build_gitignore(&path);
+            let path = build_path(&input, &self.guard)?;
+            if !path.is_dir() {
+                return Err(ToolError::ExecutionFailed {
+                    reason: format!("list_files_tool: {} is not a directory", input.path)
+                }.into());
+            }
+            let max_entries = input.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES).clamp(1, MAX_ENTRIES);
+
+            let mut lines = Vec::new();
+            let mut truncated = false;
+            // the first entry is the directory itself
+            for entry in input.walker(&path).flatten().skip(1) {
+                if lines.len() >= max_entries {
+                    truncated = true;
+                    break;
+                }
+                let indent = "  ".repeat(entry.depth() - 1);
+                let display = self.guard.display(entry.path());
+                if entry.file_type().is_some_and(|file_type| file_type.is_dir()) {
+                    lines.push(format!("{}{}/", indent, display));
+                } else {
+                    let size = entry.metadata().map(|metadata| format_size(metadata.len())).unwrap_or_else(|_| "unknown size".to_string());
+                    lines.push(format!("{}{} ({})", indent, display, size));
+                }
+            }
 
-            let output = if input.recursive {
-                list_files_recursive(&path, 0, &gitignore, input.include_hidden)
+            let mut output = if lines.is_empty() {
+                format!("{} is empty.", self.guard.display(&path))
             } else {
-                let mut output = String::new();
-                let entries = std::fs::read_dir(&path).map_err(|e| ToolError::ExecutionFailed {
-                    reason: format!("list_files_tool: Failed to read directory: {}", e)
-                })?;
-                for entry in entries {
-                    let entry = entry.map_err(|e| ToolError::ExecutionFailed {
-                        reason: format!("list_files_tool: Failed to read directory entry: {}", e)
-                    })?;
-                    let entry_path = entry.path();
-
-                    if should_include_path(&entry_path, &gitignore, input.include_hidden) {
-                        if path.is_dir() {
-                            output.push_str(&format!("{} (directory)\n", entry_path.display()));
-                        } else {
-                            output.push_str(&format!("{}\n", entry_path.display()));
-                        }
-                    }
-                }
-                output
+                lines.join("\n")
             };
-            io.show_snippet(&format!("deputy is listing files in {}", path.display()), &output);
-            Ok(output)
+            if truncated {
+                output.push_str(&format!(
+                    "\n[... stopped after {} entries; list a subdirectory, lower max_depth or use find_files ...]",
+                    max_entries
+                ));
+            }
+            io.show_snippet(&format!("deputy is listing files in {}", self.guard.display(&path)), &output);
+            Ok(output.into())
         })
     }
 }
 
-fn list_files_recursive(
-    path: &Path,
-    depth: usize,
-    gitignore: &ignore::gitignore::Gitignore,
-    include_hidden: bool,
-) -> String {
-    let mut output = String::new();
-    let indent = "  ".repeat(depth);
-
-    if let Ok(entries) = std::fs::read_dir(path) {
-        let mut entries: Vec<_> = entries.collect();
-        entries.sort_by(|a, b| {
-            match (a.as_ref(), b.as_ref()) {
-                (Ok(a_entry), Ok(b_entry)) => {
-                    let a_path = a_entry.path();
-                    let b_path = b_entry.path();
-                    match (a_path.is_dir(), b_path.is_dir()) {
-                        (true, false) => std::cmp::Ordering::Less,
-                        (false, true) => std::cmp::Ordering::Greater,
-                        _ => a_path.file_name().cmp(&b_path.file_name()),
-                    }
-                }
-                _ => std::cmp::Ordering::Equal,
-            }
-        });
-
-        for entry in entries.into_iter().flatten() {
-            let entry_path = entry.path();
-
-            if should_include_path(&entry_path, gitignore, include_hidden) {
-                if entry_path.is_dir() {
-                    output.push_str(&format!(
-                        "{}{}/ (directory)\n",
-                        indent,
-                        entry_path.display()
-                    ));
-                    output.push_str(&list_files_recursive(
-                        &entry_path,
-                        depth + 1,
-                        gitignore,
-                        include_hidden,
-                    ));
-                } else {
-                    output.push_str(&format!("{}{}\n", indent, entry_path.display()));
-                }
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::io::HeadlessIO;
+    use crate::testing::{temp_project, TempProject};
+
+    /// A project with a `.git` directory, so that `.gitignore` applies.
+    fn project() -> TempProject {
+        let big = "x".repeat(2048);
+        temp_project(&[
+            ("b.txt", &big),
+            ("a.txt", "xxxxxxxxxx"),
+            ("src/lib.rs", ""),
+            ("src/deep/er/file.rs", ""),
+            ("target/out", ""),
+            (".git/HEAD", ""),
+            (".env.example", ""),
+            (".gitignore", "target/\n"),
+        ])
     }
 
-    output
-}
-
-fn build_gitignore(path: &Path) -> ignore::gitignore::Gitignore {
-    let mut builder = GitignoreBuilder::new(path);
-
-    let mut current_path = path.to_path_buf();
-    loop {
-        let gitignore_path = current_path.join(".gitignore");
-        if gitignore_path.exists() {
-            if let Some(e) = builder.add(&gitignore_path) {
-                eprintln!(
-                    "Warning: Failed to parse .gitignore at {}: {}",
-                    gitignore_path.display(),
-                    e
-                );
-            }
-        }
+    async fn list(dir: &Path, args: serde_json::Value) -> Result<String> {
+        let tool = ListFilesTool::new(PathGuard::new(dir, &[]).unwrap());
+        let mut io: Box<dyn IO> = Box::new(HeadlessIO::new());
+        tool.call(args, &mut io).await.map(|output| output.text)
+    }
 
-        if !current_path.pop() {
-            break;
-        }
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(2048), "2.0 KB");
+        assert_eq!(format_size(5 * 1024 * 1024 + 512 * 1024), "5.5 MB");
     }
 
-    builder.build().unwrap_or_else(|e| {
-        eprintln!("Warning: Failed to build gitignore matcher: {}", e);
-        // Fallback to empty gitignore on error
-        GitignoreBuilder::new(path).build().unwrap_or_else(|_| {
-            eprintln!("Warning: Failed to create fallback gitignore matcher");
-            // Return a minimal working gitignore that ignores nothing
-            ignore::gitignore::Gitignore::empty()
-        })
-    })
-}
+    #[tokio::test]
+    async fn lists_directories_first_without_ignored_entries() {
+        let dir = project();
+        assert_eq!(list(&dir, json!({ "path": "" })).await.unwrap(), "src/\na.txt (10 B)\nb.txt (2.0 KB)");
+        let hidden = list(&dir, json!({ "path": ".", "include_hidden": true })).await.unwrap();
+        assert_eq!(hidden, "src/\n.env.example (0 B)\n.gitignore (8 B)\na.txt (10 B)\nb.txt (2.0 KB)");
+    }
 
-fn should_include_path(
-    path: &Path,
-    gitignore: &ignore::gitignore::Gitignore,
-    include_hidden: bool,
-) -> bool {
-    if !include_hidden {
-        if let Some(name) = path.file_name() {
-            if let Some(name_str) = name.to_str() {
-                if name_str.starts_with('.') {
-                    return false;
-                }
-            }
-        }
+    #[tokio::test]
+    async fn lists_trees_down_to_the_maximum_depth() {
+        let dir = project();
+        let tree = list(&dir, json!({ "path": "src", "recursive": true, "max_depth": 2 })).await.unwrap();
+        assert_eq!(tree, "src/deep/\n  src/deep/er/\nsrc/lib.rs (0 B)");
+        let tree = list(&dir, json!({ "path": "", "recursive": true, "max_entries": 2 })).await.unwrap();
+        assert!(tree.starts_with("src/\n  src/deep/\n[... stopped after 2 entries"), "{}", tree);
     }
 
-    match gitignore.matched(path, path.is_dir()) {
-        ignore::Match::None | ignore::Match::Whitelist(_) => true,
-        ignore::Match::Ignore(_) => false,
+    #[tokio::test]
+    async fn refuses_files() {
+        let dir = project();
+        assert!(list(&dir, json!({ "path": "a.txt" })).await.unwrap_err().to_string().contains("not a directory"));
     }
 }
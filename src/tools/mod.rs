@@ -1,11 +1,53 @@
+use std::path::Path;
+
+use globset::{Glob, GlobBuilder};
+use ignore::WalkBuilder;
+
+mod background;
 mod exec_command;
+mod file_tracker;
+mod find_files;
 mod list_files;
+mod paths;
+mod process;
 mod read_files;
+mod search_files;
 mod write_file;
 mod registry;
+mod sandbox;
+mod shell_session;
 
+pub use background::{BackgroundProcesses, KillProcessTool, ListProcessesTool, ReadProcessOutputTool, StartProcessTool};
 pub use exec_command::ExecCommandTool;
+pub use file_tracker::FileTracker;
+pub use find_files::FindFilesTool;
 pub use list_files::ListFilesTool;
+pub use paths::{PathGuard, resolve_path};
 pub use read_files::ReadFilesTool;
+pub use search_files::SearchFilesTool;
 pub use write_file::WriteFileTool;
-pub use registry::ToolRegistry;
\ No newline at end of file
+pub use registry::ToolRegistry;
+pub use sandbox::{Sandbox, SandboxConfig};
+pub use shell_session::ShellSessionTool;
+
+/// Starts a walk below `root` that skips `.git` and whatever `.gitignore` files at any level,
+/// `.git/info/exclude` and the global gitignore exclude, as well as hidden entries unless
+/// `include_hidden` is set.
+pub(super) fn project_walker(root: &Path, include_hidden: bool) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .hidden(!include_hidden)
+        .git_ignore(true)
+        .git_exclude(true)
+        .git_global(true)
+        .filter_entry(|entry| entry.file_name() != ".git");
+    builder
+}
+
+/// Compiles a glob for the file tools: as in `.gitignore`, a pattern without a `/` matches file
+/// names in any directory, and other patterns match paths relative to the searched directory.
+pub(super) fn name_glob(pattern: &str) -> std::result::Result<Glob, globset::Error> {
+    let pattern = pattern.trim_start_matches("./");
+    let full = if pattern.contains('/') { pattern.to_string() } else { format!("**/{}", pattern) };
+    GlobBuilder::new(&full).literal_separator(true).build()
+}
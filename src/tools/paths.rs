@@ -0,0 +1,228 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::{ConfigError, ToolError, Result};
+
+/// Files that may hold secrets, matched against the resolved path. `~/` stands for the home
+/// directory.
+const SENSITIVE_PATTERNS: &[&str] = &[
+    "**/.env",
+    "**/.env.*",
+    "**/*.pem",
+    "**/*.key",
+    "**/*.p12",
+    "**/*.pfx",
+    "**/id_rsa*",
+    "**/id_dsa*",
+    "**/id_ecdsa*",
+    "**/id_ed25519*",
+    "**/.netrc",
+    "**/.git-credentials",
+    "**/.npmrc",
+    "**/.pypirc",
+    "**/.deputy/permissions.toml",
+    "~/.ssh/**",
+    "~/.gnupg/**",
+    "~/.aws/**",
+    "~/.azure/**",
+    "~/.kube/**",
+    "~/.docker/config.json",
+    "~/.config/gcloud/**",
+    "~/.config/gh/**",
+    "~/.password-store/**",
+    "~/.local/share/keyrings/**",
+    "~/.cargo/credentials*",
+];
+
+/// Templates that match the patterns above but hold no secrets by convention.
+const SAFE_SUFFIXES: &[&str] = &[".example", ".sample", ".template", ".dist"];
+
+/// Decides which paths the file tools may access: paths below the allowed roots (the working
+/// directory and any configured extra roots) are accessible, other paths need the user's
+/// explicit permission, and files that may hold secrets are never accessible.
+#[derive(Debug, Clone)]
+pub struct PathGuard {
+    cwd: PathBuf,
+    roots: Vec<PathBuf>,
+    sensitive: GlobSet,
+}
+
+impl PathGuard {
+    pub fn new(cwd: &Path, extra_roots: &[PathBuf]) -> Result<Self> {
+        let mut roots = vec![resolve_path(cwd)];
+        for root in extra_roots {
+            roots.push(root.canonicalize().map_err(|e| ConfigError::Invalid {
+                reason: format!("allowed directory {}: {}", root.display(), e)
+            })?);
+        }
+
+        let home = dirs::home_dir().map(|home| resolve_path(&home));
+        let mut sensitive = GlobSetBuilder::new();
+        for pattern in SENSITIVE_PATTERNS {
+            let pattern = match (pattern.strip_prefix("~/"), &home) {
+                (Some(rest), Some(home)) => format!("{}/{}", home.display(), rest),
+                (Some(_), None) => continue,
+                (None, _) => pattern.to_string(),
+            };
+            let glob = GlobBuilder::new(&pattern).literal_separator(true).build().map_err(|e| ConfigError::Invalid {
+                reason: format!("sensitive file pattern {}: {}", pattern, e)
+            })?;
+            sensitive.add(glob);
+        }
+        let sensitive = sensitive.build().map_err(|e| ConfigError::Invalid {
+            reason: format!("sensitive file patterns: {}", e)
+        })?;
+
+        Ok(Self {
+            cwd: cwd.to_path_buf(),
+            roots,
+            sensitive,
+        })
+    }
+
+    /// Resolves a path given by the model relative to the working directory, following symlinks,
+    /// and refuses files that may hold secrets.
+    pub fn resolve(&self, tool: &str, path: &str) -> Result<PathBuf> {
+        let resolved = resolve_path(&self.cwd.join(path));
+        if self.is_sensitive(&resolved) {
+            return Err(ToolError::InvalidArguments {
+                reason: format!("{}: access to {} is blocked because it is a sensitive file, such as one holding credentials", tool, path)
+            }.into());
+        }
+        Ok(resolved)
+    }
+
+    /// Permission ids for the paths that lie outside the allowed roots, which the user has to
+    /// approve individually; fails if any path is blocked.
+    pub fn permission_ids<'p>(&self, tool: &str, paths: impl IntoIterator<Item = &'p str>) -> Result<Vec<String>> {
+        let mut ids = Vec::new();
+        for path in paths {
+            let resolved = self.resolve(tool, path)?;
+            if !self.is_inside(&resolved) {
+                let id = format!("outside the project: {}", resolved.display());
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    pub fn is_inside(&self, resolved: &Path) -> bool {
+        self.roots.iter().any(|root| resolved.starts_with(root))
+    }
+
+    /// Shows a resolved path relative to the working directory when it lies below it.
+    pub fn display(&self, resolved: &Path) -> String {
+        resolved
+            // the first root is the working directory
+            .strip_prefix(&self.roots[0])
+            .map(|relative| match relative.as_os_str().is_empty() {
+                true => ".".to_string(),
+                false => relative.display().to_string(),
+            })
+            .unwrap_or_else(|_| resolved.display().to_string())
+    }
+
+    /// Whether the file may hold secrets and must not be read or written.
+    pub fn is_sensitive(&self, resolved: &Path) -> bool {
+        let safe = resolved
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| SAFE_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)));
+        !safe && self.sensitive.is_match(resolved)
+    }
+}
+
+/// Makes `path` absolute and free of `.`, `..` and symlinks. Components that do not exist yet,
+/// such as a file about to be created, are appended as they are.
+pub fn resolve_path(path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+        // resolving symlinks as we go makes a later `..` leave the symlink's target, as it does
+        // for the operating system
+        if let Ok(canonical) = resolved.canonicalize() {
+            resolved = canonical;
+        }
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_project;
+
+    #[test]
+    fn resolves_dots_and_symlinks() {
+        let dir = temp_project(&[]);
+        std::fs::create_dir_all(dir.join("real/nested")).unwrap();
+        std::os::unix::fs::symlink(dir.join("real/nested"), dir.join("link")).unwrap();
+
+        assert_eq!(resolve_path(&dir.join("./real/../real/file")), dir.join("real/file"));
+        assert_eq!(resolve_path(&dir.join("link/new.txt")), dir.join("real/nested/new.txt"));
+        // `..` leaves the symlink's target, not the symlink
+        assert_eq!(resolve_path(&dir.join("link/../file")), dir.join("real/file"));
+    }
+
+    #[test]
+    fn blocks_sensitive_files_but_not_templates() {
+        let dir = temp_project(&[]);
+        let guard = PathGuard::new(&dir, &[]).unwrap();
+
+        for path in [".env", "config/.env.production", "certs/server.key", ".deputy/permissions.toml"] {
+            let reason = guard.resolve("read_files", path).unwrap_err().to_string();
+            assert!(reason.contains("sensitive file"), "{}: {}", path, reason);
+        }
+        for path in [".env.example", "config/.env.sample", "src/main.rs", "keys.rs"] {
+            assert!(guard.resolve("read_files", path).is_ok(), "{}", path);
+        }
+        if let Some(home) = dirs::home_dir() {
+            assert!(guard.resolve("read_files", &home.join(".ssh/config").display().to_string()).is_err());
+        }
+    }
+
+    #[test]
+    fn blocks_sensitive_files_reached_through_symlinks() {
+        let dir = temp_project(&[]);
+        std::fs::write(dir.join(".env"), "SECRET=1").unwrap();
+        std::os::unix::fs::symlink(dir.join(".env"), dir.join("settings.txt")).unwrap();
+        let guard = PathGuard::new(&dir, &[]).unwrap();
+
+        assert!(guard.resolve("read_files", "settings.txt").is_err());
+    }
+
+    #[test]
+    fn asks_for_paths_outside_the_allowed_roots() {
+        let dir = temp_project(&[]);
+        let project = dir.join("project");
+        let extra = dir.join("extra");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::create_dir_all(&extra).unwrap();
+        std::os::unix::fs::symlink(&dir, project.join("escape")).unwrap();
+        let guard = PathGuard::new(&project, std::slice::from_ref(&extra)).unwrap();
+
+        assert!(guard.permission_ids("read_files", ["src/main.rs", "."]).unwrap().is_empty());
+        assert!(guard.permission_ids("read_files", [extra.join("notes.md").to_str().unwrap()]).unwrap().is_empty());
+        let outside = format!("outside the project: {}", dir.join("other.txt").display());
+        assert_eq!(guard.permission_ids("read_files", ["../other.txt", "escape/other.txt"]).unwrap(), vec![outside]);
+        assert!(guard.permission_ids("read_files", ["ok.txt", ".env"]).is_err());
+    }
+
+    #[test]
+    fn displays_paths_relative_to_the_working_directory() {
+        let dir = temp_project(&[]);
+        let guard = PathGuard::new(&dir, &[]).unwrap();
+
+        assert_eq!(guard.display(&dir), ".");
+        assert_eq!(guard.display(&dir.join("src/main.rs")), "src/main.rs");
+        assert_eq!(guard.display(Path::new("/etc/hosts")), "/etc/hosts");
+    }
+}
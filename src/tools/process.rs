@@ -0,0 +1,369 @@
+use std::collections::VecDeque;
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::{Child, Command};
+
+use crate::core::interrupt::InterruptGuard;
+
+/// Seconds a command may run for when the model does not ask for a different timeout.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 120;
+pub const MAX_TIMEOUT_SECS: u64 = 600;
+
+/// Bytes kept from the start and from the end of each output stream; the middle is dropped.
+pub const OUTPUT_HEAD_BYTES: usize = 10_000;
+pub const OUTPUT_TAIL_BYTES: usize = 10_000;
+
+/// Collects a stream's output, keeping only its beginning and end once it grows too large.
+#[derive(Debug)]
+pub struct OutputBuffer {
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    total_bytes: usize,
+    total_lines: usize,
+    head_limit: usize,
+    tail_limit: usize,
+}
+
+impl Default for OutputBuffer {
+    fn default() -> Self {
+        Self::new(OUTPUT_HEAD_BYTES, OUTPUT_TAIL_BYTES)
+    }
+}
+
+impl OutputBuffer {
+    pub fn new(head_limit: usize, tail_limit: usize) -> Self {
+        Self {
+            head: Vec::new(),
+            tail: VecDeque::new(),
+            total_bytes: 0,
+            total_lines: 0,
+            head_limit,
+            tail_limit,
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.total_bytes += bytes.len();
+        self.total_lines += bytes.iter().filter(|b| **b == b'\n').count();
+
+        let into_head = bytes.len().min(self.head_limit - self.head.len());
+        self.head.extend_from_slice(&bytes[..into_head]);
+        self.tail.extend(&bytes[into_head..]);
+        if self.tail.len() > self.tail_limit {
+            let excess = self.tail.len() - self.tail_limit;
+            self.tail.drain(..excess);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total_bytes == 0
+    }
+
+    /// Whether part of the output had to be dropped.
+    pub fn is_truncated(&self) -> bool {
+        self.total_bytes > self.head.len() + self.tail.len()
+    }
+
+    /// Renders the kept output. When the middle was dropped, the cut is made on line boundaries
+    /// and marked with a note saying how much is missing.
+    pub fn render(&self) -> String {
+        let tail: Vec<u8> = self.tail.iter().copied().collect();
+        if !self.is_truncated() {
+            let mut all = self.head.clone();
+            all.extend_from_slice(&tail);
+            return String::from_utf8_lossy(&all).into_owned();
+        }
+
+        let head_end = self.head.iter().rposition(|b| *b == b'\n').map_or(self.head.len(), |i| i + 1);
+        let tail_start = tail.iter().position(|b| *b == b'\n').map_or(0, |i| i + 1);
+        let head = &self.head[..head_end];
+        let tail = &tail[tail_start..];
+
+        let kept_lines = head.iter().chain(tail).filter(|b| **b == b'\n').count();
+        let omitted_bytes = self.total_bytes - head.len() - tail.len();
+        let omitted_lines = self.total_lines.saturating_sub(kept_lines);
+        format!(
+            "{}\n[... {} bytes ({} lines) of output omitted ...]\n\n{}",
+            String::from_utf8_lossy(head),
+            omitted_bytes,
+            omitted_lines,
+            String::from_utf8_lossy(tail)
+        )
+    }
+}
+
+/// How a process run with [`run_to_completion`] ended.
+#[derive(Debug)]
+pub enum Completion {
+    Exited(std::process::ExitStatus),
+    /// A command run by a longer-lived process, such as a persistent shell, finished with this status.
+    Status(i32),
+    TimedOut,
+    Cancelled,
+}
+
+impl std::fmt::Display for Completion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Completion::Exited(status) => match status.code() {
+                Some(code) => write!(f, "{}", code),
+                None => {
+                    #[cfg(unix)]
+                    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(status) {
+                        return write!(f, "none (killed by signal {})", signal);
+                    }
+                    write!(f, "none")
+                }
+            },
+            Completion::Status(code) => write!(f, "{}", code),
+            Completion::TimedOut => write!(f, "none (timed out and killed)"),
+            Completion::Cancelled => write!(f, "none (cancelled by the user and killed)"),
+        }
+    }
+}
+
+/// Output and outcome of a finished process.
+#[derive(Debug)]
+pub struct ProcessOutput {
+    pub completion: Completion,
+    pub stdout: OutputBuffer,
+    pub stderr: OutputBuffer,
+    pub duration: Duration,
+}
+
+impl ProcessOutput {
+    pub fn success(&self) -> bool {
+        match &self.completion {
+            Completion::Exited(status) => status.success(),
+            Completion::Status(code) => *code == 0,
+            Completion::TimedOut | Completion::Cancelled => false,
+        }
+    }
+
+    /// Renders the result for the model, in the same shape whether the process succeeded or not.
+    pub fn report(&self) -> String {
+        self.report_with(&[])
+    }
+
+    /// Like [`report`](Self::report), with additional `name: value` fields before the output.
+    pub fn report_with(&self, fields: &[(&str, &str)]) -> String {
+        let mut report = format!(
+            "exit_code: {}\nduration: {:.2}s\ntruncated: {}\n",
+            self.completion,
+            self.duration.as_secs_f64(),
+            self.stdout.is_truncated() || self.stderr.is_truncated(),
+        );
+        for (name, value) in fields {
+            report.push_str(&format!("{}: {}\n", name, value));
+        }
+        report.push_str(&format!("stdout:\n{}\nstderr:\n{}", self.stdout.render(), self.stderr.render()));
+        report
+    }
+}
+
+/// A short preview of a process's output for the user: stderr in red if there is any,
+/// otherwise the first lines of stdout.
+pub fn preview(output: &ProcessOutput) -> String {
+    let stderr = output.stderr.render();
+    if !stderr.is_empty() {
+        stderr
+            .lines()
+            .map(|line| format!("\x1b[31m{}\x1b[0m", line))
+            .collect::<Vec<String>>()
+            .join("\n")
+    } else {
+        output.stdout.render().lines().take(10).collect::<Vec<&str>>().join("\n")
+    }
+}
+
+/// Puts the command in its own process group, so that it and everything it spawns can be
+/// killed together, and detaches it from deputy's stdin.
+pub fn prepare(command: &mut Command) {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    command.process_group(0);
+}
+
+/// Kills a child process together with its process group.
+pub fn kill(child: &mut Child) {
+    if let Some(pid) = child.id() {
+        kill_group(pid);
+    }
+    let _ = child.start_kill();
+}
+
+/// Kills the process group led by `pid`, which must have been started with [`prepare`].
+pub fn kill_group(pid: u32) {
+    #[cfg(unix)]
+    // SAFETY: killpg only sends a signal; the group was created for this child by `prepare`
+    unsafe {
+        libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+    }
+    #[cfg(not(unix))]
+    let _ = pid;
+}
+
+/// Runs a prepared command until it exits, the timeout elapses or the user presses Ctrl-C.
+/// In the latter two cases the whole process group is killed.
+pub async fn run_to_completion(mut command: Command, timeout: Duration) -> std::io::Result<ProcessOutput> {
+    let started = std::time::Instant::now();
+    let mut child = command.spawn()?;
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+
+    let interrupt = InterruptGuard::new();
+    let mut stdout_buffer = OutputBuffer::default();
+    let mut stderr_buffer = OutputBuffer::default();
+
+    let completion = {
+        let finished = async {
+            tokio::join!(
+                read_into(stdout, &mut stdout_buffer),
+                read_into(stderr, &mut stderr_buffer)
+            );
+            child.wait().await
+        };
+        tokio::select! {
+            status = finished => Completion::Exited(status?),
+            _ = tokio::time::sleep(timeout) => Completion::TimedOut,
+            _ = interrupt.interrupted() => Completion::Cancelled,
+        }
+    };
+
+    if !matches!(completion, Completion::Exited(_)) {
+        kill(&mut child);
+        let _ = child.wait().await;
+    }
+
+    Ok(ProcessOutput {
+        completion,
+        stdout: stdout_buffer,
+        stderr: stderr_buffer,
+        duration: started.elapsed(),
+    })
+}
+
+async fn read_into(stream: Option<impl AsyncRead + Unpin>, buffer: &mut OutputBuffer) {
+    let Some(mut stream) = stream else {
+        return;
+    };
+    let mut chunk = [0u8; 8192];
+    while let Ok(read) = stream.read(&mut chunk).await {
+        if read == 0 {
+            break;
+        }
+        buffer.push(&chunk[..read]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_small_output_whole() {
+        let mut buffer = OutputBuffer::new(16, 16);
+        buffer.push(b"hello\n");
+        buffer.push(b"world\n");
+        assert!(!buffer.is_truncated());
+        assert_eq!(buffer.render(), "hello\nworld\n");
+    }
+
+    #[test]
+    fn drops_the_middle_of_large_output_on_line_boundaries() {
+        let mut buffer = OutputBuffer::new(10, 10);
+        for line in 1..=20 {
+            buffer.push(format!("line {:02}\n", line).as_bytes());
+        }
+        assert!(buffer.is_truncated());
+        assert_eq!(buffer.render(), "line 01\n\n[... 144 bytes (18 lines) of output omitted ...]\n\nline 20\n");
+    }
+
+    #[test]
+    fn counts_output_that_arrives_in_small_pieces() {
+        let mut buffer = OutputBuffer::new(4, 4);
+        assert!(buffer.is_empty());
+        for byte in b"abcdefghij" {
+            buffer.push(&[*byte]);
+        }
+        assert!(!buffer.is_empty());
+        assert!(buffer.is_truncated());
+        assert_eq!(buffer.render(), "abcd\n[... 2 bytes (0 lines) of output omitted ...]\n\nghij");
+    }
+
+    fn shell(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        prepare(&mut command);
+        command
+    }
+
+    #[tokio::test]
+    async fn collects_both_streams_and_the_exit_status() {
+        let output = run_to_completion(shell("echo out; echo err >&2; exit 3"), Duration::from_secs(10)).await.unwrap();
+        assert!(matches!(output.completion, Completion::Exited(status) if status.code() == Some(3)));
+        assert!(!output.success());
+        assert_eq!(output.stdout.render(), "out\n");
+        assert_eq!(output.stderr.render(), "err\n");
+    }
+
+    #[tokio::test]
+    async fn kills_the_process_group_on_timeout() {
+        let started = std::time::Instant::now();
+        // the background sleep keeps stdout open, so the whole group must be killed for this to return
+        let output = run_to_completion(shell("sleep 30 & echo started; wait"), Duration::from_millis(300)).await.unwrap();
+        assert!(matches!(output.completion, Completion::TimedOut));
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(output.stdout.render(), "started\n");
+    }
+
+    fn output(completion: Completion, stdout: &str, stderr: &str) -> ProcessOutput {
+        let mut output = ProcessOutput {
+            completion,
+            stdout: OutputBuffer::default(),
+            stderr: OutputBuffer::default(),
+            duration: Duration::from_millis(1_250),
+        };
+        output.stdout.push(stdout.as_bytes());
+        output.stderr.push(stderr.as_bytes());
+        output
+    }
+
+    #[test]
+    fn reports_failures_in_the_same_shape_as_successes() {
+        let failed = output(Completion::Status(101), "running 3 tests\ntest a ... FAILED\n", "error: test failed\n");
+        assert!(!failed.success());
+        assert_eq!(
+            failed.report(),
+            "exit_code: 101\nduration: 1.25s\ntruncated: false\nstdout:\nrunning 3 tests\ntest a ... FAILED\n\nstderr:\nerror: test failed\n"
+        );
+
+        let succeeded = output(Completion::Status(0), "ok\n", "");
+        assert!(succeeded.success());
+        assert_eq!(succeeded.report(), "exit_code: 0\nduration: 1.25s\ntruncated: false\nstdout:\nok\n\nstderr:\n");
+    }
+
+    #[test]
+    fn reports_extra_fields_and_how_a_command_was_stopped() {
+        let timed_out = output(Completion::TimedOut, "", "");
+        assert!(!timed_out.success());
+        assert_eq!(
+            timed_out.report_with(&[("cwd", "/tmp/project")]),
+            "exit_code: none (timed out and killed)\nduration: 1.25s\ntruncated: false\ncwd: /tmp/project\nstdout:\n\nstderr:\n"
+        );
+        assert_eq!(Completion::Cancelled.to_string(), "none (cancelled by the user and killed)");
+    }
+
+    #[test]
+    fn previews_stderr_in_red_or_else_the_start_of_stdout() {
+        let stdout: String = (1..=20).map(|line| format!("{}\n", line)).collect();
+        assert_eq!(preview(&output(Completion::Status(0), &stdout, "")), "1\n2\n3\n4\n5\n6\n7\n8\n9\n10");
+        assert_eq!(preview(&output(Completion::Status(1), &stdout, "oops\n")), "\x1b[31moops\x1b[0m");
+    }
+}
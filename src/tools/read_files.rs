@@ -1,10 +1,65 @@
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use base64::Engine;
 use serde::Deserialize;
 
-use crate::{core::Tool, error::{ToolError, Result}, io::IO};
+use super::{FileTracker, PathGuard};
+use crate::{core::{Attachment, Tool, ToolOutput}, error::{ToolError, Result}, io::IO};
 
-pub struct ReadFilesTool;
+/// Lines returned per file when no limit is given.
+const DEFAULT_LIMIT: usize = 2_000;
+/// Longer lines are cut, so that minified files do not flood the context.
+const MAX_LINE_CHARS: usize = 2_000;
+/// Output per file and for all files of one call, in bytes.
+const MAX_FILE_OUTPUT: usize = 100 * 1024;
+const MAX_TOTAL_OUTPUT: usize = 256 * 1024;
+/// Larger files are streamed rather than read into memory, and are not tracked for changes.
+const MAX_TRACKED_BYTES: u64 = 10 * 1024 * 1024;
+/// Largest images and PDFs passed to the model, within the limits of the providers' APIs.
+const MAX_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+const MAX_DOCUMENT_BYTES: u64 = MAX_TRACKED_BYTES;
+/// Base64-encoded attachments of one call, in bytes; further images and PDFs are left out, so
+/// that the request stays within the providers' size limits.
+const MAX_TOTAL_ATTACHMENT_BYTES: usize = 16 * 1024 * 1024;
+
+pub struct ReadFilesTool {
+    tracker: Arc<FileTracker>,
+    guard: PathGuard,
+}
+
+impl ReadFilesTool {
+    pub fn new(tracker: Arc<FileTracker>, guard: PathGuard) -> Self {
+        Self { tracker, guard }
+    }
+
+    /// Reads the requested part of a text file, or the whole of an image or PDF. Files small
+    /// enough to hold in memory are recorded in the file tracker.
+    fn read(&self, path: &Path, offset: usize, limit: usize, budget: usize) -> std::io::Result<FileContent> {
+        let size = std::fs::metadata(path)?.len();
+        if size <= MAX_TRACKED_BYTES {
+            let data = std::fs::read(path)?;
+            if let Some(media_type) = media_type(&data) {
+                return attach(path, media_type, data);
+            }
+            if is_binary(&data) {
+                return Ok(FileContent::Binary(size));
+            }
+            self.tracker.record(path, &data);
+            return excerpt(data.as_slice(), offset, limit, budget).map(FileContent::Text);
+        }
+
+        let mut reader = BufReader::new(std::fs::File::open(path)?);
+        if let Some(media_type) = media_type(reader.fill_buf()?) {
+            return Err(too_large(media_type, size));
+        }
+        if is_binary(reader.fill_buf()?) {
+            return Ok(FileContent::Binary(size));
+        }
+        excerpt(reader, offset, limit, budget).map(FileContent::Text)
+    }
+}
 
 #[derive(Deserialize, Debug)]
 pub struct Input {
@@ -13,11 +68,118 @@ pub struct Input {
     offset: Option<usize>,
 }
 
-fn get_paths(input: &Input) -> Result<Vec<PathBuf>> {
-    let cwd = std::env::current_dir().map_err(|e| ToolError::ExecutionFailed {
-        reason: format!("read_files: Failed to get current working directory: {}", e)
-    })?;
-    Ok(input.paths.iter().map(|p| cwd.join(p)).collect())
+fn get_paths(input: &Input, guard: &PathGuard) -> Result<Vec<PathBuf>> {
+    input.paths.iter().map(|p| guard.resolve("read_files", p)).collect()
+}
+
+/// The part of a file that is shown to the model.
+struct Excerpt {
+    /// Selected lines, numbered from 1 like the line ranges of write_file.
+    content: String,
+    total_lines: usize,
+    notes: Vec<String>,
+}
+
+/// What read_files returns for one file.
+enum FileContent {
+    Text(Excerpt),
+    Attachment(Attachment),
+    /// A binary file that cannot be shown, with its size.
+    Binary(u64),
+}
+
+/// Recognises the images and documents that can be passed to the model by their first bytes.
+fn media_type(data: &[u8]) -> Option<&'static str> {
+    match data {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [b'%', b'P', b'D', b'F', b'-', ..] => Some("application/pdf"),
+        _ => None,
+    }
+}
+
+fn size_limit(media_type: &str) -> u64 {
+    if media_type.starts_with("image/") { MAX_IMAGE_BYTES } else { MAX_DOCUMENT_BYTES }
+}
+
+fn too_large(media_type: &str, size: u64) -> std::io::Error {
+    std::io::Error::other(format!(
+        "{} file of {} bytes is too large to pass to the model; the limit is {} bytes",
+        media_type, size, size_limit(media_type)
+    ))
+}
+
+fn attach(path: &Path, media_type: &str, data: Vec<u8>) -> std::io::Result<FileContent> {
+    if data.len() as u64 > size_limit(media_type) {
+        return Err(too_large(media_type, data.len() as u64));
+    }
+    Ok(FileContent::Attachment(Attachment {
+        path: path.display().to_string(),
+        media_type: media_type.to_string(),
+        data: base64::engine::general_purpose::STANDARD.encode(data),
+    }))
+}
+
+/// Whether the data looks like a binary file rather than text.
+fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(8192)].contains(&0)
+}
+
+/// Numbers the lines after the first `offset`, up to `limit` lines or `max_bytes` of output, and
+/// counts the remaining lines. Invalid UTF-8 is replaced rather than refused.
+fn excerpt(reader: impl BufRead, offset: usize, limit: usize, max_bytes: usize) -> std::io::Result<Excerpt> {
+    let mut content = String::new();
+    let mut notes = Vec::new();
+    let mut total_lines = 0;
+    let mut shown = 0;
+    let mut invalid_utf8 = false;
+    let mut long_lines = false;
+    let mut cut = false;
+
+    for line in reader.split(b'\n') {
+        let line = line?;
+        total_lines += 1;
+        if total_lines <= offset || shown >= limit || cut {
+            continue;
+        }
+        let text = String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(&line));
+        invalid_utf8 |= matches!(text, std::borrow::Cow::Owned(_));
+        let text = if text.chars().count() > MAX_LINE_CHARS {
+            long_lines = true;
+            format!("{}… [line cut]", text.chars().take(MAX_LINE_CHARS).collect::<String>())
+        } else {
+            text.into_owned()
+        };
+        let numbered = format!("{:>6}\t{}\n", total_lines, text);
+        if content.len() + numbered.len() > max_bytes && shown > 0 {
+            cut = true;
+            continue;
+        }
+        content.push_str(&numbered);
+        shown += 1;
+    }
+    content.pop();
+
+    let last = offset + shown;
+    if offset >= total_lines && total_lines > 0 {
+        notes.push(format!("offset {} is past the end of the file, which has {} lines", offset, total_lines));
+    } else if cut {
+        notes.push(format!(
+            "showing lines {}-{} of {}, as the output reached its size limit; read on with offset {}",
+            offset + 1, last, total_lines, last
+        ));
+    } else if last < total_lines {
+        notes.push(format!("showing lines {}-{} of {}; read on with offset {}", offset + 1, last, total_lines, last));
+    }
+    if long_lines {
+        notes.push(format!("lines longer than {} characters were cut", MAX_LINE_CHARS));
+    }
+    if invalid_utf8 {
+        notes.push("the file is not valid UTF-8; invalid bytes are shown as \u{FFFD}".to_string());
+    }
+    Ok(Excerpt { content, total_lines, notes })
 }
 
 impl Tool for ReadFilesTool {
@@ -26,9 +188,12 @@ impl Tool for ReadFilesTool {
     }
 
     fn description(&self) -> String {
-        "Read files. The paths must be relative to the the current working directory.\
-         Each file will be read and returned as a string. Optionally, you can provide a limit and offset for the lines to be read.\
-         This is generally a good idea when you want to get a quick sense of what a file contains while preserving some space in your context.\n\
+        "Read files. The paths must be relative to the the current working directory. \
+         Each file is returned with numbered lines (the numbers are not part of the file) and its total line count; use these numbers for line ranges in write_file. \
+         Optionally, you can provide a limit and offset for the lines to be read. \
+         This is generally a good idea when you want to get a quick sense of what a file contains while preserving some space in your context. \
+         Without a limit, up to 2000 lines are returned, and very long output is cut with a note saying how to read on. \
+         Images (PNG, JPEG, GIF, WebP) and PDFs are attached to the result for you to view; other binary files are not shown.\n\
          Never read a file without having first validated that the path exist; especially if the user has given you a filename in their message.\n\n\
          Always prefer reading multiple files at once, rather than calling this tool multiple times, provided that you know which files you want to read. Doing so is more efficient.
          ".to_owned()
@@ -36,7 +201,7 @@ impl Tool for ReadFilesTool {
 
     fn ask_permission(&self, args: serde_json::Value, io: &mut Box<dyn IO>) {
         let input: Input = serde_json::from_value(args).unwrap_or(Input { paths: vec!["<invalid>".to_string()], limit: None, offset: None });
-        let display_paths: Vec<String> = get_paths(&input).unwrap_or_default()
+        let display_paths: Vec<String> = get_paths(&input, &self.guard).unwrap_or_default()
             .iter()
             .map(|p| p.to_string_lossy())
             .map(|s| s.to_string())
@@ -51,8 +216,18 @@ impl Tool for ReadFilesTool {
         );
     }
 
-    fn permission_id(&self, _args: serde_json::Value) -> Result<String> {
-        Ok(String::from("read_files"))
+    fn permission_ids(&self, args: serde_json::Value) -> Result<Vec<String>> {
+        let input: Input = serde_json::from_value(args)
+            .map_err(|e| ToolError::InvalidArguments {
+                reason: format!("read_files: {}", e)
+            })?;
+        let mut ids = vec![String::from("read_files")];
+        ids.extend(self.guard.permission_ids("read_files", input.paths.iter().map(String::as_str))?);
+        Ok(ids)
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
     }
 
     fn input_schema(&self) -> serde_json::Value {
@@ -69,7 +244,7 @@ impl Tool for ReadFilesTool {
                 "limit": {
                     "type": "integer",
                     "minimum": 0,
-                    "description": "Number of lines to read from each file. If not provided, the entire file will be read."
+                    "description": "Number of lines to read from each file. If not provided, up to 2000 lines will be read."
                 },
                 "offset": {
                     "type": "integer",
@@ -85,7 +260,7 @@ impl Tool for ReadFilesTool {
         &'a self,
         args: serde_json::Value,
         io: &'a mut Box<dyn IO>,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String>> + Send + 'a>>
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ToolOutput>> + Send + 'a>>
     {
         Box::pin(async move {
             let input: Input = serde_json::from_value(args)
@@ -93,45 +268,164 @@ impl Tool for ReadFilesTool {
                     reason: format!("read_files: {}", e)
                 })?;
 
-            let paths = get_paths(&input)?;
+            let paths = get_paths(&input, &self.guard)?;
+            let offset = input.offset.unwrap_or(0);
+            let limit = input.limit.unwrap_or(DEFAULT_LIMIT);
             let mut output = String::new();
+            let mut attachments = Vec::new();
+            let mut attached_bytes = 0;
             for path in &paths {
-                match std::fs::read_to_string(path) {
-                    Ok(data) => {
-                        let lines = data.lines().collect::<Vec<_>>();
-                        let total_lines = lines.len();
-                        let limit = input.limit.unwrap_or(total_lines);
-                        let offset = input.offset.unwrap_or(0);
-                        let end_index = (offset + limit).min(total_lines);
-                        let sampled_lines = &lines[offset..end_index];
-
-                        // Create content without line numbers
-                        let content = sampled_lines.join("\n");
-                        
-                        // Show snippet to user
-                        io.show_snippet(&format!("deputy is reading {}", &path.to_string_lossy()), &content);
-
-                        // Add to output for LLM
+                let display = self.guard.display(path);
+                let budget = MAX_TOTAL_OUTPUT.saturating_sub(output.len()).min(MAX_FILE_OUTPUT);
+                if budget == 0 {
+                    output.push_str(&format!(
+                        "<path>\n{}\n</path>\n<error>\nnot read because this call's output limit was reached; read it separately\n</error>\n",
+                        display
+                    ));
+                    continue;
+                }
+
+                match self.read(path, offset, limit, budget) {
+                    Ok(FileContent::Text(excerpt)) => {
+                        io.show_snippet(&format!("deputy is reading {}", display), &excerpt.content);
+                        output.push_str(&format!(
+                            "<path>\n{}\n</path>\n<data total_lines=\"{}\">\n{}\n</data>\n",
+                            display,
+                            excerpt.total_lines,
+                            excerpt.content
+                        ));
+                        for note in excerpt.notes {
+                            output.push_str(&format!("<note>\n{}\n</note>\n", note));
+                        }
+                    }
+                    Ok(FileContent::Attachment(attachment)) if attached_bytes + attachment.data.len() > MAX_TOTAL_ATTACHMENT_BYTES => {
+                        io.show_message(&format!("Not attaching: {}", display), "this call's attachments reached their size limit");
+                        output.push_str(&format!(
+                            "<path>\n{}\n</path>\n<error>\nnot attached because this call's attachments reached their size limit of {} MB; read it separately\n</error>\n",
+                            display,
+                            MAX_TOTAL_ATTACHMENT_BYTES / (1024 * 1024)
+                        ));
+                    }
+                    Ok(FileContent::Attachment(mut attachment)) => {
+                        let description = format!("{} file; attached to this result", attachment.media_type);
+                        io.show_snippet(&format!("deputy is reading {}", display), &description);
+                        output.push_str(&format!(
+                            "<path>\n{}\n</path>\n<attachment>\n{}\n</attachment>\n",
+                            display, description
+                        ));
+                        attached_bytes += attachment.data.len();
+                        attachment.path = display;
+                        attachments.push(attachment);
+                    }
+                    Ok(FileContent::Binary(size)) => {
+                        io.show_message(&format!("Not reading: {}", display), "binary file");
                         output.push_str(&format!(
-                            "<path>\n{}\n</path>\n<data>\n{}\n</data>\n",
-                            path.display(),
-                            content
+                            "<path>\n{}\n</path>\n<error>\nbinary file ({} bytes); not shown\n</error>\n",
+                            display, size
                         ));
                     }
                     Err(error) => {
                         io.show_message(
-                            &format!("Error reading: {}", path.to_string_lossy()),
+                            &format!("Error reading: {}", display),
                             &error.to_string(),
                         );
                         output.push_str(&format!(
                             "<path>\n{}\n</path>\n<error>\n{}\n</error>\n",
-                            path.display(),
+                            display,
                             error,
                         ));
                     }
                 };
             }
-            Ok(output)
+            Ok(ToolOutput { text: output, attachments })
         })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(count: usize) -> String {
+        (1..=count).map(|line| format!("line {}\n", line)).collect()
+    }
+
+    fn excerpt_of(text: &str, offset: usize, limit: usize) -> Excerpt {
+        excerpt(text.as_bytes(), offset, limit, MAX_FILE_OUTPUT).unwrap()
+    }
+
+    #[test]
+    fn numbers_the_selected_lines() {
+        let shown = excerpt_of(&lines(3), 0, DEFAULT_LIMIT);
+        assert_eq!(shown.content, "     1\tline 1\n     2\tline 2\n     3\tline 3");
+        assert_eq!(shown.total_lines, 3);
+        assert!(shown.notes.is_empty());
+
+        let shown = excerpt_of("a\r\nb\r\n", 1, 1);
+        assert_eq!(shown.content, "     2\tb");
+    }
+
+    #[test]
+    fn says_how_to_read_on() {
+        let shown = excerpt_of(&lines(10), 2, 3);
+        assert_eq!(shown.content, "     3\tline 3\n     4\tline 4\n     5\tline 5");
+        assert_eq!(shown.notes, vec!["showing lines 3-5 of 10; read on with offset 5"]);
+
+        let shown = excerpt_of(&lines(10), 20, 3);
+        assert_eq!(shown.content, "");
+        assert_eq!(shown.notes, vec!["offset 20 is past the end of the file, which has 10 lines"]);
+    }
+
+    #[test]
+    fn stops_at_the_size_limit() {
+        // each numbered line takes 14 bytes
+        let shown = excerpt(lines(9).as_bytes(), 0, DEFAULT_LIMIT, 30).unwrap();
+        assert_eq!(shown.content, "     1\tline 1\n     2\tline 2");
+        assert_eq!(shown.total_lines, 9);
+        assert_eq!(shown.notes, vec!["showing lines 1-2 of 9, as the output reached its size limit; read on with offset 2"]);
+
+        // a single line is shown even if it exceeds the limit on its own
+        assert_eq!(excerpt("a long line".as_bytes(), 0, DEFAULT_LIMIT, 4).unwrap().content, "     1\ta long line");
+    }
+
+    #[test]
+    fn cuts_long_lines_and_replaces_invalid_utf8() {
+        let long = "x".repeat(MAX_LINE_CHARS + 10);
+        let shown = excerpt_of(&long, 0, DEFAULT_LIMIT);
+        assert!(shown.content.ends_with("x… [line cut]"));
+        assert_eq!(shown.notes, vec![format!("lines longer than {} characters were cut", MAX_LINE_CHARS)]);
+
+        let shown = excerpt(&b"caf\xE9\n"[..], 0, DEFAULT_LIMIT, MAX_FILE_OUTPUT).unwrap();
+        assert_eq!(shown.content, "     1\tcaf\u{FFFD}");
+        assert!(shown.notes[0].contains("not valid UTF-8"));
+    }
+
+    #[test]
+    fn recognises_binary_files_and_attachments() {
+        assert!(is_binary(b"ELF\0\x01"));
+        assert!(!is_binary("plain text".as_bytes()));
+        assert_eq!(media_type(b"\x89PNG\r\n\x1a\n"), Some("image/png"));
+        assert_eq!(media_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(media_type(b"%PDF-1.7"), Some("application/pdf"));
+        assert_eq!(media_type(b"GIF8"), None);
+        assert!(attach(Path::new("big.png"), "image/png", vec![0; MAX_IMAGE_BYTES as usize + 1]).is_err());
+    }
+
+    #[tokio::test]
+    async fn limits_the_attachments_of_one_call() {
+        let dir = crate::testing::temp_project(&[]);
+        // each image takes 6 MB once encoded, so only two fit
+        let mut image = b"\x89PNG\r\n\x1a\n".to_vec();
+        image.resize(4_718_592, 0);
+        for name in ["a.png", "b.png", "c.png"] {
+            std::fs::write(dir.join(name), &image).unwrap();
+        }
+        let tool = ReadFilesTool::new(Arc::new(FileTracker::new()), PathGuard::new(&dir, &[]).unwrap());
+        let mut io: Box<dyn IO> = Box::new(crate::io::HeadlessIO::new());
+
+        let output = tool.call(serde_json::json!({ "paths": ["a.png", "b.png", "c.png"] }), &mut io).await.unwrap();
+
+        assert_eq!(output.attachments.iter().map(|attachment| attachment.path.as_str()).collect::<Vec<_>>(), vec!["a.png", "b.png"]);
+        assert!(output.text.contains("<path>\nc.png\n</path>\n<error>\nnot attached because this call's attachments reached their size limit"), "{}", output.text);
+    }
+}
@@ -1,20 +1,46 @@
-use crate::core::Tool;
-use super::{ExecCommandTool, ListFilesTool, ReadFilesTool, WriteFileTool};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{context::Context, core::Tool, error::Result};
+use super::{
+    BackgroundProcesses, ExecCommandTool, FileTracker, FindFilesTool, KillProcessTool, ListFilesTool, ListProcessesTool, PathGuard,
+    ReadFilesTool, ReadProcessOutputTool, Sandbox, SearchFilesTool, ShellSessionTool, StartProcessTool, WriteFileTool,
+};
 
 pub struct ToolRegistry {
     tools: Vec<Box<dyn Tool>>,
 }
 
 impl ToolRegistry {
-    pub fn with_default_tools() -> Self {
-        Self {
-            tools: vec![
-                Box::new(ListFilesTool {}),
-                Box::new(ReadFilesTool {}),
-                Box::new(WriteFileTool {}),
-                Box::new(ExecCommandTool {}),
-            ],
+    pub fn with_default_tools(context: &Context) -> Result<Self> {
+        let sandbox = context
+            .model_config
+            .sandbox
+            .clone()
+            .map(|config| Sandbox::new(config, Path::new(context.session_config.cwd())))
+            .transpose()?;
+
+        let background = Arc::new(BackgroundProcesses::new(sandbox.clone()));
+        let tracker = Arc::new(FileTracker::new());
+        let guard = PathGuard::new(Path::new(context.session_config.cwd()), &context.model_config.allowed_roots)?;
+
+        let mut tools: Vec<Box<dyn Tool>> = vec![
+            Box::new(ListFilesTool::new(guard.clone())),
+            Box::new(ReadFilesTool::new(tracker.clone(), guard.clone())),
+            Box::new(FindFilesTool::new(guard.clone())),
+            Box::new(SearchFilesTool::new(guard.clone())),
+            Box::new(WriteFileTool::new(tracker, context.session_config.checkpoints().clone(), guard)),
+            Box::new(ExecCommandTool::new(sandbox.clone())),
+            Box::new(StartProcessTool::new(background.clone())),
+            Box::new(ReadProcessOutputTool::new(background.clone())),
+            Box::new(ListProcessesTool::new(background.clone())),
+            Box::new(KillProcessTool::new(background)),
+        ];
+        if context.model_config.persistent_shell {
+            tools.push(Box::new(ShellSessionTool::new(sandbox)));
         }
+
+        Ok(Self { tools })
     }
 
     pub fn into_tools(self) -> Vec<Box<dyn Tool>> {
@@ -0,0 +1,174 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::{ConfigError, Result};
+
+/// Restrictions for shell commands run by deputy's tools.
+#[derive(Debug, Clone, Default)]
+pub struct SandboxConfig {
+    /// Whether sandboxed commands may use the network.
+    pub allow_network: bool,
+    /// Paths, in addition to the working directory and a private `/tmp`, that commands may write to.
+    pub writable_paths: Vec<PathBuf>,
+}
+
+/// Runs shell commands inside a bubblewrap (`bwrap`) sandbox: the filesystem is mounted read-only
+/// except for the working directory, a private `/tmp` and any configured writable paths, and the
+/// command gets its own process (and optionally network) namespace. The project's `.deputy`
+/// directory and git hooks stay read-only, so that a command cannot change its own permissions or
+/// plant code that runs outside the sandbox.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    bwrap: PathBuf,
+    cwd: PathBuf,
+    config: SandboxConfig,
+}
+
+impl Sandbox {
+    /// Checks that bubblewrap is available and that every writable path exists.
+    pub fn new(config: SandboxConfig, cwd: &Path) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            return Err(ConfigError::Invalid {
+                reason: "sandbox: only supported on Linux".to_string()
+            }.into());
+        }
+        let bwrap = Self::find_bwrap().ok_or_else(|| ConfigError::Missing {
+            reason: "sandbox: bubblewrap (bwrap) was not found on PATH".to_string()
+        })?;
+
+        let mut config = config;
+        config.writable_paths = config
+            .writable_paths
+            .iter()
+            .map(|path| {
+                path.canonicalize().map_err(|e| ConfigError::Invalid {
+                    reason: format!("sandbox: writable path {}: {}", path.display(), e)
+                })
+            })
+            .collect::<std::result::Result<_, _>>()?;
+
+        Ok(Self {
+            bwrap,
+            cwd: cwd.to_path_buf(),
+            config,
+        })
+    }
+
+    /// Explains the restrictions to the model, so that it does not mistake them for failures.
+    pub fn describe(&self) -> String {
+        let mut writable = vec!["the working directory".to_string(), "a private /tmp".to_string()];
+        writable.extend(self.config.writable_paths.iter().map(|path| path.display().to_string()));
+        format!(
+            "Commands run in a sandbox: the filesystem is read-only except for {}, and network access is {}.",
+            writable.join(", "),
+            if self.config.allow_network { "allowed" } else { "disabled" }
+        )
+    }
+
+    /// Builds the command that runs `script` with `sh -c` inside the sandbox.
+    pub fn command(&self, script: &str) -> Command {
+        let mut command = Command::new(&self.bwrap);
+        command
+            .args(["--ro-bind", "/", "/"])
+            .args(["--dev", "/dev"])
+            .args(["--proc", "/proc"])
+            .args(["--tmpfs", "/tmp"]);
+        // bound after /tmp, so that a working directory below /tmp stays visible
+        for path in std::iter::once(&self.cwd).chain(&self.config.writable_paths) {
+            command.arg("--bind").arg(path).arg(path);
+        }
+        for path in [self.cwd.join(".deputy"), self.cwd.join(".git").join("hooks")] {
+            if path.exists() {
+                command.arg("--ro-bind").arg(&path).arg(&path);
+            }
+        }
+        command.args(["--unshare-pid", "--die-with-parent", "--new-session"]);
+        if !self.config.allow_network {
+            command.arg("--unshare-net");
+        }
+        command
+            .arg("--chdir")
+            .arg(&self.cwd)
+            .args(["--", "sh", "-c", script]);
+        command
+    }
+
+    fn find_bwrap() -> Option<PathBuf> {
+        let path = std::env::var_os("PATH")?;
+        std::env::split_paths(&path)
+            .map(|dir| dir.join("bwrap"))
+            .find(|candidate| candidate.is_file())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sandbox(allow_network: bool, writable_paths: Vec<PathBuf>) -> Sandbox {
+        Sandbox {
+            bwrap: PathBuf::from("/usr/bin/bwrap"),
+            cwd: PathBuf::from("/tmp/project"),
+            config: SandboxConfig { allow_network, writable_paths },
+        }
+    }
+
+    fn args(command: &Command) -> Vec<String> {
+        command.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect()
+    }
+
+    #[test]
+    fn mounts_the_filesystem_read_only_except_for_writable_paths() {
+        let command = sandbox(true, vec![PathBuf::from("/home/me/.cache")]).command("cargo build");
+        assert_eq!(command.get_program(), "/usr/bin/bwrap");
+        let args = args(&command).join(" ");
+        assert!(args.starts_with("--ro-bind / / --dev /dev --proc /proc --tmpfs /tmp "));
+        // the working directory is bound after the private /tmp that would otherwise hide it
+        assert!(args.contains("--tmpfs /tmp --bind /tmp/project /tmp/project --bind /home/me/.cache /home/me/.cache"));
+        assert!(args.ends_with("--chdir /tmp/project -- sh -c cargo build"));
+        assert!(!args.contains("--unshare-net"));
+    }
+
+    #[test]
+    fn keeps_the_project_configuration_and_git_hooks_read_only() {
+        let project = crate::testing::temp_project(&[(".deputy/permissions.toml", ""), (".git/hooks/pre-commit", "")]);
+        let mut confined = sandbox(true, Vec::new());
+        confined.cwd = project.to_path_buf();
+        let bound = args(&confined.command("git commit")).join(" ");
+        let root = project.display();
+        assert!(
+            bound.contains(&format!(
+                "--bind {root} {root} --ro-bind {root}/.deputy {root}/.deputy --ro-bind {root}/.git/hooks {root}/.git/hooks --unshare-pid"
+            )),
+            "{}",
+            bound
+        );
+
+        let project = crate::testing::temp_project(&[]);
+        let mut confined = sandbox(true, Vec::new());
+        confined.cwd = project.to_path_buf();
+        let bound = args(&confined.command("ls")).join(" ");
+        assert!(!bound.contains(".deputy") && !bound.contains(".git/hooks"), "{}", bound);
+    }
+
+    #[test]
+    fn disables_the_network_unless_allowed() {
+        let command = sandbox(false, Vec::new()).command("curl example.com");
+        assert!(args(&command).contains(&"--unshare-net".to_string()));
+    }
+
+    #[test]
+    fn passes_the_script_as_a_single_argument() {
+        let command = sandbox(true, Vec::new()).command("echo a && echo 'b c'");
+        assert_eq!(args(&command).last().map(String::as_str), Some("echo a && echo 'b c'"));
+    }
+
+    #[test]
+    fn describes_its_restrictions() {
+        let description = sandbox(false, vec![PathBuf::from("/opt/cache")]).describe();
+        assert_eq!(
+            description,
+            "Commands run in a sandbox: the filesystem is read-only except for the working directory, a private /tmp, /opt/cache, and network access is disabled."
+        );
+    }
+}
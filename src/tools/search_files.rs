@@ -0,0 +1,412 @@
+use globset::{GlobSet, GlobSetBuilder};
+use ignore::types::TypesBuilder;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use std::path::Path;
+
+use super::{PathGuard, name_glob, project_walker};
+use crate::{core::{Tool, ToolOutput}, error::{ToolError, Result}, io::IO};
+
+const DEFAULT_MAX_RESULTS: usize = 100;
+const MAX_RESULTS: usize = 1_000;
+const MAX_CONTEXT_LINES: usize = 10;
+/// Files larger than this are skipped; they are rarely source code.
+const MAX_FILE_BYTES: u64 = 2 * 1024 * 1024;
+/// Matched lines are cut to this many characters, so that minified files do not flood the output.
+const MAX_LINE_CHARS: usize = 300;
+
+pub struct SearchFilesTool {
+    guard: PathGuard,
+}
+
+impl SearchFilesTool {
+    pub fn new(guard: PathGuard) -> Self {
+        Self { guard }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum OutputMode {
+    #[default]
+    Content,
+    Files,
+    Count,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Input {
+    pattern: String,
+    #[serde(default)]
+    path: String,
+    #[serde(default)]
+    literal: bool,
+    #[serde(default)]
+    case_insensitive: bool,
+    glob: Option<String>,
+    file_type: Option<String>,
+    context: Option<usize>,
+    max_results: Option<usize>,
+    #[serde(default)]
+    output_mode: OutputMode,
+    #[serde(default)]
+    include_hidden: bool,
+}
+
+impl Input {
+    fn regex(&self) -> Result<Regex> {
+        let pattern = if self.literal { regex::escape(&self.pattern) } else { self.pattern.clone() };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(self.case_insensitive)
+            .build()
+            .map_err(|e| ToolError::InvalidArguments {
+                reason: format!("search_files: invalid pattern: {}", e)
+            }.into())
+    }
+
+    /// The `glob` filter. Like in find_files, a glob without a `/` matches file names in any
+    /// directory and other globs match paths relative to the searched directory.
+    fn glob_filter(&self) -> Result<Option<GlobFilter>> {
+        let Some(glob) = &self.glob else {
+            return Ok(None);
+        };
+        let (pattern, exclude) = match glob.strip_prefix('!') {
+            Some(pattern) => (pattern, true),
+            None => (glob.as_str(), false),
+        };
+        let globs = name_glob(pattern)
+            .and_then(|glob| GlobSetBuilder::new().add(glob).build())
+            .map_err(|e| ToolError::InvalidArguments {
+                reason: format!("search_files: invalid glob {}: {}", glob, e)
+            })?;
+        Ok(Some(GlobFilter { globs, exclude }))
+    }
+
+    fn walker(&self, root: &Path) -> Result<ignore::Walk> {
+        let mut builder = project_walker(root, self.include_hidden);
+        builder.sort_by_file_name(|a, b| a.cmp(b));
+
+        if let Some(file_type) = &self.file_type {
+            let types = TypesBuilder::new()
+                .add_defaults()
+                .select(file_type)
+                .build()
+                .map_err(|e| ToolError::InvalidArguments {
+                    reason: format!("search_files: invalid file type {}: {}", file_type, e)
+                })?;
+            builder.types(types);
+        }
+        Ok(builder.build())
+    }
+}
+
+/// Restricts the search to files matching a glob, or with a leading `!`, to the other files.
+/// Unlike the walker's overrides, it never brings back files that `.gitignore` excludes.
+struct GlobFilter {
+    globs: GlobSet,
+    exclude: bool,
+}
+
+impl GlobFilter {
+    fn allows(&self, relative: &Path) -> bool {
+        self.globs.is_match(relative) != self.exclude
+    }
+}
+
+/// The matches found in one file.
+struct FileMatches {
+    path: String,
+    lines: Vec<String>,
+    matched: Vec<usize>,
+}
+
+impl FileMatches {
+    /// Renders the matched lines, ripgrep style: `path:line:text` for matches and
+    /// `path-line-text` for context, with `--` between separate groups when showing context.
+    fn render(&self, context: usize, output: &mut Vec<String>) {
+        let mut matched = self.matched.iter().peekable();
+        while let Some(&first) = matched.next() {
+            let start = first.saturating_sub(context);
+            let mut end = (first + context).min(self.lines.len() - 1);
+            // merge groups whose context overlaps
+            while let Some(&&next) = matched.peek() {
+                if next.saturating_sub(context) > end + 1 {
+                    break;
+                }
+                end = (next + context).min(self.lines.len() - 1);
+                matched.next();
+            }
+
+            if context > 0 && !output.is_empty() {
+                output.push("--".to_string());
+            }
+            for index in start..=end {
+                let separator = if self.matched.binary_search(&index).is_ok() { ':' } else { '-' };
+                output.push(format!("{}{}{}{}{}", self.path, separator, index + 1, separator, truncate(&self.lines[index])));
+            }
+        }
+    }
+}
+
+fn truncate(line: &str) -> String {
+    if line.chars().count() > MAX_LINE_CHARS {
+        format!("{}…", line.chars().take(MAX_LINE_CHARS).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Reads a file as text, or returns `None` for files that are too large or look binary.
+fn read_text(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if metadata.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    let bytes = std::fs::read(path).ok()?;
+    if bytes[..bytes.len().min(8192)].contains(&0) {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+impl Tool for SearchFilesTool {
+    fn name(&self) -> String {
+        "search_files".to_owned()
+    }
+
+    fn description(&self) -> String {
+        "Search the contents of files for a regular expression (or a literal string), respecting .gitignore. Prefer this over running grep or rg through a shell. \
+         Narrow the search with `path`, a `glob` such as \"src/**/*.rs\" or a `file_type` such as \"rust\" or \"py\". \
+         The output mode `content` (default) returns matching lines as path:line:text, with optional context lines; `files` returns only the paths of matching files; `count` returns the number of matching lines per file. \
+         Binary files, very large files and files that may hold secrets are skipped.".to_owned()
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "pattern": {
+                    "type": "string",
+                    "description": "Regular expression (Rust regex syntax) to search for, or a literal string if `literal` is true."
+                },
+                "path": {
+                    "type": "string",
+                    "description": "File or directory to search, relative to the current working directory. Defaults to the working directory."
+                },
+                "literal": {
+                    "type": "boolean",
+                    "description": "Treat the pattern as a literal string rather than a regular expression. Defaults to false."
+                },
+                "case_insensitive": {
+                    "type": "boolean",
+                    "description": "Ignore case when matching. Defaults to false."
+                },
+                "glob": {
+                    "type": "string",
+                    "description": "Only search files matching this glob, e.g. \"*.rs\" or \"src/**/*.ts\"; prefix with ! to exclude matching files instead."
+                },
+                "file_type": {
+                    "type": "string",
+                    "description": "Only search files of this type, as known to ripgrep, e.g. \"rust\", \"py\", \"js\", \"ts\", \"go\", \"md\"."
+                },
+                "context": {
+                    "type": "integer",
+                    "description": "Number of lines to show before and after each match in content mode (default 0, maximum 10)."
+                },
+                "max_results": {
+                    "type": "integer",
+                    "description": "Maximum number of matching lines (content mode) or files (files and count modes) to return (default 100, maximum 1000)."
+                },
+                "output_mode": {
+                    "type": "string",
+                    "enum": ["content", "files", "count"],
+                    "description": "What to return; defaults to content."
+                },
+                "include_hidden": {
+                    "type": "boolean",
+                    "description": "Also search hidden files and directories. Defaults to false."
+                }
+            },
+            "required": ["pattern"]
+        })
+    }
+
+    fn ask_permission(&self, args: serde_json::Value, io: &mut Box<dyn IO>) {
+        let (pattern, path) = match serde_json::from_value::<Input>(args) {
+            Ok(input) => (input.pattern, input.path),
+            Err(_) => ("<invalid>".to_string(), String::new()),
+        };
+        io.show_message(
+            "Permission request",
+            &format!(
+                "deputy wants to search for {:?} in {}",
+                pattern,
+                if path.is_empty() { "the working directory" } else { &path }
+            ),
+        );
+    }
+
+    fn permission_ids(&self, args: serde_json::Value) -> Result<Vec<String>> {
+        let input: Input = serde_json::from_value(args)
+            .map_err(|e| ToolError::InvalidArguments {
+                reason: format!("search_files: {}", e)
+            })?;
+        let mut ids = vec![String::from("search_files")];
+        ids.extend(self.guard.permission_ids("search_files", [input.path.as_str()])?);
+        Ok(ids)
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn call<'a>(
+        &'a self,
+        args: serde_json::Value,
+        io: &'a mut Box<dyn IO>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ToolOutput>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            let input: Input = serde_json::from_value(args)
+                .map_err(|e| ToolError::InvalidArguments {
+                    reason: format!("search_files: {}", e)
+                })?;
+
+            let root = self.guard.resolve("search_files", &input.path)?;
+            let regex = input.regex()?;
+            let context = input.context.unwrap_or(0).min(MAX_CONTEXT_LINES);
+            let max_results = input.max_results.unwrap_or(DEFAULT_MAX_RESULTS).clamp(1, MAX_RESULTS);
+
+            let mut output = Vec::new();
+            let mut results = 0;
+            let mut truncated = false;
+            let glob_filter = input.glob_filter()?;
+            for entry in input.walker(&root)?.flatten() {
+                if !entry.file_type().is_some_and(|file_type| file_type.is_file()) || self.guard.is_sensitive(entry.path()) {
+                    continue;
+                }
+                // a single file to search is matched by its name
+                let relative = match entry.path().strip_prefix(&root) {
+                    Ok(relative) if !relative.as_os_str().is_empty() => relative,
+                    _ => Path::new(entry.file_name()),
+                };
+                if glob_filter.as_ref().is_some_and(|filter| !filter.allows(relative)) {
+                    continue;
+                }
+                let Some(text) = read_text(entry.path()) else {
+                    continue;
+                };
+                let lines: Vec<String> = text.lines().map(str::to_string).collect();
+                let matched: Vec<usize> = lines
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, line)| regex.is_match(line))
+                    .map(|(index, _)| index)
+                    .collect();
+                if matched.is_empty() {
+                    continue;
+                }
+
+                if results >= max_results {
+                    truncated = true;
+                    break;
+                }
+                let path = self.guard.display(entry.path());
+                match input.output_mode {
+                    OutputMode::Files => {
+                        output.push(path);
+                        results += 1;
+                    }
+                    OutputMode::Count => {
+                        output.push(format!("{}: {}", path, matched.len()));
+                        results += 1;
+                    }
+                    OutputMode::Content => {
+                        let remaining = max_results - results;
+                        truncated = matched.len() > remaining;
+                        let matched: Vec<usize> = matched.into_iter().take(remaining).collect();
+                        results += matched.len();
+                        FileMatches { path, lines, matched }.render(context, &mut output);
+                        if truncated {
+                            break;
+                        }
+                    }
+                }
+            }
+
+            let mut text = if output.is_empty() {
+                format!("No matches for {:?}.", input.pattern)
+            } else {
+                output.join("\n")
+            };
+            if truncated {
+                text.push_str(&format!(
+                    "\n[... stopped after {} results; narrow the search or raise max_results ...]",
+                    max_results
+                ));
+            }
+            io.show_snippet(
+                &format!("deputy searched for {:?} in {}", input.pattern, self.guard.display(&root)),
+                &text.lines().take(10).collect::<Vec<&str>>().join("\n"),
+            );
+            Ok(text.into())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::io::HeadlessIO;
+    use crate::testing::{temp_project, TempProject};
+
+    /// A project with a `.git` directory, so that `.gitignore` applies.
+    fn project() -> TempProject {
+        temp_project(&[
+            ("src/main.rs", "fn main() {\n    let needle = 1;\n}\n"),
+            ("src/lib.rs", "// needle\n"),
+            ("docs/guide.md", "a needle in the docs\n"),
+            ("target/debug/build.rs", "needle\n"),
+            (".gitignore", "target/\n"),
+            (".git/config", "needle\n"),
+            (".env", "NEEDLE=secret\n"),
+        ])
+    }
+
+    async fn search(dir: &Path, args: serde_json::Value) -> String {
+        let tool = SearchFilesTool::new(PathGuard::new(dir, &[]).unwrap());
+        let mut io: Box<dyn IO> = Box::new(HeadlessIO::new());
+        tool.call(args, &mut io).await.unwrap().text
+    }
+
+    #[tokio::test]
+    async fn finds_matching_lines_outside_ignored_files() {
+        let dir = project();
+        let found = search(&dir, json!({ "pattern": "needle", "include_hidden": true })).await;
+        assert_eq!(found, "docs/guide.md:1:a needle in the docs\nsrc/lib.rs:1:// needle\nsrc/main.rs:2:    let needle = 1;");
+    }
+
+    #[tokio::test]
+    async fn globs_do_not_bring_back_ignored_files() {
+        let dir = project();
+        assert_eq!(search(&dir, json!({ "pattern": "needle", "glob": "*.rs", "output_mode": "files" })).await, "src/lib.rs\nsrc/main.rs");
+        assert_eq!(search(&dir, json!({ "pattern": "needle", "glob": "src/m*", "output_mode": "files" })).await, "src/main.rs");
+        assert_eq!(search(&dir, json!({ "pattern": "needle", "glob": "!*.rs", "output_mode": "files" })).await, "docs/guide.md");
+        assert_eq!(
+            search(&dir, json!({ "pattern": "needle", "path": "src/main.rs", "glob": "*.rs", "output_mode": "count" })).await,
+            "src/main.rs: 1"
+        );
+    }
+
+    #[tokio::test]
+    async fn shows_context_and_stops_at_the_limit() {
+        let dir = project();
+        let found = search(&dir, json!({ "pattern": "NEEDLE", "case_insensitive": true, "path": "src", "context": 1 })).await;
+        assert_eq!(found, "src/lib.rs:1:// needle\n--\nsrc/main.rs-1-fn main() {\nsrc/main.rs:2:    let needle = 1;\nsrc/main.rs-3-}");
+        let found = search(&dir, json!({ "pattern": "needle", "file_type": "rust", "max_results": 1 })).await;
+        assert!(found.starts_with("src/lib.rs:1:// needle\n[... stopped after 1 results"), "{}", found);
+        assert_eq!(search(&dir, json!({ "pattern": "needle", "output_mode": "count", "path": "src" })).await, "src/lib.rs: 1\nsrc/main.rs: 1");
+    }
+}
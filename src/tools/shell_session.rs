@@ -0,0 +1,393 @@
+use serde::Deserialize;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout};
+use tokio::sync::Mutex;
+
+use super::Sandbox;
+use super::process::{self, Completion, OutputBuffer, ProcessOutput, DEFAULT_TIMEOUT_SECS, MAX_TIMEOUT_SECS};
+use crate::{core::{interrupt::InterruptGuard, shell, Tool, ToolOutput}, error::{ToolError, Result}, io::IO};
+
+/// Prefers bash, so that the model can rely on the same syntax as in `exec_command`.
+const LAUNCH_SCRIPT: &str = "command -v bash >/dev/null 2>&1 && exec bash --noprofile --norc; exec sh";
+
+/// Removes the functions and aliases left behind by earlier commands before each command, so
+/// that an approved `ls` is not whatever an earlier command defined under that name; a changed
+/// `PATH` does carry over. `case` is a reserved word and `BASH_VERSINFO` is read-only, so neither
+/// can be redefined. Other shells cannot list their functions and only lose their aliases.
+const CLEAR_DEFINITIONS: &str = "case ${BASH_VERSINFO-} in '') unalias -a ;; *) builtin unset -f $(builtin compgen -A function); builtin unalias -a ;; esac 2>/dev/null";
+
+/// Resets the traps a command set once it has finished, so that a `DEBUG` or `EXIT` trap cannot
+/// run code during later commands. This happens before the shell reports the command's status,
+/// as a `DEBUG` trap still fires before the reset itself and its output belongs to the command.
+const CLEAR_TRAPS: &str = "case ${BASH_VERSINFO-} in '') trap - EXIT ;; *) builtin trap - DEBUG ERR RETURN EXIT ;; esac 2>/dev/null";
+
+/// Runs commands in one long-lived shell, so that the working directory, environment variables
+/// and activated virtualenvs carry over from one call to the next. Functions, aliases and traps
+/// do not.
+pub struct ShellSessionTool {
+    sandbox: Option<Sandbox>,
+    shell: Mutex<Option<ShellProcess>>,
+    calls: AtomicU64,
+}
+
+impl ShellSessionTool {
+    pub fn new(sandbox: Option<Sandbox>) -> Self {
+        Self {
+            sandbox,
+            shell: Mutex::new(None),
+            calls: AtomicU64::new(0),
+        }
+    }
+
+    /// A marker that the command's own output is very unlikely to contain.
+    fn next_marker(&self) -> String {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos());
+        format!("__DEPUTY_{}_{}_{}__", std::process::id(), self.calls.fetch_add(1, Ordering::Relaxed), nanos)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Input {
+    command: Option<String>,
+    timeout: Option<u64>,
+    #[serde(default)]
+    reset: bool,
+}
+
+/// How a command sent to the shell ended, if the shell survived it.
+enum Ending {
+    Finished { status: i32, cwd: String },
+    ShellExited,
+}
+
+struct ShellProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    stderr: BufReader<ChildStderr>,
+}
+
+impl ShellProcess {
+    fn spawn(sandbox: Option<&Sandbox>) -> std::io::Result<Self> {
+        let command = match sandbox {
+            Some(sandbox) => sandbox.command(LAUNCH_SCRIPT),
+            None => {
+                let mut command = std::process::Command::new("sh");
+                command.arg("-c").arg(LAUNCH_SCRIPT);
+                command
+            }
+        };
+        let mut command = tokio::process::Command::from(command);
+        process::prepare(&mut command);
+        command.stdin(Stdio::piped());
+
+        let mut child = command.spawn()?;
+        let stdin = child.stdin.take().ok_or_else(|| std::io::Error::other("shell stdin is not piped"))?;
+        let stdout = child.stdout.take().ok_or_else(|| std::io::Error::other("shell stdout is not piped"))?;
+        let stderr = child.stderr.take().ok_or_else(|| std::io::Error::other("shell stderr is not piped"))?;
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            stderr: BufReader::new(stderr),
+        })
+    }
+
+    /// Sends one command to the shell and collects its output up to the markers that the shell
+    /// prints once the command has finished. The command reads from /dev/null, so that it cannot
+    /// consume the commands that follow it, starts without functions or aliases and leaves no
+    /// traps behind.
+    async fn run(
+        &mut self,
+        command: &str,
+        marker: &str,
+        stdout: &mut OutputBuffer,
+        stderr: &mut OutputBuffer,
+    ) -> std::io::Result<Ending> {
+        let script = format!(
+            "{}\neval '{}' < /dev/null; set -- \"$?\"; {}; printf '\\n{} %s %s\\n' \"$1\" \"$PWD\"; printf '\\n{}\\n' >&2\n",
+            CLEAR_DEFINITIONS,
+            command.replace('\'', "'\\''"),
+            CLEAR_TRAPS,
+            marker,
+            marker
+        );
+        if self.stdin.write_all(script.as_bytes()).await.is_err() || self.stdin.flush().await.is_err() {
+            return Ok(Ending::ShellExited);
+        }
+
+        let (status_line, _) = tokio::try_join!(
+            read_until_marker(&mut self.stdout, marker, stdout),
+            read_until_marker(&mut self.stderr, marker, stderr)
+        )?;
+        let Some(status_line) = status_line else {
+            return Ok(Ending::ShellExited);
+        };
+        let (status, cwd) = status_line.split_once(' ').unwrap_or((&status_line, ""));
+        Ok(Ending::Finished {
+            status: status.parse().unwrap_or(-1),
+            cwd: cwd.to_string(),
+        })
+    }
+}
+
+impl Drop for ShellProcess {
+    fn drop(&mut self) {
+        process::kill(&mut self.child);
+    }
+}
+
+/// Copies lines into `buffer` until the marker line, and returns what follows the marker on
+/// that line, or `None` if the stream ended first. The newline printed before the marker, which
+/// guarantees that the marker starts a line, is removed again; to that end each line's newline
+/// is only copied once the next line has arrived.
+async fn read_until_marker(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    marker: &str,
+    buffer: &mut OutputBuffer,
+) -> std::io::Result<Option<String>> {
+    let mut newline_pending = false;
+    loop {
+        let mut line = Vec::new();
+        if reader.read_until(b'\n', &mut line).await? == 0 {
+            if newline_pending {
+                buffer.push(b"\n");
+            }
+            return Ok(None);
+        }
+        if line.starts_with(marker.as_bytes()) {
+            let rest = String::from_utf8_lossy(&line[marker.len()..]).trim().to_string();
+            return Ok(Some(rest));
+        }
+        if newline_pending {
+            buffer.push(b"\n");
+        }
+        newline_pending = line.ends_with(b"\n");
+        buffer.push(line.strip_suffix(b"\n").unwrap_or(&line));
+    }
+}
+
+impl Tool for ShellSessionTool {
+    fn name(&self) -> String {
+        "shell".to_owned()
+    }
+
+    fn description(&self) -> String {
+        let mut description = "Execute a bash command in a persistent shell that is shared by all calls: the working directory, exported environment variables and activated virtualenvs carry over to the next call, but shell functions, aliases and traps do not. The result reports the exit code, duration, the shell's working directory, stdout and stderr. Set `reset` to start over with a fresh shell in the project directory; the shell is also restarted after a command times out or is cancelled.".to_owned();
+        if let Some(sandbox) = &self.sandbox {
+            description.push_str(&format!(" {}", sandbox.describe()));
+        }
+        description
+    }
+
+    fn input_schema(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "command": {
+                    "type": "string",
+                    "description": "The bash command to execute. It cannot read from stdin."
+                },
+                "timeout": {
+                    "type": "integer",
+                    "description": "Seconds after which the command is killed together with the shell (default 120, maximum 600)."
+                },
+                "reset": {
+                    "type": "boolean",
+                    "description": "Restart the shell before running the command, discarding its working directory and environment. May be given without a command."
+                }
+            }
+        })
+    }
+
+    fn ask_permission(&self, args: serde_json::Value, io: &mut Box<dyn IO>) {
+        let input: Input = serde_json::from_value(args).unwrap_or(Input {
+            command: Some("<invalid command>".to_string()),
+            timeout: None,
+            reset: false,
+        });
+        match &input.command {
+            Some(command) => io.show_message(
+                if input.reset {
+                    "deputy wants to reset its shell and execute the following command"
+                } else {
+                    "deputy wants to execute the following command in its shell"
+                },
+                command,
+            ),
+            None => io.show_message("deputy wants to reset its shell", ""),
+        }
+    }
+
+    fn permission_ids(&self, args: serde_json::Value) -> Result<Vec<String>> {
+        let input: Input = serde_json::from_value(args)
+            .map_err(|e| ToolError::InvalidArguments {
+                reason: format!("shell: {}", e)
+            })?;
+        Ok(match &input.command {
+            Some(command) => shell::permission_ids(command),
+            None => vec!["reset".to_string()],
+        })
+    }
+
+    fn call<'a>(
+        &'a self,
+        args: serde_json::Value,
+        io: &'a mut Box<dyn IO>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ToolOutput>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            let input: Input = serde_json::from_value(args)
+                .map_err(|e| ToolError::InvalidArguments {
+                    reason: format!("shell: {}", e)
+                })?;
+
+            let mut shell = self.shell.lock().await;
+            if input.reset {
+                *shell = None;
+            }
+            let Some(command) = input.command else {
+                if input.reset {
+                    io.show_snippet("deputy reset its shell", "");
+                    return Ok("The shell was reset.".to_string().into());
+                }
+                return Err(ToolError::InvalidArguments {
+                    reason: "shell: either `command` or `reset` is required".to_string()
+                }.into());
+            };
+
+            if shell.is_none() {
+                *shell = Some(ShellProcess::spawn(self.sandbox.as_ref()).map_err(|e| ToolError::ExecutionFailed {
+                    reason: format!("shell: could not start the shell: {}", e)
+                })?);
+            }
+            let Some(process) = shell.as_mut() else {
+                unreachable!("the shell was started above");
+            };
+
+            let timeout_secs = input.timeout.unwrap_or(DEFAULT_TIMEOUT_SECS).clamp(1, MAX_TIMEOUT_SECS);
+            let marker = self.next_marker();
+            let started = Instant::now();
+            let interrupt = InterruptGuard::new();
+            let mut stdout = OutputBuffer::default();
+            let mut stderr = OutputBuffer::default();
+
+            let ending = tokio::select! {
+                ending = process.run(&command, &marker, &mut stdout, &mut stderr) => Ok(ending),
+                _ = tokio::time::sleep(Duration::from_secs(timeout_secs)) => Err(Completion::TimedOut),
+                _ = interrupt.interrupted() => Err(Completion::Cancelled),
+            };
+
+            let (completion, state) = match ending {
+                Ok(Ok(Ending::Finished { status, cwd })) => (Completion::Status(status), ("cwd", cwd)),
+                Ok(Ok(Ending::ShellExited)) => {
+                    let status = process.child.wait().await.map_err(|e| ToolError::ExecutionFailed {
+                        reason: format!("shell: {}", e)
+                    })?;
+                    *shell = None;
+                    (Completion::Exited(status), ("shell", "exited; the next command starts a fresh shell in the project directory".to_string()))
+                }
+                Ok(Err(e)) => {
+                    *shell = None;
+                    return Err(ToolError::ExecutionFailed {
+                        reason: format!("shell: {}", e)
+                    }.into());
+                }
+                Err(completion) => {
+                    *shell = None;
+                    (completion, ("shell", "killed; the next command starts a fresh shell in the project directory".to_string()))
+                }
+            };
+
+            let output = ProcessOutput {
+                completion,
+                stdout,
+                stderr,
+                duration: started.elapsed(),
+            };
+            let title = match &output.completion {
+                Completion::TimedOut => format!("deputy stopped {} after {}s", &command, timeout_secs),
+                Completion::Cancelled => format!("deputy stopped {}", &command),
+                _ if output.success() => format!("deputy is running {}", &command),
+                completion => format!("deputy ran {} (exit code {})", &command, completion),
+            };
+            io.show_snippet(&title, &process::preview(&output));
+
+            Ok(output.report_with(&[(state.0, &state.1)]).into())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::io::HeadlessIO;
+
+    async fn run(tool: &ShellSessionTool, command: &str) -> String {
+        let mut io: Box<dyn IO> = Box::new(HeadlessIO::new());
+        tool.call(json!({ "command": command }), &mut io).await.unwrap().text
+    }
+
+    #[tokio::test]
+    async fn keeps_the_working_directory_and_environment() {
+        let tool = ShellSessionTool::new(None);
+        run(&tool, "cd /tmp && export DEPUTY_TEST_VALUE=kept").await;
+        let report = run(&tool, "echo $DEPUTY_TEST_VALUE").await;
+        assert!(report.contains("cwd: /tmp\n"), "{}", report);
+        assert!(report.contains("stdout:\nkept\n"), "{}", report);
+    }
+
+    #[tokio::test]
+    async fn forgets_functions_and_aliases_between_commands() {
+        let tool = ShellSessionTool::new(None);
+        run(&tool, "echo() { builtin echo hijacked; }; shopt -s expand_aliases; alias cat='echo aliased'").await;
+        let report = run(&tool, "echo safe; cat /dev/null").await;
+        assert!(report.contains("exit_code: 0\n"), "{}", report);
+        assert!(report.contains("stdout:\nsafe\n\nstderr:\n"), "{}", report);
+
+        // definitions still work within the command that makes them
+        let report = run(&tool, "greet() { echo hello; }; greet").await;
+        assert!(report.contains("stdout:\nhello\n"), "{}", report);
+    }
+
+    #[tokio::test]
+    async fn forgets_traps_between_commands() {
+        let tool = ShellSessionTool::new(None);
+        let report = run(&tool, "trap 'echo trapped' DEBUG; trap 'echo exiting' EXIT; echo set; false").await;
+        assert!(report.starts_with("exit_code: 1\n"), "{}", report);
+        assert!(report.contains("trapped\nset\n"), "{}", report);
+
+        let report = run(&tool, "echo safe").await;
+        assert!(report.contains("stdout:\nsafe\n\nstderr:\n"), "{}", report);
+        let report = run(&tool, "exit 0").await;
+        assert!(!report.contains("exiting"), "{}", report);
+    }
+
+    #[tokio::test]
+    async fn reports_failures_and_recovers_from_an_exited_shell() {
+        let tool = ShellSessionTool::new(None);
+        let report = run(&tool, "echo out; echo err >&2; false").await;
+        assert!(report.starts_with("exit_code: 1\n"), "{}", report);
+        assert!(report.contains("stdout:\nout\n\nstderr:\nerr\n"), "{}", report);
+
+        let report = run(&tool, "exit 7").await;
+        assert!(report.starts_with("exit_code: 7\n"), "{}", report);
+        assert!(report.contains("shell: exited"), "{}", report);
+        let report = run(&tool, "echo again").await;
+        assert!(report.contains("stdout:\nagain\n"), "{}", report);
+    }
+
+    #[test]
+    fn asks_for_each_program_or_for_a_reset() {
+        let tool = ShellSessionTool::new(None);
+        assert_eq!(tool.permission_ids(json!({ "command": "cd src && cargo test" })).unwrap(), ["cd", "cargo test"]);
+        assert_eq!(tool.permission_ids(json!({ "reset": true })).unwrap(), ["reset"]);
+    }
+}
@@ -1,11 +1,23 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use serde::Deserialize;
 use similar::{ChangeTag, TextDiff};
 
-use crate::{core::Tool, error::{ToolError, Result}, io::IO};
+use super::{FileTracker, PathGuard};
+use crate::{core::{CheckpointLog, Tool, ToolOutput}, error::{ToolError, Result}, io::IO};
 
-pub struct WriteFileTool;
+pub struct WriteFileTool {
+    tracker: Arc<FileTracker>,
+    checkpoints: Arc<CheckpointLog>,
+    guard: PathGuard,
+}
+
+impl WriteFileTool {
+    pub fn new(tracker: Arc<FileTracker>, checkpoints: Arc<CheckpointLog>, guard: PathGuard) -> Self {
+        Self { tracker, checkpoints, guard }
+    }
+}
 
 #[derive(Deserialize, Debug)]
 struct Range {
@@ -13,18 +25,168 @@ struct Range {
     end: usize,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+struct Edit {
+    old_string: String,
+    new_string: String,
+    #[serde(default)]
+    replace_all: bool,
+}
+
 #[derive(Deserialize, Debug)]
 struct Input {
     path: String,
-    content: String,
+    content: Option<String>,
     range: Option<Range>,
+    old_string: Option<String>,
+    new_string: Option<String>,
+    #[serde(default)]
+    replace_all: bool,
+    #[serde(default)]
+    edits: Vec<Edit>,
+}
+
+impl Input {
+    /// The search-and-replace edits of this call: the top-level `old_string`/`new_string` pair,
+    /// if any, followed by `edits`.
+    fn edits(&self) -> Result<Vec<Edit>> {
+        let mut edits = Vec::new();
+        match (&self.old_string, &self.new_string) {
+            (Some(old_string), Some(new_string)) => edits.push(Edit {
+                old_string: old_string.clone(),
+                new_string: new_string.clone(),
+                replace_all: self.replace_all,
+            }),
+            (None, None) => {}
+            _ => {
+                return Err(ToolError::InvalidArguments {
+                    reason: "write_file: old_string and new_string must be given together".to_string()
+                }.into());
+            }
+        }
+        edits.extend(self.edits.iter().cloned());
+        Ok(edits)
+    }
+
+    /// Computes the file's new content from its current content, or from nothing if the file does
+    /// not exist yet. An existing file keeps its line endings and whether it ends with a newline.
+    fn apply(&self, current_file: Option<&str>) -> Result<String> {
+        match current_file.and_then(LineStyle::detect) {
+            Some(style) => {
+                let updated = self.apply_lf(&style.to_lf(current_file.unwrap_or_default()), |text| style.to_lf(text))?;
+                Ok(style.restore(&updated))
+            }
+            None => self.apply_lf(current_file.unwrap_or_default(), str::to_string),
+        }
+    }
+
+    /// Applies the change to `current_file`, whose lines end in `\n`; `to_lf` converts the
+    /// model's strings to the same line endings.
+    fn apply_lf(&self, current_file: &str, to_lf: impl Fn(&str) -> String) -> Result<String> {
+        let edits = self.edits()?;
+        match (&self.content, edits.is_empty()) {
+            (Some(content), true) => match &self.range {
+                Some(range) => replace_range(current_file, range, &to_lf(content)),
+                None => Ok(to_lf(content)),
+            },
+            (None, false) => {
+                if self.range.is_some() {
+                    return Err(ToolError::InvalidArguments {
+                        reason: "write_file: range cannot be combined with old_string/new_string edits".to_string()
+                    }.into());
+                }
+                // every edit applies to the result of the previous one; if any fails, nothing is written
+                edits.iter().enumerate().try_fold(current_file.to_string(), |text, (index, edit)| {
+                    let edit = Edit {
+                        old_string: to_lf(&edit.old_string),
+                        new_string: to_lf(&edit.new_string),
+                        replace_all: edit.replace_all,
+                    };
+                    apply_edit(&text, &edit).map_err(|reason| ToolError::InvalidArguments {
+                        reason: if edits.len() > 1 {
+                            format!("write_file: edit {} of {}: {}; no edits were applied", index + 1, edits.len(), reason)
+                        } else {
+                            format!("write_file: {}", reason)
+                        }
+                    }.into())
+                })
+            }
+            (Some(_), false) => Err(ToolError::InvalidArguments {
+                reason: "write_file: give either content or old_string/new_string edits, not both".to_string()
+            }.into()),
+            (None, true) => Err(ToolError::InvalidArguments {
+                reason: "write_file: either content or old_string/new_string is required".to_string()
+            }.into()),
+        }
+    }
+}
+
+/// How an existing file ends its lines, which writes preserve.
+#[derive(Debug, Clone, Copy)]
+struct LineStyle {
+    crlf: bool,
+    final_newline: bool,
 }
 
-fn get_path(input: &Input) -> Result<PathBuf> {
-    let cwd = std::env::current_dir().map_err(|e| ToolError::ExecutionFailed {
-        reason: format!("write_file: Failed to get current working directory: {}", e)
-    })?;
-    Ok(cwd.join(&input.path))
+impl LineStyle {
+    /// Returns `None` for an empty file, which has no style to preserve.
+    fn detect(text: &str) -> Option<Self> {
+        if text.is_empty() {
+            return None;
+        }
+        let newlines = text.matches('\n').count();
+        Some(Self {
+            // files that mix line endings are left as they are
+            crlf: newlines > 0 && text.matches("\r\n").count() == newlines,
+            final_newline: text.ends_with('\n'),
+        })
+    }
+
+    fn to_lf(self, text: &str) -> String {
+        if self.crlf {
+            text.replace("\r\n", "\n")
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Converts text with `\n` line endings back to this style. For a file without a final
+    /// newline, only the one the edit added is removed, so that blank lines at its end are kept.
+    fn restore(self, text: &str) -> String {
+        let mut text = text.to_string();
+        if self.final_newline && !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        } else if !self.final_newline && text.ends_with('\n') {
+            text.pop();
+        }
+        if self.crlf {
+            text = text.replace('\n', "\r\n");
+        }
+        text
+    }
+}
+
+/// Replaces the unique occurrence of `old_string`, or every occurrence with `replace_all`.
+fn apply_edit(text: &str, edit: &Edit) -> std::result::Result<String, String> {
+    if edit.old_string.is_empty() {
+        return Err("old_string must not be empty".to_string());
+    }
+    if edit.old_string == edit.new_string {
+        return Err("old_string and new_string are identical".to_string());
+    }
+    match text.matches(&edit.old_string).count() {
+        0 => Err("old_string was not found in the file; read the file again and copy the text exactly, including whitespace and indentation".to_string()),
+        1 => Ok(text.replacen(&edit.old_string, &edit.new_string, 1)),
+        _ if edit.replace_all => Ok(text.replace(&edit.old_string, &edit.new_string)),
+        count => Err(format!(
+            "old_string occurs {} times in the file; include more surrounding lines to make it unique, or set replace_all to replace every occurrence",
+            count
+        )),
+    }
+}
+
+fn get_path(input: &Input, guard: &PathGuard) -> Result<PathBuf> {
+    guard.resolve("write_file", &input.path)
 }
 
 fn diff(old_content: &str, new_content: &str) -> String {
@@ -107,13 +269,45 @@ fn diff_summary(old_content: &str, new_content: &str, max_lines: usize) -> Strin
     curtailed
 }
 
-fn replace_range(full_text: &str, range: &Range, new_content: &str) -> String {
-    let mut lines = full_text.lines().collect::<Vec<_>>();
-    lines.splice(
-        range.start - 1..range.end,
-        new_content.lines().collect::<Vec<_>>(),
-    );
-    lines.join("\n")
+/// Replaces lines `range.start..=range.end` with `new_content`. Every other line keeps its own
+/// line ending, so files that mix `\r\n` and `\n` are only changed within the range; the new lines
+/// end like the lines they replace.
+fn replace_range(full_text: &str, range: &Range, new_content: &str) -> Result<String> {
+    let mut lines = full_text.split_inclusive('\n').collect::<Vec<_>>();
+    if range.start == 0 || range.start > range.end || range.end > lines.len() {
+        return Err(ToolError::InvalidArguments {
+            reason: format!(
+                "write_file: invalid range {}-{}; lines are numbered from 1, start must not be after end, and the file has {} lines",
+                range.start,
+                range.end,
+                lines.len()
+            )
+        }.into());
+    }
+    let ending = match line_ending(lines[range.start - 1]) {
+        "" => "\n",
+        ending => ending,
+    };
+    let last_ending = line_ending(lines[range.end - 1]);
+    let new_lines = new_content.lines().collect::<Vec<_>>();
+    let replacement = new_lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| format!("{}{}", line, if index + 1 == new_lines.len() { last_ending } else { ending }))
+        .collect::<Vec<_>>();
+    lines.splice(range.start - 1..range.end, replacement.iter().map(String::as_str));
+    Ok(lines.concat())
+}
+
+/// The `\r\n` or `\n` that ends `line`, or nothing for a last line without one.
+fn line_ending(line: &str) -> &str {
+    if line.ends_with("\r\n") {
+        "\r\n"
+    } else if line.ends_with('\n') {
+        "\n"
+    } else {
+        ""
+    }
 }
 
 impl Tool for WriteFileTool {
@@ -122,7 +316,7 @@ impl Tool for WriteFileTool {
     }
 
     fn description(&self) -> String {
-        "Writes or edits a file.".to_owned()
+        "Writes or edits a file. Prefer editing with old_string/new_string: old_string must match the file's current text exactly (including whitespace) and be unique unless replace_all is set. Several edits to the same file can be made in one call with `edits`; they are applied in order and either all succeed or none is applied. To create a file or rewrite it completely, give `content` instead; `content` with a `range` replaces those lines.".to_owned()
     }
 
     fn input_schema(&self) -> serde_json::Value {
@@ -135,7 +329,32 @@ impl Tool for WriteFileTool {
                 },
                 "content": {
                     "type": "string",
-                    "description": "Content to be written to the file."
+                    "description": "Content to be written to the file, or to replace the lines in `range` with."
+                },
+                "old_string": {
+                    "type": "string",
+                    "description": "Exact text to replace. Must occur exactly once in the file unless replace_all is set."
+                },
+                "new_string": {
+                    "type": "string",
+                    "description": "Text to replace old_string with."
+                },
+                "replace_all": {
+                    "type": "boolean",
+                    "description": "Replace every occurrence of old_string instead of requiring it to be unique."
+                },
+                "edits": {
+                    "type": "array",
+                    "description": "Several search-and-replace edits to apply to the file in order, atomically.",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "old_string": { "type": "string" },
+                            "new_string": { "type": "string" },
+                            "replace_all": { "type": "boolean" }
+                        },
+                        "required": ["old_string", "new_string"]
+                    }
                 },
                 "range": {
                     "type": "object",
@@ -143,7 +362,7 @@ impl Tool for WriteFileTool {
                     "properties": {
                         "start": {
                             "type": "integer",
-                            "description": "The first line of the range; inclusive."
+                            "description": "The first line of the range, counting from 1; inclusive."
                         },
                         "end": {
                             "type": "integer",
@@ -153,7 +372,7 @@ impl Tool for WriteFileTool {
                     "required": ["start", "end"]
                 }
             },
-            "required": ["path", "content"]
+            "required": ["path"]
         })
     }
 
@@ -161,7 +380,7 @@ impl Tool for WriteFileTool {
         &'a self,
         args: serde_json::Value,
         io: &'a mut Box<dyn IO>,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String>> + Send + 'a>>
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<ToolOutput>> + Send + 'a>>
     {
         Box::pin(async move {
             let input: Input = serde_json::from_value(args)
@@ -169,14 +388,26 @@ impl Tool for WriteFileTool {
                     reason: format!("write_file: {}", e)
                 })?;
 
-            let path = get_path(&input)?;
-            let current_file = std::fs::read_to_string(&path).unwrap_or_default();
-
-            let new_content = if let Some(range) = input.range {
-                replace_range(&current_file, &range, &input.content)
+            let path = get_path(&input, &self.guard)?;
+            if !input.edits()?.is_empty() && !path.is_file() {
+                return Err(ToolError::InvalidArguments {
+                    reason: format!("write_file: {} does not exist; use content to create it", input.path)
+                }.into());
+            }
+            // search-and-replace edits only apply to text that is still there, but overwrites and
+            // line ranges rely on the model's view of the file being up to date
+            if input.content.is_some() {
+                self.tracker.check_unchanged(&path)?;
+            }
+            let original = if path.exists() {
+                Some(std::fs::read_to_string(&path).map_err(|e| ToolError::ExecutionFailed {
+                    reason: format!("write_file: Failed to read file: {}", e)
+                })?)
             } else {
-                input.content
+                None
             };
+            let new_content = input.apply(original.as_deref())?;
+            let current_file = original.clone().unwrap_or_default();
 
             let short_diff = diff_summary(&current_file, &new_content, 15);
 
@@ -186,27 +417,169 @@ impl Tool for WriteFileTool {
                 .map_err(|e| ToolError::ExecutionFailed {
                     reason: format!("write_file: Failed to write file: {}", e)
                 })?;
-            Ok("File written successfully".to_owned())
+            self.tracker.record(&path, new_content.as_bytes());
+            self.checkpoints.record(&path, original, new_content);
+            Ok(match input.edits()?.len() {
+                0 => "File written successfully".to_owned(),
+                1 => "Applied 1 edit successfully".to_owned(),
+                count => format!("Applied {} edits successfully", count),
+            }.into())
         })
     }
 
     fn ask_permission(&self, args: serde_json::Value, io: &mut Box<dyn IO>) {
-        let input: Input = serde_json::from_value(args).unwrap_or(Input { path: "<invalid>".to_string(), content: String::new(), range: None });
-        let path = get_path(&input).unwrap_or_else(|_| PathBuf::from("<invalid>"));
-        let current_file = std::fs::read_to_string(&path).unwrap_or_default();
-        let new_file = if let Some(range) = input.range {
-            replace_range(&current_file, &range, &input.content)
-        } else {
-            input.content
+        let input: Input = serde_json::from_value(args).unwrap_or(Input {
+            path: "<invalid>".to_string(),
+            content: None,
+            range: None,
+            old_string: None,
+            new_string: None,
+            replace_all: false,
+            edits: Vec::new(),
+        });
+        let path = get_path(&input, &self.guard).unwrap_or_else(|_| PathBuf::from("<invalid>"));
+        let current_file = std::fs::read_to_string(&path).ok();
+        let diff = match input.apply(current_file.as_deref()) {
+            Ok(new_file) => diff(current_file.as_deref().unwrap_or_default(), &new_file),
+            Err(e) => format!("The edit cannot be applied: {}", e),
         };
-        let diff = diff(&current_file, &new_file);
         io.show_message(
             &format!("deputy wants to edit the file at {}", input.path),
             &diff,
         );
     }
 
-    fn permission_id(&self, _args: serde_json::Value) -> Result<String> {
-        Ok(String::from("write_file"))
+    fn permission_ids(&self, args: serde_json::Value) -> Result<Vec<String>> {
+        let input: Input = serde_json::from_value(args)
+            .map_err(|e| ToolError::InvalidArguments {
+                reason: format!("write_file: {}", e)
+            })?;
+        let mut ids = vec![String::from("write_file")];
+        ids.extend(self.guard.permission_ids("write_file", [input.path.as_str()])?);
+        Ok(ids)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(args: serde_json::Value) -> Input {
+        serde_json::from_value(args).unwrap()
+    }
+
+    fn edit(old_string: &str, new_string: &str) -> Edit {
+        Edit { old_string: old_string.to_string(), new_string: new_string.to_string(), replace_all: false }
+    }
+
+    fn error(result: Result<String>) -> String {
+        result.unwrap_err().to_string()
+    }
+
+    #[test]
+    fn replaces_a_unique_occurrence() {
+        assert_eq!(apply_edit("let a = 1;\nlet b = 2;\n", &edit("b = 2", "b = 3")).unwrap(), "let a = 1;\nlet b = 3;\n");
+    }
+
+    #[test]
+    fn refuses_missing_ambiguous_and_pointless_edits() {
+        assert!(apply_edit("abc", &edit("x", "y")).unwrap_err().contains("not found"));
+        assert!(apply_edit("a a", &edit("a", "b")).unwrap_err().contains("occurs 2 times"));
+        assert!(apply_edit("abc", &edit("", "y")).unwrap_err().contains("must not be empty"));
+        assert!(apply_edit("abc", &edit("b", "b")).unwrap_err().contains("identical"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn replace_all_replaces_every_occurrence() {
+        let edit = Edit { replace_all: true, ..edit("a", "b") };
+        assert_eq!(apply_edit("a a a", &edit).unwrap(), "b b b");
+    }
+
+    #[test]
+    fn applies_edits_in_order() {
+        let input = input(serde_json::json!({
+            "path": "f",
+            "old_string": "one",
+            "new_string": "two",
+            "edits": [{ "old_string": "two", "new_string": "three" }]
+        }));
+        assert_eq!(input.apply(Some("one\n")).unwrap(), "three\n");
+    }
+
+    #[test]
+    fn fails_all_edits_if_one_fails() {
+        let input = input(serde_json::json!({
+            "path": "f",
+            "edits": [
+                { "old_string": "one", "new_string": "two" },
+                { "old_string": "missing", "new_string": "x" }
+            ]
+        }));
+        let reason = error(input.apply(Some("one\n")));
+        assert!(reason.contains("edit 2 of 2"), "{}", reason);
+        assert!(reason.contains("no edits were applied"), "{}", reason);
+    }
+
+    #[test]
+    fn requires_old_and_new_string_together() {
+        let input = input(serde_json::json!({ "path": "f", "old_string": "one" }));
+        assert!(error(input.apply(Some("one\n"))).contains("must be given together"));
+    }
+
+    #[test]
+    fn refuses_content_combined_with_edits() {
+        let input = input(serde_json::json!({ "path": "f", "content": "x", "old_string": "one", "new_string": "two" }));
+        assert!(error(input.apply(Some("one\n"))).contains("not both"));
+    }
+
+    #[test]
+    fn edits_crlf_files_with_lf_strings() {
+        let input = input(serde_json::json!({ "path": "f", "old_string": "a\nb", "new_string": "a\nx\nb" }));
+        assert_eq!(input.apply(Some("a\r\nb\r\n")).unwrap(), "a\r\nx\r\nb\r\n");
+    }
+
+    fn range(start: usize, end: usize, content: &str) -> Input {
+        input(serde_json::json!({ "path": "f", "content": content, "range": { "start": start, "end": end } }))
+    }
+
+    #[test]
+    fn replaces_a_range_of_lines() {
+        assert_eq!(range(2, 3, "x\ny\nz").apply(Some("a\nb\nc\nd\n")).unwrap(), "a\nx\ny\nz\nd\n");
+        assert_eq!(range(2, 2, "").apply(Some("a\nb\nc\n")).unwrap(), "a\nc\n");
+        assert_eq!(range(2, 2, "x\n").apply(Some("a\nb")).unwrap(), "a\nx");
+        assert_eq!(range(2, 2, "").apply(Some("a\nb")).unwrap(), "a");
+    }
+
+    #[test]
+    fn keeps_the_line_endings_around_a_range() {
+        assert_eq!(range(2, 2, "x\ny").apply(Some("a\r\nb\r\nc\r\n")).unwrap(), "a\r\nx\r\ny\r\nc\r\n");
+        // a file that mixes line endings is only changed within the range
+        assert_eq!(range(2, 2, "x\ny").apply(Some("a\r\nb\nc\r\n")).unwrap(), "a\r\nx\ny\nc\r\n");
+        assert_eq!(range(3, 3, "x").apply(Some("a\nb\r\nc\r\n")).unwrap(), "a\nb\r\nx\r\n");
+    }
+
+    #[test]
+    fn refuses_invalid_ranges() {
+        for (start, end) in [(0, 1), (2, 1), (1, 4)] {
+            assert!(error(range(start, end, "x").apply(Some("a\nb\nc\n"))).contains("invalid range"));
+        }
+    }
+
+    #[test]
+    fn detects_the_line_style_of_a_file() {
+        let style = LineStyle::detect("a\r\nb\r\n").unwrap();
+        assert!(style.crlf && style.final_newline);
+        let style = LineStyle::detect("a\r\nb\nc").unwrap();
+        assert!(!style.crlf && !style.final_newline);
+        assert!(LineStyle::detect("").is_none());
+        assert_eq!(LineStyle::detect("a\r\n").unwrap().restore("x\ny"), "x\r\ny\r\n");
+    }
+
+    #[test]
+    fn keeps_blank_lines_added_at_the_end_of_a_file_without_a_final_newline() {
+        let style = LineStyle::detect("a\nb").unwrap();
+        assert_eq!(style.restore("a\nb\n"), "a\nb");
+        assert_eq!(style.restore("a\nb\n\n"), "a\nb\n");
+        let style = LineStyle::detect("a\r\nb\r\nc").unwrap();
+        assert_eq!(style.restore("a\nb\nc\n\n"), "a\r\nb\r\nc\r\n");
+    }
+}